@@ -0,0 +1,48 @@
+use std::os::unix::fs::OpenOptionsExt;
+use std::{fs::OpenOptions, io, thread, time::Duration};
+
+use input_linux::{InputId, Key, UInputHandle, VirtualMouse};
+use nix::libc::O_NONBLOCK;
+
+
+// The mouse-movements example using the VirtualMouse preset.
+// Creates a virtual mouse, moves it down and to the right 250 units
+// in increments of 5 units, then clicks and scrolls down
+//
+// This example requires either root (bad practice, too general) or
+// the running user to be a member of the uinput group to actually
+// make the mouse move
+fn main() -> io::Result<()> {
+    let uinput_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(O_NONBLOCK)
+        .open("/dev/uinput")?;
+    let uhandle = UInputHandle::new(uinput_file);
+
+    let input_id = InputId {
+        bustype: input_linux::sys::BUS_USB,
+        vendor: 0x1234,
+        product: 0x5678,
+        version: 0,
+    };
+    let device_name = b"Example device";
+    let mut mouse = VirtualMouse::create(uhandle, &input_id, device_name)?
+        .with_interval(Duration::from_micros(15_000));
+
+    // Events written before userspace has opened the device are lost
+    mouse.handle().wait_ready(Duration::from_secs(5))?;
+
+    // 50 steps of 5 units each, 15ms apart
+    mouse.move_smooth(250, 250, Duration::from_micros(15_000 * 50))?;
+
+    mouse.click(Key::ButtonLeft)?;
+    mouse.scroll(-1, 0)?;
+
+    // Give userspace a chance to read the last events before the device
+    // goes away
+    thread::sleep(Duration::from_secs(1));
+    mouse.handle().dev_destroy()?;
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+//! Explicit `input_event` layouts for the various userspace ABIs.
+//!
+//! The size and layout of `struct input_event` depends on the word size of
+//! the process that reads it, so event recordings captured on one machine may
+//! not match the native [`InputEvent`] of another. These types describe the
+//! wire formats explicitly and convert to and from the native representation.
+
+use std::convert::TryFrom;
+use std::mem::size_of;
+use crate::{InputEvent, EventKind, EventTime, RangeError};
+
+/// The byte order of a serialized event.
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum ByteOrder {
+    /// The byte order of the current machine.
+    #[default]
+    Native,
+    /// Little endian.
+    Little,
+    /// Big endian.
+    Big,
+}
+
+macro_rules! byte_order_impl {
+    ($($ty:ident: $read:ident, $write:ident;)*) => {
+        impl ByteOrder {
+            $(
+                fn $read(&self, bytes: &[u8]) -> $ty {
+                    let mut buf = [0u8; size_of::<$ty>()];
+                    buf.copy_from_slice(&bytes[..size_of::<$ty>()]);
+                    match self {
+                        ByteOrder::Native => $ty::from_ne_bytes(buf),
+                        ByteOrder::Little => $ty::from_le_bytes(buf),
+                        ByteOrder::Big => $ty::from_be_bytes(buf),
+                    }
+                }
+
+                fn $write(&self, bytes: &mut [u8], value: $ty) {
+                    let buf = match self {
+                        ByteOrder::Native => value.to_ne_bytes(),
+                        ByteOrder::Little => value.to_le_bytes(),
+                        ByteOrder::Big => value.to_be_bytes(),
+                    };
+                    bytes[..size_of::<$ty>()].copy_from_slice(&buf);
+                }
+            )*
+        }
+    };
+}
+
+byte_order_impl! {
+    u16: read_u16, write_u16;
+    u32: read_u32, write_u32;
+    i32: read_i32, write_i32;
+    i64: read_i64, write_i64;
+}
+
+/// An `input_event` as seen by 32-bit userspace.
+///
+/// The timestamp fields hold the raw bits of the kernel's 32-bit seconds and
+/// microseconds; whether the seconds are interpreted as signed or unsigned
+/// depends on the ABI the recording was made with, see [`EventLayout`].
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub struct InputEvent32 {
+    /// The seconds component of the timestamp.
+    pub sec: u32,
+    /// The microseconds component of the timestamp.
+    pub usec: u32,
+    /// The raw event type.
+    pub type_: u16,
+    /// The raw event code.
+    pub code: u16,
+    /// The value of the event.
+    pub value: i32,
+}
+
+/// An `input_event` as seen by 64-bit userspace.
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub struct InputEvent64 {
+    /// The seconds component of the timestamp.
+    pub sec: i64,
+    /// The microseconds component of the timestamp.
+    pub usec: i64,
+    /// The raw event type.
+    pub type_: u16,
+    /// The raw event code.
+    pub code: u16,
+    /// The value of the event.
+    pub value: i32,
+}
+
+impl InputEvent32 {
+    /// The serialized size of the event.
+    pub const SIZE: usize = 16;
+
+    /// Deserializes the event from bytes.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE], order: ByteOrder) -> Self {
+        InputEvent32 {
+            sec: order.read_u32(&bytes[0..]),
+            usec: order.read_u32(&bytes[4..]),
+            type_: order.read_u16(&bytes[8..]),
+            code: order.read_u16(&bytes[10..]),
+            value: order.read_i32(&bytes[12..]),
+        }
+    }
+
+    /// Serializes the event into bytes.
+    pub fn to_bytes(&self, order: ByteOrder) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        order.write_u32(&mut bytes[0..], self.sec);
+        order.write_u32(&mut bytes[4..], self.usec);
+        order.write_u16(&mut bytes[8..], self.type_);
+        order.write_u16(&mut bytes[10..], self.code);
+        order.write_i32(&mut bytes[12..], self.value);
+        bytes
+    }
+
+    /// Converts the event into its native representation, interpreting the
+    /// seconds as unsigned as done by time64 userspace.
+    ///
+    /// Timestamps past 2038 are only representable this way.
+    pub fn to_event_time64(&self) -> Result<InputEvent, RangeError> {
+        to_event(self.sec as i64, self.usec as i64, self.type_, self.code, self.value)
+    }
+
+    /// Converts a native event, storing the seconds as unsigned as done by
+    /// time64 userspace.
+    ///
+    /// Fails if the timestamp is before 1970 or after 2106.
+    pub fn from_event_time64(event: &InputEvent) -> Result<Self, RangeError> {
+        from_event(u32::try_from(event.time.seconds()).map_err(|_| RangeError)?, event)
+    }
+}
+
+impl InputEvent64 {
+    /// The serialized size of the event.
+    pub const SIZE: usize = 24;
+
+    /// Deserializes the event from bytes.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE], order: ByteOrder) -> Self {
+        InputEvent64 {
+            sec: order.read_i64(&bytes[0..]),
+            usec: order.read_i64(&bytes[8..]),
+            type_: order.read_u16(&bytes[16..]),
+            code: order.read_u16(&bytes[18..]),
+            value: order.read_i32(&bytes[20..]),
+        }
+    }
+
+    /// Serializes the event into bytes.
+    pub fn to_bytes(&self, order: ByteOrder) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        order.write_i64(&mut bytes[0..], self.sec);
+        order.write_i64(&mut bytes[8..], self.usec);
+        order.write_u16(&mut bytes[16..], self.type_);
+        order.write_u16(&mut bytes[18..], self.code);
+        order.write_i32(&mut bytes[20..], self.value);
+        bytes
+    }
+}
+
+fn to_event(sec: i64, usec: i64, type_: u16, code: u16, value: i32) -> Result<InputEvent, RangeError> {
+    EventKind::from_type(type_).map(|kind| InputEvent {
+        time: EventTime::new(sec, usec),
+        kind,
+        code,
+        value,
+    })
+}
+
+impl TryFrom<InputEvent32> for InputEvent {
+    type Error = RangeError;
+
+    /// Interprets the seconds as signed, as done by legacy 32-bit userspace.
+    fn try_from(event: InputEvent32) -> Result<Self, Self::Error> {
+        to_event(event.sec as i32 as i64, event.usec as i32 as i64, event.type_, event.code, event.value)
+    }
+}
+
+impl TryFrom<InputEvent64> for InputEvent {
+    type Error = RangeError;
+
+    fn try_from(event: InputEvent64) -> Result<Self, Self::Error> {
+        to_event(event.sec, event.usec, event.type_, event.code, event.value)
+    }
+}
+
+fn from_event(sec: u32, event: &InputEvent) -> Result<InputEvent32, RangeError> {
+    Ok(InputEvent32 {
+        sec,
+        usec: u32::try_from(event.time.microseconds()).map_err(|_| RangeError)?,
+        type_: event.kind as u16,
+        code: event.code,
+        value: event.value,
+    })
+}
+
+impl TryFrom<InputEvent> for InputEvent32 {
+    type Error = RangeError;
+
+    /// Stores the seconds as signed, as done by legacy 32-bit userspace.
+    ///
+    /// Fails if the timestamp does not fit in 32 bits.
+    fn try_from(event: InputEvent) -> Result<Self, Self::Error> {
+        from_event(i32::try_from(event.time.seconds()).map_err(|_| RangeError)? as u32, &event)
+    }
+}
+
+impl From<InputEvent> for InputEvent64 {
+    fn from(event: InputEvent) -> Self {
+        InputEvent64 {
+            sec: event.time.seconds(),
+            usec: event.time.microseconds(),
+            type_: event.kind as u16,
+            code: event.code,
+            value: event.value,
+        }
+    }
+}
+
+/// Describes the userspace ABI an `input_event` stream was produced with.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum EventLayout {
+    /// 64-bit userspace, see [`InputEvent64`].
+    Bits64,
+    /// 32-bit userspace with a legacy 32-bit `time_t`, see [`InputEvent32`].
+    ///
+    /// Seconds are signed and overflow in 2038.
+    Bits32,
+    /// 32-bit userspace built with a 64-bit `time_t`, see [`InputEvent32`].
+    ///
+    /// The kernel still reports 32 bits of seconds, but they are unsigned.
+    Bits32Time64,
+}
+
+impl EventLayout {
+    /// The layout used by the current target.
+    pub const fn native() -> Self {
+        match (size_of::<usize>(), size_of::<nix::libc::time_t>()) {
+            (8, _) => EventLayout::Bits64,
+            (_, 8) => EventLayout::Bits32Time64,
+            _ => EventLayout::Bits32,
+        }
+    }
+
+    /// The serialized size of a single event.
+    pub const fn size(&self) -> usize {
+        match self {
+            EventLayout::Bits64 => InputEvent64::SIZE,
+            EventLayout::Bits32 | EventLayout::Bits32Time64 => InputEvent32::SIZE,
+        }
+    }
+
+    /// Deserializes a single event.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is shorter than [`size`](Self::size).
+    pub fn decode(&self, bytes: &[u8], order: ByteOrder) -> Result<InputEvent, RangeError> {
+        match self {
+            EventLayout::Bits64 => {
+                let bytes = <&[u8; InputEvent64::SIZE]>::try_from(&bytes[..InputEvent64::SIZE]).unwrap();
+                InputEvent::try_from(InputEvent64::from_bytes(bytes, order))
+            },
+            EventLayout::Bits32 | EventLayout::Bits32Time64 => {
+                let bytes = <&[u8; InputEvent32::SIZE]>::try_from(&bytes[..InputEvent32::SIZE]).unwrap();
+                let event = InputEvent32::from_bytes(bytes, order);
+                match self {
+                    EventLayout::Bits32Time64 => event.to_event_time64(),
+                    _ => InputEvent::try_from(event),
+                }
+            },
+        }
+    }
+
+    /// Serializes a single event.
+    ///
+    /// Fails if the timestamp cannot be represented by a 32-bit layout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` is shorter than [`size`](Self::size).
+    pub fn encode(&self, event: &InputEvent, order: ByteOrder, dst: &mut [u8]) -> Result<(), RangeError> {
+        match self {
+            EventLayout::Bits64 =>
+                dst[..InputEvent64::SIZE].copy_from_slice(&InputEvent64::from(*event).to_bytes(order)),
+            EventLayout::Bits32 =>
+                dst[..InputEvent32::SIZE].copy_from_slice(&InputEvent32::try_from(*event)?.to_bytes(order)),
+            EventLayout::Bits32Time64 =>
+                dst[..InputEvent32::SIZE].copy_from_slice(&InputEvent32::from_event_time64(event)?.to_bytes(order)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for EventLayout {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+#[test]
+fn event_layout_native() {
+    let layout = EventLayout::native();
+    assert_eq!(layout.size(), size_of::<InputEvent>());
+
+    let event = InputEvent {
+        time: EventTime::new(1234, 5678),
+        kind: EventKind::Key,
+        code: 30,
+        value: 1,
+    };
+    let mut bytes = [0u8; InputEvent64::SIZE];
+    layout.encode(&event, ByteOrder::Native, &mut bytes).unwrap();
+    assert_eq!(&bytes[..layout.size()], &event.as_bytes()[..]);
+    assert_eq!(layout.decode(&bytes, ByteOrder::Native), Ok(event));
+
+    let time64 = InputEvent32 { sec: 0x8000_0000, ..InputEvent32::try_from(event).unwrap() };
+    assert_eq!(InputEvent::try_from(time64).unwrap().time.seconds(), -0x8000_0000);
+    assert_eq!(time64.to_event_time64().unwrap().time.seconds(), 0x8000_0000);
+}
+
+#[test]
+fn event_layout_fixtures() {
+    let event = |sec| InputEvent {
+        time: EventTime::new(sec, 2),
+        kind: EventKind::Key,
+        code: 30,
+        value: 1,
+    };
+
+    let bits32_le = [
+        0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x1e, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(EventLayout::Bits32.decode(&bits32_le, ByteOrder::Little), Ok(event(1)));
+    let mut bytes = [0u8; InputEvent32::SIZE];
+    EventLayout::Bits32.encode(&event(1), ByteOrder::Little, &mut bytes).unwrap();
+    assert_eq!(bytes, bits32_le);
+
+    // the same bits past 2038 are negative for legacy 32-bit userspace
+    let time64_le = [
+        0x00, 0x00, 0x00, 0x80, 0x02, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x1e, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(EventLayout::Bits32Time64.decode(&time64_le, ByteOrder::Little), Ok(event(0x8000_0000)));
+    assert_eq!(EventLayout::Bits32.decode(&time64_le, ByteOrder::Little), Ok(event(-0x8000_0000)));
+    EventLayout::Bits32Time64.encode(&event(0x8000_0000), ByteOrder::Little, &mut bytes).unwrap();
+    assert_eq!(bytes, time64_le);
+    assert!(EventLayout::Bits32.encode(&event(0x8000_0000), ByteOrder::Little, &mut bytes).is_err());
+    assert!(EventLayout::Bits32Time64.encode(&event(-1), ByteOrder::Little, &mut bytes).is_err());
+    assert_eq!(InputEvent32::try_from(event(0x1_0000_0000)), Err(RangeError));
+
+    let bits32_be = [
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02,
+        0x00, 0x01, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x01,
+    ];
+    assert_eq!(EventLayout::Bits32.decode(&bits32_be, ByteOrder::Big), Ok(event(1)));
+
+    let bits64_be = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x00, 0x01, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x01,
+    ];
+    assert_eq!(EventLayout::Bits64.decode(&bits64_be, ByteOrder::Big), Ok(event(1)));
+    let mut bytes = [0u8; InputEvent64::SIZE];
+    EventLayout::Bits64.encode(&event(1), ByteOrder::Big, &mut bytes).unwrap();
+    assert_eq!(bytes, bits64_be);
+
+    // unknown event types are rejected
+    let mut invalid = bits64_be;
+    invalid[17] = 0x30;
+    assert_eq!(EventLayout::Bits64.decode(&invalid, ByteOrder::Big), Err(RangeError));
+}
@@ -0,0 +1,169 @@
+//! A description of everything an input device is capable of reporting.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+use crate::{
+    AbsoluteAxis, AbsoluteInfoSetup, EventKind, InputId, InputProperty, Key,
+    LedKind, MiscKind, RelativeAxis, SoundKind, SwitchKind, ForceFeedbackKind,
+    EvdevHandle, UInputHandle,
+};
+use crate::uinput::UInputDevice;
+use crate::bitmask::Bitmask;
+
+/// The identity and capabilities of an input device.
+///
+/// This can be queried from an existing device via
+/// [`EvdevHandle::capabilities`] and used to create a matching virtual device
+/// via [`UInputHandle::create_from_capabilities`].
+#[derive(Clone, Debug, Default)]
+pub struct DeviceCapabilities {
+    /// The identity of the device.
+    pub id: InputId,
+    /// The name of the device.
+    pub name: Vec<u8>,
+    /// The maximum number of force feedback effects the device supports.
+    pub ff_effects_max: u32,
+    /// Device properties and quirks.
+    pub properties: Bitmask<InputProperty>,
+    /// The supported event types.
+    pub events: Bitmask<EventKind>,
+    /// The supported keys and buttons.
+    pub keys: Bitmask<Key>,
+    /// The supported relative axes.
+    pub relative: Bitmask<RelativeAxis>,
+    /// The supported absolute axes along with their constraints.
+    pub absolute: Vec<AbsoluteInfoSetup>,
+    /// The supported miscellaneous events.
+    pub misc: Bitmask<MiscKind>,
+    /// The supported switches.
+    pub switches: Bitmask<SwitchKind>,
+    /// The supported LEDs.
+    pub leds: Bitmask<LedKind>,
+    /// The supported sounds.
+    pub sounds: Bitmask<SoundKind>,
+    /// The supported force feedback effects.
+    pub force_feedback: Bitmask<ForceFeedbackKind>,
+}
+
+impl DeviceCapabilities {
+    /// Creates an empty set of capabilities for a device with the given name.
+    pub fn new(id: InputId, name: &[u8]) -> Self {
+        DeviceCapabilities {
+            id,
+            name: name.into(),
+            .. Default::default()
+        }
+    }
+
+    /// Adds a key or button, along with the [`EventKind::Key`] event type.
+    pub fn insert_key(&mut self, key: Key) {
+        self.events.insert(EventKind::Key);
+        self.keys.insert(key);
+    }
+
+    /// Adds a relative axis, along with the [`EventKind::Relative`] event type.
+    pub fn insert_relative(&mut self, axis: RelativeAxis) {
+        self.events.insert(EventKind::Relative);
+        self.relative.insert(axis);
+    }
+
+    /// Adds or replaces an absolute axis, along with the
+    /// [`EventKind::Absolute`] event type.
+    pub fn insert_absolute(&mut self, setup: AbsoluteInfoSetup) {
+        self.events.insert(EventKind::Absolute);
+        self.absolute.retain(|abs| abs.axis != setup.axis);
+        self.absolute.push(setup);
+    }
+
+    /// Looks up the constraints of an absolute axis.
+    pub fn absolute_setup(&self, axis: AbsoluteAxis) -> Option<&AbsoluteInfoSetup> {
+        self.absolute.iter().find(|abs| abs.axis == axis)
+    }
+}
+
+impl<F: AsRawFd> EvdevHandle<F> {
+    /// Queries the identity and all capabilities of the device.
+    pub fn capabilities(&self) -> io::Result<DeviceCapabilities> {
+        let events = self.event_bits()?;
+        let absolute_bits = if events.get(EventKind::Absolute) {
+            self.absolute_bits()?
+        } else {
+            Default::default()
+        };
+        let absolute = absolute_bits.iter()
+            .map(|axis| self.absolute_info(axis).map(|info| AbsoluteInfoSetup { axis, info }))
+            .collect::<io::Result<_>>()?;
+
+        let mut name = self.device_name()?;
+        if let Some(nul) = name.iter().position(|&c| c == 0) {
+            name.truncate(nul);
+        }
+
+        Ok(DeviceCapabilities {
+            id: self.device_id()?,
+            name,
+            ff_effects_max: if events.get(EventKind::ForceFeedback) {
+                self.effects_count()? as u32
+            } else {
+                0
+            },
+            properties: self.device_properties()?,
+            keys: if events.get(EventKind::Key) { self.key_bits()? } else { Default::default() },
+            relative: if events.get(EventKind::Relative) { self.relative_bits()? } else { Default::default() },
+            absolute,
+            misc: if events.get(EventKind::Misc) { self.misc_bits()? } else { Default::default() },
+            switches: if events.get(EventKind::Switch) { self.switch_bits()? } else { Default::default() },
+            leds: if events.get(EventKind::Led) { self.led_bits()? } else { Default::default() },
+            sounds: if events.get(EventKind::Sound) { self.sound_bits()? } else { Default::default() },
+            force_feedback: if events.get(EventKind::ForceFeedback) { self.force_feedback_bits()? } else { Default::default() },
+            events,
+        })
+    }
+}
+
+impl<F: AsRawFd> UInputHandle<F> {
+    /// Enables all of the described capabilities and creates the device.
+    pub fn create_from_capabilities(&self, caps: &DeviceCapabilities) -> io::Result<()> {
+        for kind in &caps.events {
+            if kind != EventKind::Synchronize {
+                self.set_evbit(kind)?;
+            }
+        }
+        for prop in &caps.properties {
+            self.set_propbit(prop)?;
+        }
+        for key in &caps.keys {
+            self.set_keybit(key)?;
+        }
+        for axis in &caps.relative {
+            self.set_relbit(axis)?;
+        }
+        for abs in &caps.absolute {
+            self.set_absbit(abs.axis)?;
+        }
+        for misc in &caps.misc {
+            self.set_mscbit(misc)?;
+        }
+        for switch in &caps.switches {
+            self.set_swbit(switch)?;
+        }
+        for led in &caps.leds {
+            self.set_ledbit(led)?;
+        }
+        for sound in &caps.sounds {
+            self.set_sndbit(sound)?;
+        }
+        for ff in &caps.force_feedback {
+            self.set_ffbit(ff)?;
+        }
+
+        self.create(&caps.id, &caps.name, caps.ff_effects_max, &caps.absolute)
+    }
+
+    /// Creates a device from the described capabilities that is destroyed
+    /// when dropped.
+    pub fn create_device_from_capabilities(self, caps: &DeviceCapabilities) -> io::Result<UInputDevice<F>> {
+        self.create_from_capabilities(caps)?;
+        Ok(UInputDevice::new(self))
+    }
+}
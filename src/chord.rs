@@ -0,0 +1,291 @@
+//! Recognizing key combinations and sequences.
+//!
+//! A [`ChordDetector`] watches a stream of events for user-defined
+//! [`Combo`]s and reports an action whenever one is completed. Combos can
+//! either be chords of keys pressed together, or sequences of keys tapped one
+//! after another such as those started by a leader key.
+//!
+//! Like [`TapHoldProcessor`](crate::TapHoldProcessor), time-based decisions
+//! require calling [`timeout`](ChordDetector::timeout) once the
+//! [`deadline`](ChordDetector::deadline) passes.
+
+use std::time::Duration;
+use std::collections::HashSet;
+use crate::{EventTime, InputEvent, Key, KeyEvent, KeyState, GenericEvent};
+
+/// A combination of keys that triggers an action.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Combo {
+    /// Keys that are all held down together, in any order.
+    ///
+    /// The keys must all be pressed within the chord window.
+    Chord(Vec<Key>),
+    /// Keys that are pressed in order.
+    ///
+    /// Each key must be pressed within the sequence timeout of the previous
+    /// one.
+    Sequence(Vec<Key>),
+}
+
+/// The result of processing an event with a [`ChordDetector`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ChordOutput<A> {
+    /// An event to be passed along.
+    Event(InputEvent),
+    /// A combo was recognized.
+    Action(A),
+}
+
+/// Recognizes chords and key sequences.
+#[derive(Clone, Debug)]
+pub struct ChordDetector<A> {
+    combos: Vec<(Combo, A)>,
+    chord_window: Duration,
+    sequence_timeout: Duration,
+    swallow: bool,
+    progress: Vec<Key>,
+    first: EventTime,
+    last: EventTime,
+    released: bool,
+    buffer: Vec<InputEvent>,
+    swallowed: HashSet<Key>,
+}
+
+impl<A> Default for ChordDetector<A> {
+    fn default() -> Self {
+        ChordDetector {
+            combos: Default::default(),
+            chord_window: Self::DEFAULT_CHORD_WINDOW,
+            sequence_timeout: Self::DEFAULT_SEQUENCE_TIMEOUT,
+            swallow: false,
+            progress: Default::default(),
+            first: Default::default(),
+            last: Default::default(),
+            released: false,
+            buffer: Default::default(),
+            swallowed: Default::default(),
+        }
+    }
+}
+
+impl<A> ChordDetector<A> {
+    /// The default time within which all keys of a chord must be pressed.
+    pub const DEFAULT_CHORD_WINDOW: Duration = Duration::from_millis(50);
+
+    /// The default time allowed between the keys of a sequence.
+    pub const DEFAULT_SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+}
+
+impl<A: Clone> ChordDetector<A> {
+    /// A detector without any combos.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a combo.
+    ///
+    /// When a combo is completed that is also the beginning of a longer one,
+    /// the shorter combo wins.
+    pub fn with(mut self, combo: Combo, action: A) -> Self {
+        self.insert(combo, action);
+        self
+    }
+
+    /// Adds a combo.
+    pub fn insert(&mut self, combo: Combo, action: A) {
+        self.combos.push((combo, action));
+    }
+
+    /// Sets the time within which all keys of a chord must be pressed.
+    pub fn with_chord_window(self, chord_window: Duration) -> Self {
+        ChordDetector { chord_window, ..self }
+    }
+
+    /// Sets the time allowed between the keys of a sequence.
+    pub fn with_sequence_timeout(self, sequence_timeout: Duration) -> Self {
+        ChordDetector { sequence_timeout, ..self }
+    }
+
+    /// Sets whether the keys that trigger a combo are swallowed.
+    ///
+    /// When enabled, events are held back while a combo is in progress, and
+    /// the keys of a recognized combo are never passed along. Otherwise,
+    /// events are passed along immediately.
+    pub fn with_swallow(self, swallow: bool) -> Self {
+        ChordDetector { swallow, ..self }
+    }
+
+    /// Checks the progress against all combos, returning the first that
+    /// matches exactly along with the deadline of any others that may still
+    /// complete.
+    fn evaluate(&self) -> (Option<usize>, Option<EventTime>) {
+        let chord_deadline = self.first.checked_add(self.chord_window).unwrap_or(self.first);
+        let sequence_deadline = self.last.checked_add(self.sequence_timeout).unwrap_or(self.last);
+        let mut deadline = None;
+        for (i, (combo, _)) in self.combos.iter().enumerate() {
+            let (exact, candidate) = match combo {
+                Combo::Chord(keys) => {
+                    let candidate = !self.released && self.last <= chord_deadline &&
+                        self.progress.iter().all(|key| keys.contains(key));
+                    let exact = candidate && keys.iter().all(|key| self.progress.contains(key));
+                    (exact, candidate.then_some(chord_deadline))
+                },
+                Combo::Sequence(keys) => (
+                    keys[..] == self.progress[..],
+                    keys.starts_with(&self.progress).then_some(sequence_deadline),
+                ),
+            };
+            if exact {
+                return (Some(i), None)
+            }
+            deadline = deadline.max(candidate);
+        }
+        (None, deadline)
+    }
+
+    /// The time at which an incomplete combo is abandoned.
+    pub fn deadline(&self) -> Option<EventTime> {
+        match self.progress.is_empty() {
+            true => None,
+            false => self.evaluate().1,
+        }
+    }
+
+    /// How long to wait for further events before calling
+    /// [`timeout`](Self::timeout).
+    pub fn time_until_deadline(&self, now: EventTime) -> Option<Duration> {
+        self.deadline().map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    fn hold(&mut self, event: &InputEvent, out: &mut Vec<ChordOutput<A>>) {
+        if self.swallow {
+            self.buffer.push(*event);
+        } else {
+            out.push(ChordOutput::Event(*event));
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<ChordOutput<A>>) {
+        self.progress.clear();
+        self.released = false;
+        out.extend(self.buffer.drain(..).map(ChordOutput::Event));
+    }
+
+    fn fire(&mut self, index: usize, out: &mut Vec<ChordOutput<A>>) {
+        let progress = std::mem::take(&mut self.progress);
+        self.released = false;
+        let mut held = HashSet::new();
+        for event in self.buffer.drain(..) {
+            match KeyEvent::from_ref(&event) {
+                Ok(key) if progress.contains(&key.key) => if key.value == KeyState::RELEASED {
+                    held.remove(&key.key);
+                } else {
+                    held.insert(key.key);
+                },
+                _ => out.push(ChordOutput::Event(event)),
+            }
+        }
+        self.swallowed.extend(held);
+        out.push(ChordOutput::Action(self.combos[index].1.clone()));
+    }
+
+    /// Processes a single input event, appending the resulting events and
+    /// actions to `out`.
+    pub fn process(&mut self, event: &InputEvent, out: &mut Vec<ChordOutput<A>>) {
+        let key = match KeyEvent::from_ref(event) {
+            Ok(key) => key,
+            Err(..) => return match self.progress.is_empty() {
+                true => out.push(ChordOutput::Event(*event)),
+                false => self.hold(event, out),
+            },
+        };
+
+        if !self.progress.is_empty() && self.deadline().map(|deadline| key.time > deadline).unwrap_or(true) {
+            self.flush(out);
+        }
+
+        if key.value != KeyState::PRESSED {
+            if self.progress.is_empty() {
+                if !self.swallowed.contains(&key.key) {
+                    out.push(ChordOutput::Event(*event));
+                } else if key.value == KeyState::RELEASED {
+                    self.swallowed.remove(&key.key);
+                }
+                return
+            }
+            if key.value == KeyState::RELEASED && self.progress.contains(&key.key) {
+                self.released = true;
+            }
+            self.hold(event, out);
+            if self.evaluate().1.is_none() {
+                self.flush(out);
+            }
+            return
+        }
+
+        if self.progress.is_empty() {
+            self.first = key.time;
+        }
+        self.progress.push(key.key);
+        self.last = key.time;
+        match self.evaluate() {
+            (Some(index), _) => {
+                self.hold(event, out);
+                self.fire(index, out);
+            },
+            (None, Some(..)) => self.hold(event, out),
+            (None, None) if self.progress.len() > 1 => {
+                // abandon the combo, this key may start a new one
+                self.progress.pop();
+                self.flush(out);
+                self.process(event, out);
+            },
+            (None, None) => {
+                self.hold(event, out);
+                self.flush(out);
+            },
+        }
+    }
+
+    /// Abandons an incomplete combo if its deadline has passed, passing along
+    /// any events that were held back.
+    pub fn timeout(&mut self, now: EventTime, out: &mut Vec<ChordOutput<A>>) {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => self.flush(out),
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn chord_detector() {
+    let key = |ms: i64, key, value| InputEvent::from(KeyEvent::new(EventTime::new(0, ms * 1000), key, value));
+    let mut chords = ChordDetector::new()
+        .with(Combo::Chord(vec![Key::LeftCtrl, Key::LeftAlt, Key::T]), "terminal")
+        .with(Combo::Sequence(vec![Key::ScrollLock, Key::L]), "lock")
+        .with_swallow(true);
+
+    let mut out = Vec::new();
+    chords.process(&key(0, Key::T, KeyState::PRESSED), &mut out);
+    chords.process(&key(10, Key::LeftAlt, KeyState::PRESSED), &mut out);
+    assert!(out.is_empty());
+    chords.process(&key(20, Key::LeftCtrl, KeyState::PRESSED), &mut out);
+    chords.process(&key(30, Key::T, KeyState::RELEASED), &mut out);
+    assert_eq!(out, [ChordOutput::Action("terminal")]);
+
+    out.clear();
+    chords.process(&key(2000, Key::ScrollLock, KeyState::PRESSED), &mut out);
+    chords.process(&key(2010, Key::ScrollLock, KeyState::RELEASED), &mut out);
+    chords.timeout(EventTime::new(3, 0), &mut out);
+    assert_eq!(out, [
+        ChordOutput::Event(key(2000, Key::ScrollLock, KeyState::PRESSED)),
+        ChordOutput::Event(key(2010, Key::ScrollLock, KeyState::RELEASED)),
+    ]);
+
+    out.clear();
+    chords.process(&key(4000, Key::ScrollLock, KeyState::PRESSED), &mut out);
+    chords.process(&key(4010, Key::ScrollLock, KeyState::RELEASED), &mut out);
+    chords.process(&key(4500, Key::L, KeyState::PRESSED), &mut out);
+    chords.process(&key(4510, Key::L, KeyState::RELEASED), &mut out);
+    assert_eq!(out, [ChordOutput::Action("lock")]);
+}
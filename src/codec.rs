@@ -1,48 +1,369 @@
 #![allow(missing_docs)]
 
-use std::{io, mem, ptr};
-use crate::sys::input_event;
+use std::io;
 #[cfg(feature = "bytes")]
 use bytes::{BytesMut, BufMut};
-use crate::InputEvent;
+use crate::{InputEvent, Event, SynchronizeKind, EventKind, RangeError};
+use crate::abi::{EventLayout, ByteOrder};
 
+/// Determines which events are considered invalid by an [`EventCodec`].
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum Validation {
+    /// Only the event type is validated.
+    ///
+    /// Codes that are unknown to this crate, such as vendor-specific keys,
+    /// are passed through unchanged.
+    #[default]
+    Type,
+    /// Both the event type and code must be representable as a typed [`Event`].
+    Strict,
+}
+
+/// Determines how an [`EventCodec`] handles invalid events.
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum InvalidEvents {
+    /// Fail decoding with an [`io::ErrorKind::InvalidData`] error.
+    #[default]
+    Error,
+    /// Silently discard invalid events and continue decoding.
+    Skip,
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct EventCodec {
-    _dummy: (),
+    layout: EventLayout,
+    byte_order: ByteOrder,
+    validation: Validation,
+    invalid: InvalidEvents,
 }
 
 impl EventCodec {
     pub const fn new() -> Self {
         EventCodec {
-            _dummy: (),
+            layout: EventLayout::native(),
+            byte_order: ByteOrder::Native,
+            validation: Validation::Type,
+            invalid: InvalidEvents::Error,
+        }
+    }
+
+    /// Configures the layout of the serialized events.
+    ///
+    /// Defaults to [`EventLayout::native`], so that a recording made on
+    /// another machine can be decoded by specifying its layout here.
+    pub const fn with_layout(self, layout: EventLayout) -> Self {
+        EventCodec {
+            layout,
+            ..self
+        }
+    }
+
+    /// Configures the byte order of the serialized events.
+    pub const fn with_byte_order(self, byte_order: ByteOrder) -> Self {
+        EventCodec {
+            byte_order,
+            ..self
+        }
+    }
+
+    /// Configures which events are considered invalid.
+    pub const fn with_validation(self, validation: Validation) -> Self {
+        EventCodec {
+            validation,
+            ..self
+        }
+    }
+
+    /// Configures how invalid events are handled.
+    pub const fn with_invalid_events(self, invalid: InvalidEvents) -> Self {
+        EventCodec {
+            invalid,
+            ..self
+        }
+    }
+
+    /// Decode whole `SYN_REPORT` frames rather than individual events.
+    pub const fn frames(self) -> FrameCodec {
+        FrameCodec::new(self)
+    }
+
+    /// The layout of the serialized events.
+    pub const fn layout(&self) -> EventLayout {
+        self.layout
+    }
+
+    /// The byte order of the serialized events.
+    pub const fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    /// The size of a single serialized event.
+    pub const fn event_size(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// Deserializes a single event from the start of `src`.
+    ///
+    /// Invalid events always fail with [`io::ErrorKind::InvalidData`]; see
+    /// [`decode_event`](Self::decode_event) to honour
+    /// [`InvalidEvents::Skip`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` is shorter than [`event_size`](Self::event_size).
+    pub fn decode_slice(&self, src: &[u8]) -> Result<InputEvent, io::Error> {
+        self.decode_valid(src)
+            .map_err(From::from)
+    }
+
+    /// Deserializes a single event from the start of `src`.
+    ///
+    /// Returns `None` if the event was invalid and discarded due to
+    /// [`InvalidEvents::Skip`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` is shorter than [`event_size`](Self::event_size).
+    pub fn decode_event(&self, src: &[u8]) -> Result<Option<InputEvent>, io::Error> {
+        match (self.decode_valid(src), self.invalid) {
+            (Ok(event), _) => Ok(Some(event)),
+            (Err(..), InvalidEvents::Skip) => Ok(None),
+            (Err(e), InvalidEvents::Error) => Err(e.into()),
         }
     }
 
+    fn decode_valid(&self, src: &[u8]) -> Result<InputEvent, RangeError> {
+        self.layout.decode(src, self.byte_order)
+            .and_then(|event| match self.validation {
+                Validation::Type => Ok(event),
+                Validation::Strict => Event::new(event).map(|_| event),
+            })
+    }
+
+    /// Serializes a single event into the start of `dst`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` is shorter than [`event_size`](Self::event_size), or
+    /// if the timestamp cannot be represented by a 32-bit
+    /// [`layout`](Self::with_layout). See
+    /// [`try_encode_slice`](Self::try_encode_slice) for a fallible version.
+    pub fn encode_slice(&self, item: &InputEvent, dst: &mut [u8]) {
+        self.try_encode_slice(item, dst)
+            .expect("event timestamp out of range for layout")
+    }
+
+    /// Serializes a single event into the start of `dst`, failing with
+    /// [`io::ErrorKind::InvalidData`] if its timestamp cannot be represented
+    /// by the codec's layout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` is shorter than [`event_size`](Self::event_size).
+    pub fn try_encode_slice(&self, item: &InputEvent, dst: &mut [u8]) -> Result<(), io::Error> {
+        self.layout.encode(item, self.byte_order, dst)
+            .map_err(From::from)
+    }
+
     #[cfg(feature = "bytes")]
     #[cfg_attr(feature = "dox", doc(cfg(feature = "bytes")))]
     pub fn decode_bytes(&mut self, src: &mut BytesMut) -> Result<Option<InputEvent>, io::Error> {
-        if src.len() >= mem::size_of::<InputEvent>() {
-            let src = src.split_to(mem::size_of::<InputEvent>());
-            let event = unsafe {
-                let mut event = mem::MaybeUninit::<input_event>::uninit();
-                ptr::copy_nonoverlapping(src.as_ptr(), event.as_mut_ptr() as *mut u8, mem::size_of::<InputEvent>());
-                InputEvent::from_raw(&*event.as_ptr()).map(|e| *e).map_err(From::from)
-            };
+        while src.len() >= self.event_size() {
+            let src = src.split_to(self.event_size());
+            if let Some(event) = self.decode_event(&src)? {
+                return Ok(Some(event))
+            }
+        }
+
+        Ok(None)
+    }
 
-            event.map(Some)
-        } else {
-            Ok(None)
+    #[cfg(feature = "bytes")]
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "bytes")))]
+    pub fn encode_bytes<E: AsRef<InputEvent>>(&mut self, item: E, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let len = dst.len();
+        dst.put_bytes(0, self.event_size());
+        let res = self.try_encode_slice(item.as_ref(), &mut dst[len..]);
+        if res.is_err() {
+            dst.truncate(len);
         }
+        res
+    }
+}
+
+impl Default for EventCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes and encodes whole frames of events, each terminated by a
+/// `SYN_REPORT`.
+///
+/// Decoded frames include their terminating `SYN_REPORT` event. When the
+/// stream reports `SYN_DROPPED`, the incomplete frame and all events up to and
+/// including the next `SYN_REPORT` are discarded as described by the evdev
+/// documentation.
+#[derive(Clone, Debug)]
+pub struct FrameCodec {
+    codec: EventCodec,
+    frame: Vec<InputEvent>,
+    dropped: bool,
+}
+
+impl FrameCodec {
+    pub const fn new(codec: EventCodec) -> Self {
+        FrameCodec {
+            codec,
+            frame: Vec::new(),
+            dropped: false,
+        }
+    }
+
+    /// The underlying event codec.
+    pub const fn codec(&self) -> &EventCodec {
+        &self.codec
+    }
+
+    /// Extracts the underlying event codec.
+    pub fn into_inner(self) -> EventCodec {
+        self.codec
+    }
+
+    /// Feeds a single decoded event into the frame, returning the frame if
+    /// it has been completed.
+    pub fn push_event(&mut self, event: InputEvent) -> Option<Vec<InputEvent>> {
+        match (event.kind, SynchronizeKind::from_code(event.code)) {
+            (EventKind::Synchronize, Ok(SynchronizeKind::Dropped)) => {
+                self.frame.clear();
+                self.dropped = true;
+                None
+            },
+            (EventKind::Synchronize, Ok(SynchronizeKind::Report)) if self.dropped => {
+                self.dropped = false;
+                None
+            },
+            (EventKind::Synchronize, Ok(SynchronizeKind::Report)) => {
+                self.frame.push(event);
+                Some(std::mem::take(&mut self.frame))
+            },
+            _ if self.dropped => None,
+            _ => {
+                self.frame.push(event);
+                None
+            },
+        }
+    }
+
+    #[cfg(feature = "bytes")]
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "bytes")))]
+    pub fn decode_bytes(&mut self, src: &mut BytesMut) -> Result<Option<Vec<InputEvent>>, io::Error> {
+        while let Some(event) = self.codec.decode_bytes(src)? {
+            if let Some(frame) = self.push_event(event) {
+                return Ok(Some(frame))
+            }
+        }
+
+        Ok(None)
     }
 
     #[cfg(feature = "bytes")]
     #[cfg_attr(feature = "dox", doc(cfg(feature = "bytes")))]
-    pub fn encode_bytes(&mut self, item: InputEvent, dst: &mut BytesMut) -> Result<(), io::Error> {
-        dst.reserve(mem::size_of::<InputEvent>());
-        dst.put_slice(item.as_bytes());
+    pub fn encode_bytes<E: AsRef<InputEvent>>(&mut self, items: &[E], dst: &mut BytesMut) -> Result<(), io::Error> {
+        dst.reserve(self.codec.event_size() * items.len());
+        for item in items {
+            self.codec.encode_bytes(item, dst)?;
+        }
         Ok(())
     }
 }
 
+impl From<EventCodec> for FrameCodec {
+    fn from(codec: EventCodec) -> Self {
+        Self::new(codec)
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+#[test]
+fn event_codec_validation() {
+    use crate::{EventTime, Key, KeyEvent, KeyState, SynchronizeEvent};
+
+    let time = EventTime::new(1, 2);
+    let key = |key| InputEvent::from(KeyEvent::new(time, key, KeyState::PRESSED));
+    let syn = |kind| InputEvent::from(SynchronizeEvent::new(time, kind, 0));
+    let bytes = |events: &[InputEvent]| -> Vec<u8> {
+        events.iter().flat_map(|e| e.as_bytes().iter().cloned()).collect()
+    };
+    // a key code beyond KEY_MAX is a valid type but not a valid typed event
+    let unknown = InputEvent { code: 0x2ff + 1, ..key(Key::A) };
+    // an event type beyond EV_MAX is never valid
+    let mut malformed = bytes(&[key(Key::A)]);
+    let type_offset = EventCodec::new().event_size() - 8;
+    malformed[type_offset] = 0x30;
+
+    let codec = EventCodec::new();
+    assert_eq!(codec.decode_slice(&bytes(&[key(Key::A)])).unwrap(), key(Key::A));
+    assert_eq!(codec.decode_slice(&bytes(&[unknown])).unwrap(), unknown);
+    assert_eq!(codec.decode_slice(&malformed).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let strict = codec.with_validation(Validation::Strict);
+    assert_eq!(strict.decode_slice(&bytes(&[unknown])).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(strict.decode_event(&bytes(&[unknown])).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    let skip = strict.with_invalid_events(InvalidEvents::Skip);
+    assert_eq!(skip.decode_event(&bytes(&[unknown])).unwrap(), None);
+    assert_eq!(skip.decode_event(&malformed).unwrap(), None);
+    assert!(skip.decode_slice(&malformed).is_err());
+
+    let mut frames = FrameCodec::new(codec);
+    let events = [
+        key(Key::A), syn(SynchronizeKind::Report),
+        key(Key::B), syn(SynchronizeKind::Dropped), key(Key::C), syn(SynchronizeKind::Report),
+        key(Key::D),
+    ];
+    let decoded: Vec<_> = events.iter().filter_map(|&e| frames.push_event(e)).collect();
+    assert_eq!(decoded, [vec![key(Key::A), syn(SynchronizeKind::Report)]]);
+    assert_eq!(frames.push_event(syn(SynchronizeKind::Report)), Some(vec![key(Key::D), syn(SynchronizeKind::Report)]));
+
+    #[cfg(feature = "bytes")]
+    {
+        let mut src = BytesMut::from(&bytes(&events)[..]);
+        src.extend_from_slice(&malformed);
+        let mut frames = FrameCodec::new(EventCodec::new().with_invalid_events(InvalidEvents::Skip));
+        assert_eq!(frames.decode_bytes(&mut src).unwrap(), Some(vec![key(Key::A), syn(SynchronizeKind::Report)]));
+        assert_eq!(frames.decode_bytes(&mut src).unwrap(), None);
+        assert!(src.is_empty());
+        src.extend_from_slice(&bytes(&[syn(SynchronizeKind::Report)]));
+        assert_eq!(frames.decode_bytes(&mut src).unwrap(), Some(vec![key(Key::D), syn(SynchronizeKind::Report)]));
+
+        // a partial event is left for the next call
+        let mut src = BytesMut::from(&bytes(&[key(Key::A)])[..4]);
+        assert_eq!(EventCodec::new().decode_bytes(&mut src).unwrap(), None);
+        assert_eq!(src.len(), 4);
+
+        let mut dst = BytesMut::new();
+        frames.encode_bytes(&[KeyEvent::new(time, Key::A, KeyState::PRESSED)], &mut dst).unwrap();
+        frames.encode_bytes(&[syn(SynchronizeKind::Report)], &mut dst).unwrap();
+        assert_eq!(&dst[..], &bytes(&[key(Key::A), syn(SynchronizeKind::Report)])[..]);
+    }
+
+    #[cfg(feature = "tokio-util-0_7")]
+    {
+        use tokio_util_0_7::codec::Encoder;
+
+        let mut codec = EventCodec::new();
+        let mut dst = BytesMut::new();
+        codec.encode(KeyEvent::new(time, Key::A, KeyState::PRESSED), &mut dst).unwrap();
+        codec.encode(key(Key::B), &mut dst).unwrap();
+        assert_eq!(&dst[..], &bytes(&[key(Key::A), key(Key::B)])[..]);
+    }
+}
+
 #[cfg(feature = "tokio-util-0_6")]
 #[cfg_attr(feature = "dox", doc(cfg(feature = "tokio-util-0_6")))]
 mod tokio_util_impl_0_6 {
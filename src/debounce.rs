@@ -0,0 +1,389 @@
+//! Suppressing chatter from worn or cheap switches.
+//!
+//! A [`Debouncer`] filters the key and switch events of a stream, discarding
+//! the rapid changes caused by mechanical bounce. Each key or switch can use
+//! its own [`DebounceMode`]:
+//!
+//! - [`Eager`](DebounceMode::Eager) reports a change immediately and then
+//!   ignores further changes until the window has passed, adding no latency.
+//! - [`Deferred`](DebounceMode::Deferred) only reports a change once the
+//!   input has remained stable for the whole window, which also rejects
+//!   spurious single glitches at the cost of that much latency.
+//!
+//! Events are forwarded a whole frame at a time, and frames left empty by
+//! suppressed events are dropped along with their `SYN_REPORT`. Changes that
+//! are only reported once a window passes are delivered in a frame of their
+//! own, either when a later event arrives or when calling
+//! [`timeout`](Debouncer::timeout) once the
+//! [`deadline`](Debouncer::deadline) passes. Every suppressed event is
+//! recorded as a [`Bounce`] for diagnostics.
+//!
+//! A `SYN_DROPPED` discards the incomplete frame and forgets the state of
+//! every input, so the next change to each is reported as it arrives. To
+//! debounce a device, use the debouncer as a stage of a
+//! [`Pipeline`](crate::Pipeline).
+
+use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::collections::vec_deque;
+use crate::{
+    EventTime, InputEvent, EventRef, Key, KeyEvent, KeyState, SwitchKind,
+    SwitchEvent, SynchronizeEvent, SynchronizeKind,
+};
+
+/// How changes to a key or switch are debounced.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum DebounceMode {
+    /// Reports each change immediately, then ignores any further changes
+    /// within the window.
+    Eager(Duration),
+    /// Reports a change only after no further changes have occurred within
+    /// the window.
+    Deferred(Duration),
+}
+
+impl DebounceMode {
+    /// The default debounce window.
+    pub const DEFAULT_WINDOW: Duration = Duration::from_millis(5);
+
+    /// The debounce window.
+    pub const fn window(&self) -> Duration {
+        match *self {
+            DebounceMode::Eager(window) | DebounceMode::Deferred(window) => window,
+        }
+    }
+}
+
+impl Default for DebounceMode {
+    fn default() -> Self {
+        DebounceMode::Eager(Self::DEFAULT_WINDOW)
+    }
+}
+
+/// A key or switch being debounced.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum DebounceInput {
+    /// A key or button.
+    Key(Key),
+    /// A switch.
+    Switch(SwitchKind),
+}
+
+impl DebounceInput {
+    fn event(&self, time: EventTime, value: i32) -> InputEvent {
+        match *self {
+            DebounceInput::Key(key) => KeyEvent::new(time, key, KeyState { value }).into(),
+            DebounceInput::Switch(switch) => SwitchEvent::new(time, switch, value).into(),
+        }
+    }
+}
+
+/// An event that was suppressed as bounce.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Bounce {
+    /// The timestamp of the suppressed event.
+    pub time: EventTime,
+    /// The key or switch that bounced.
+    pub input: DebounceInput,
+    /// The value of the suppressed event.
+    pub value: i32,
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+struct State {
+    reported: i32,
+    actual: i32,
+    /// The time of the last reported change in eager mode, or of the last
+    /// actual change in deferred mode.
+    changed: Option<EventTime>,
+}
+
+impl State {
+    /// The state of an input after events were dropped, which differs from
+    /// any value it can report.
+    const UNKNOWN: State = State {
+        reported: -1,
+        actual: -1,
+        changed: None,
+    };
+
+    fn settled(&self, time: EventTime, window: Duration) -> bool {
+        match self.changed {
+            Some(changed) => time.saturating_duration_since(changed) >= window,
+            None => true,
+        }
+    }
+
+    fn deadline(&self, window: Duration) -> Option<EventTime> {
+        match self.changed {
+            Some(changed) if self.actual != self.reported =>
+                Some(changed.checked_add(window).unwrap_or(changed)),
+            _ => None,
+        }
+    }
+}
+
+/// Debounces the keys and switches of a stream of events.
+#[derive(Clone, Debug)]
+pub struct Debouncer {
+    mode: DebounceMode,
+    modes: HashMap<DebounceInput, DebounceMode>,
+    states: HashMap<DebounceInput, State>,
+    frame: Vec<InputEvent>,
+    log: VecDeque<Bounce>,
+    log_capacity: usize,
+}
+
+impl Default for Debouncer {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl Debouncer {
+    /// The default number of bounces kept in the log.
+    pub const DEFAULT_LOG_CAPACITY: usize = 256;
+
+    /// Debounces every key and switch using `mode`.
+    pub fn new(mode: DebounceMode) -> Self {
+        Debouncer {
+            mode,
+            modes: Default::default(),
+            states: Default::default(),
+            frame: Default::default(),
+            log: Default::default(),
+            log_capacity: Self::DEFAULT_LOG_CAPACITY,
+        }
+    }
+
+    /// Debounces a specific key differently.
+    pub fn with_key(mut self, key: Key, mode: DebounceMode) -> Self {
+        self.insert(DebounceInput::Key(key), mode);
+        self
+    }
+
+    /// Debounces a specific switch differently.
+    pub fn with_switch(mut self, switch: SwitchKind, mode: DebounceMode) -> Self {
+        self.insert(DebounceInput::Switch(switch), mode);
+        self
+    }
+
+    /// Sets how many bounces are kept in the log before the oldest are
+    /// discarded.
+    pub fn with_log_capacity(mut self, capacity: usize) -> Self {
+        self.log_capacity = capacity;
+        while self.log.len() > capacity {
+            self.log.pop_front();
+        }
+        self
+    }
+
+    /// Overrides the mode of a key or switch, returning its previous
+    /// override.
+    pub fn insert(&mut self, input: DebounceInput, mode: DebounceMode) -> Option<DebounceMode> {
+        self.modes.insert(input, mode)
+    }
+
+    /// Removes the override for a key or switch, returning it.
+    pub fn remove(&mut self, input: DebounceInput) -> Option<DebounceMode> {
+        self.modes.remove(&input)
+    }
+
+    /// The mode used for a key or switch.
+    pub fn mode(&self, input: DebounceInput) -> DebounceMode {
+        self.modes.get(&input).cloned().unwrap_or(self.mode)
+    }
+
+    /// The suppressed events, oldest first.
+    pub fn bounces(&self) -> vec_deque::Iter<'_, Bounce> {
+        self.log.iter()
+    }
+
+    /// Removes and returns the suppressed events logged so far.
+    pub fn take_bounces(&mut self) -> Vec<Bounce> {
+        self.log.drain(..).collect()
+    }
+
+    /// The time at which a change that is being held back is due to be
+    /// reported.
+    pub fn deadline(&self) -> Option<EventTime> {
+        self.states.iter()
+            .filter_map(|(&input, state)| state.deadline(self.mode(input).window()))
+            .min()
+    }
+
+    /// How long to wait for further events before calling
+    /// [`timeout`](Self::timeout).
+    ///
+    /// Returns `None` if there is no deadline to wait for.
+    pub fn time_until_deadline(&self, now: EventTime) -> Option<Duration> {
+        self.deadline().map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    fn log(&mut self, time: EventTime, input: DebounceInput, value: i32) {
+        if self.log_capacity == 0 {
+            return
+        }
+        if self.log.len() >= self.log_capacity {
+            self.log.pop_front();
+        }
+        self.log.push_back(Bounce { time, input, value });
+    }
+
+    /// Processes a single input event, appending the resulting events to `out`.
+    ///
+    /// Events are held back until the `SYN_REPORT` that ends their frame.
+    pub fn process(&mut self, event: &InputEvent, out: &mut Vec<InputEvent>) {
+        self.timeout(event.time, out);
+
+        let (input, value) = match EventRef::new(event) {
+            Ok(EventRef::Key(key)) => (DebounceInput::Key(key.key), key.value.value),
+            Ok(EventRef::Switch(switch)) => (DebounceInput::Switch(switch.switch), switch.value),
+            Ok(EventRef::Synchronize(sync)) if sync.kind == SynchronizeKind::Report => {
+                if !self.frame.is_empty() {
+                    out.append(&mut self.frame);
+                    out.push(*event);
+                }
+                return
+            },
+            Ok(EventRef::Synchronize(sync)) if sync.kind == SynchronizeKind::Dropped => {
+                self.frame.clear();
+                for state in self.states.values_mut() {
+                    *state = State::UNKNOWN;
+                }
+                return self.frame.push(*event)
+            },
+            _ => return self.frame.push(*event),
+        };
+        let mode = self.mode(input);
+        let state = self.states.entry(input).or_default();
+
+        if let DebounceInput::Key(..) = input {
+            if value == KeyState::AUTOREPEAT.value {
+                // repeats only make sense while the key is reported as held
+                if state.reported == KeyState::PRESSED.value {
+                    self.frame.push(*event);
+                }
+                return
+            }
+        }
+        if state.actual == value {
+            return
+        }
+
+        match mode {
+            DebounceMode::Eager(window) => {
+                state.actual = value;
+                if state.settled(event.time, window) && value != state.reported {
+                    state.reported = value;
+                    state.changed = Some(event.time);
+                    self.frame.push(*event);
+                } else {
+                    self.log(event.time, input, value);
+                }
+            },
+            DebounceMode::Deferred(window) => {
+                let pending = match state.changed {
+                    Some(changed) if state.actual != state.reported => Some((changed, state.actual)),
+                    _ => None,
+                };
+                state.actual = value;
+                state.changed = Some(event.time);
+                let reported = state.reported;
+                if value != reported && window == Duration::from_secs(0) {
+                    state.reported = value;
+                    self.frame.push(*event);
+                }
+                if let Some((time, value)) = pending {
+                    self.log(time, input, value);
+                }
+                if value == reported {
+                    self.log(event.time, input, value);
+                }
+            },
+        }
+    }
+
+    /// Reports any changes whose window has passed by `now`, appending them
+    /// to `out` as a frame of their own.
+    pub fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        let mut due: Vec<_> = self.states.iter()
+            .filter_map(|(&input, state)| state.deadline(self.mode(input).window()).map(|deadline| (deadline, input)))
+            .filter(|&(deadline, _)| deadline <= now)
+            .collect();
+        if due.is_empty() {
+            return
+        }
+        due.sort();
+        for (deadline, input) in due {
+            let mode = self.mode(input);
+            let state = self.states.get_mut(&input).expect("due input has a state");
+            state.reported = state.actual;
+            if let DebounceMode::Eager(..) = mode {
+                state.changed = Some(deadline);
+            }
+            out.push(input.event(deadline, state.actual));
+        }
+        out.push(SynchronizeEvent::report(now).into());
+    }
+}
+
+#[test]
+fn debounce_modes() {
+    let at = |ms: i64| EventTime::new(0, ms * 1000);
+    let key = |ms, key, state| InputEvent::from(KeyEvent::new(at(ms), key, state));
+    let syn = |ms| InputEvent::from(SynchronizeEvent::report(at(ms)));
+    let lid = |ms, value| InputEvent::from(SwitchEvent::new(at(ms), SwitchKind::Lid, value));
+    let window = Duration::from_millis(10);
+
+    let mut debouncer = Debouncer::new(DebounceMode::Eager(window))
+        .with_switch(SwitchKind::Lid, DebounceMode::Deferred(window));
+    let mut out = Vec::new();
+    for event in &[
+        key(0, Key::A, KeyState::PRESSED), syn(0),
+        key(2, Key::A, KeyState::RELEASED), syn(2),
+        key(4, Key::A, KeyState::PRESSED), lid(4, 1), syn(4),
+        key(6, Key::A, KeyState::RELEASED), lid(6, 0), syn(6),
+        lid(8, 1), syn(8),
+    ] {
+        debouncer.process(event, &mut out);
+    }
+    assert_eq!(out, [key(0, Key::A, KeyState::PRESSED), syn(0)]);
+    assert_eq!(debouncer.deadline(), Some(at(10)));
+
+    out.clear();
+    debouncer.timeout(at(20), &mut out);
+    assert_eq!(out, [key(10, Key::A, KeyState::RELEASED), lid(18, 1), syn(20)]);
+    assert_eq!(debouncer.deadline(), None);
+
+    let bounces: Vec<_> = debouncer.take_bounces().into_iter()
+        .map(|bounce| (bounce.time, bounce.value))
+        .collect();
+    assert_eq!(bounces, [(at(2), 0), (at(4), 1), (at(6), 0), (at(4), 1), (at(6), 0)]);
+    assert_eq!(debouncer.bounces().count(), 0);
+}
+
+#[test]
+fn debounce_dropped() {
+    let at = |ms: i64| EventTime::new(0, ms * 1000);
+    let key = |ms, key, state| InputEvent::from(KeyEvent::new(at(ms), key, state));
+    let syn = |ms| InputEvent::from(SynchronizeEvent::report(at(ms)));
+    let dropped = InputEvent::from(SynchronizeEvent::new(at(2), SynchronizeKind::Dropped, 0));
+
+    let mut debouncer = Debouncer::new(DebounceMode::Eager(Duration::from_millis(10)));
+    let mut out = Vec::new();
+    for event in &[
+        key(0, Key::A, KeyState::PRESSED), syn(0),
+        key(1, Key::B, KeyState::PRESSED), dropped,
+        key(3, Key::A, KeyState::RELEASED), syn(3),
+        key(4, Key::A, KeyState::PRESSED), syn(4),
+    ] {
+        debouncer.process(event, &mut out);
+    }
+    // the release within the window is reported since the state was unknown
+    assert_eq!(out, [
+        key(0, Key::A, KeyState::PRESSED), syn(0),
+        dropped, key(3, Key::A, KeyState::RELEASED), syn(3),
+    ]);
+    assert_eq!(debouncer.bounces().map(|bounce| bounce.time).collect::<Vec<_>>(), [at(4)]);
+}
@@ -3,15 +3,18 @@
 use std::io;
 use std::mem::{MaybeUninit, size_of};
 use std::slice::from_raw_parts_mut;
+use std::time::Duration;
+use std::convert::TryFrom;
 use std::os::unix::io::{RawFd, AsRawFd, IntoRawFd, FromRawFd};
 use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 use nix;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
 use crate::sys;
 use crate::{
     AbsoluteAxis, AbsoluteInfo, AutorepeatKind, EventKind, InputId,
     InputProperty, Key, LedKind, MiscKind, RelativeAxis, SoundKind, SwitchKind,
     ForceFeedbackKind, ForceFeedbackStatusKind,
-    InputEvent,
+    InputEvent, ClockId, DeviceCapabilities,
 };
 use crate::macros::convert_error;
 use crate::bitmask::Bitmask;
@@ -90,6 +93,14 @@ impl<F: AsRawFd> EvdevHandle<F> {
             .map_err(convert_error)
     }
 
+    /// Waits until events are available to be read from the device.
+    ///
+    /// Returns `false` if the timeout elapsed first. A timeout of `None` waits
+    /// indefinitely.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        wait_readable(self.as_fd(), timeout)
+    }
+
     /// Read events from the input device
     pub fn read_input_events<'e>(&self, events: &'e mut [MaybeUninit<InputEvent>]) -> io::Result<&'e mut [InputEvent]> {
         let res = {
@@ -373,6 +384,13 @@ impl<F: AsRawFd> EvdevHandle<F> {
         }
     }
 
+    /// `EVIOCSCLOCKID`
+    ///
+    /// Selects the clock used to timestamp events read from the device.
+    pub fn set_clock(&self, clock: ClockId) -> io::Result<()> {
+        self.set_clock_id(clock.into())
+    }
+
     /// `EVIOCSCLOCKID`
     pub fn set_clock_id(&self, value: i32) -> io::Result<()> {
         unsafe {
@@ -382,3 +400,99 @@ impl<F: AsRawFd> EvdevHandle<F> {
         }
     }
 }
+
+/// Converts a timeout to whole milliseconds for `poll`, rounding up so that
+/// waiting for a deadline never wakes up just before it.
+pub(crate) fn poll_timeout(timeout: Duration) -> PollTimeout {
+    timeout.checked_add(Duration::from_nanos(999_999))
+        .and_then(|timeout| PollTimeout::try_from(timeout.as_millis()).ok())
+        .unwrap_or(PollTimeout::MAX)
+}
+
+/// Waits until `fd` is readable, returning `false` if the timeout elapsed
+/// first.
+pub(crate) fn wait_readable(fd: BorrowedFd<'_>, timeout: Option<Duration>) -> io::Result<bool> {
+    let timeout = match timeout {
+        Some(timeout) => poll_timeout(timeout),
+        None => PollTimeout::NONE,
+    };
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    poll(&mut fds, timeout)
+        .map(|count| count > 0)
+        .map_err(convert_error)
+}
+
+/// The operations of an input device used to read events and query its
+/// state.
+///
+/// This is implemented by [`EvdevHandle`], and by
+/// [`MockDevice`](crate::mock::MockDevice) for testing without real devices.
+pub trait EvdevDevice {
+    /// Reads events from the device.
+    fn read_input_events<'e>(&self, events: &'e mut [MaybeUninit<InputEvent>]) -> io::Result<&'e mut [InputEvent]>;
+
+    /// Reads a single event from the device.
+    fn read_input_event(&self) -> io::Result<InputEvent> {
+        let mut events = [MaybeUninit::<InputEvent>::uninit()];
+        match self.read_input_events(&mut events)? {
+            [event] => Ok(*event),
+            _ => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "empty evdev read")),
+        }
+    }
+
+    /// Waits until events are available to be read from the device.
+    fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool>;
+
+    /// Queries the identity and all capabilities of the device.
+    fn capabilities(&self) -> io::Result<DeviceCapabilities>;
+
+    /// Queries the current state of an absolute axis.
+    fn absolute_info(&self, abs: AbsoluteAxis) -> io::Result<AbsoluteInfo>;
+
+    /// Queries which keys are currently pressed.
+    fn pressed_keys(&self) -> io::Result<Bitmask<Key>>;
+
+    /// Queries which LEDs are currently lit.
+    fn active_leds(&self) -> io::Result<Bitmask<LedKind>>;
+
+    /// Grabs the device for exclusive access, or releases the grab.
+    fn grab(&self, grab: bool) -> io::Result<()>;
+}
+
+impl<F: AsRawFd> EvdevDevice for EvdevHandle<F> {
+    fn read_input_events<'e>(&self, events: &'e mut [MaybeUninit<InputEvent>]) -> io::Result<&'e mut [InputEvent]> {
+        EvdevHandle::read_input_events(self, events)
+    }
+
+    fn read_input_event(&self) -> io::Result<InputEvent> {
+        EvdevHandle::read_input_event(self)
+    }
+
+    fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        EvdevHandle::wait_readable(self, timeout)
+    }
+
+    fn capabilities(&self) -> io::Result<DeviceCapabilities> {
+        EvdevHandle::capabilities(self)
+    }
+
+    fn absolute_info(&self, abs: AbsoluteAxis) -> io::Result<AbsoluteInfo> {
+        EvdevHandle::absolute_info(self, abs)
+    }
+
+    fn pressed_keys(&self) -> io::Result<Bitmask<Key>> {
+        let mut keys = Bitmask::default();
+        self.key_state(&mut keys)?;
+        Ok(keys)
+    }
+
+    fn active_leds(&self) -> io::Result<Bitmask<LedKind>> {
+        let mut leds = Bitmask::default();
+        self.led_state(&mut leds)?;
+        Ok(leds)
+    }
+
+    fn grab(&self, grab: bool) -> io::Result<()> {
+        EvdevHandle::grab(self, grab)
+    }
+}
@@ -0,0 +1,783 @@
+//! A small versioned protocol for forwarding input devices over a byte stream.
+//!
+//! A [`Sender`] begins the stream by describing the forwarded device with its
+//! [`DeviceCapabilities`], so that the [`Receiver`] can create a matching
+//! [`UInputHandle`]. Each frame of events is then sent as a single
+//! [`Message::Events`], encoded via [`EventCodec`] using the little endian
+//! [`EventLayout::Bits64`] layout regardless of the machines involved.
+//!
+//! Any `Read`/`Write` transport works, such as a `TcpStream` or a
+//! `UnixStream`. Enabling the `tokio-1` feature additionally provides
+//! [`AsyncSender`] and [`AsyncReceiver`] for tokio's `AsyncRead`/`AsyncWrite`.
+//!
+//! Senders should call [`Sender::keepalive`] periodically while idle, and
+//! receivers configured with [`Receiver::with_timeout`] treat a stream that
+//! has been silent for longer than that as disconnected, so that a half-open
+//! connection can't leave keys pressed. Either way,
+//! [`Receiver::release_events`] provides the events needed to release any
+//! keys the remote side left pressed.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::fd::AsFd;
+use std::time::Duration;
+use crate::{
+    AbsoluteAxis, AbsoluteInfo, AbsoluteInfoSetup, EventCodec,
+    InputEvent, InputId, EventTime, UInputHandle,
+};
+use crate::abi::{EventLayout, ByteOrder};
+use crate::bitmask::{Bitmask, BitmaskTrait};
+use crate::capabilities::DeviceCapabilities;
+use crate::tracker::KeyStateTracker;
+
+/// Identifies the start of a stream.
+pub const MAGIC: [u8; 4] = *b"ILFW";
+
+/// The version of the protocol implemented by this module.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// The largest message payload that will be accepted.
+pub const MAX_MESSAGE_LEN: usize = 0x10000;
+
+const HEADER_LEN: usize = 5;
+const HANDSHAKE_LEN: usize = 6;
+
+const TAG_CAPABILITIES: u8 = 1;
+const TAG_EVENTS: u8 = 2;
+const TAG_KEEPALIVE: u8 = 3;
+const TAG_RELEASE_ALL: u8 = 4;
+
+const CODEC: EventCodec = EventCodec::new()
+    .with_layout(EventLayout::Bits64)
+    .with_byte_order(ByteOrder::Little);
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn encode_len(len: usize, dst: &mut Vec<u8>) -> io::Result<()> {
+    let len = u16::try_from(len)
+        .map_err(|_| invalid_input("forwarded capabilities too large"))?;
+    dst.extend_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// A single protocol message.
+#[derive(Clone, Debug)]
+pub enum Message {
+    /// Describes the forwarded device, always the first message of a stream.
+    Capabilities(DeviceCapabilities),
+    /// A frame of events, usually terminated by a `SYN_REPORT`.
+    Events(Vec<InputEvent>),
+    /// Sent periodically while idle to indicate the sender is still alive.
+    Keepalive,
+    /// Requests that all currently pressed keys be released, typically sent
+    /// immediately before disconnecting.
+    ReleaseAll,
+}
+
+impl Message {
+    /// Serializes the message, including its header, into `dst`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] without modifying `dst` if
+    /// the message is too large to be received, see [`MAX_MESSAGE_LEN`].
+    pub fn encode(&self, dst: &mut Vec<u8>) -> io::Result<()> {
+        let start = dst.len();
+        let res = self.encode_message(dst);
+        if res.is_err() {
+            dst.truncate(start);
+        }
+        res
+    }
+
+    fn encode_message(&self, dst: &mut Vec<u8>) -> io::Result<()> {
+        let start = dst.len();
+        dst.extend_from_slice(&[0u8; HEADER_LEN]);
+        let tag = match self {
+            Message::Capabilities(caps) => {
+                encode_capabilities(caps, dst)?;
+                TAG_CAPABILITIES
+            },
+            Message::Events(events) => {
+                for event in events {
+                    let len = dst.len();
+                    dst.resize(len + CODEC.event_size(), 0);
+                    CODEC.encode_slice(event, &mut dst[len..]);
+                }
+                TAG_EVENTS
+            },
+            Message::Keepalive => TAG_KEEPALIVE,
+            Message::ReleaseAll => TAG_RELEASE_ALL,
+        };
+        let len = dst.len() - start - HEADER_LEN;
+        if len > MAX_MESSAGE_LEN {
+            return Err(invalid_input("forwarded message too large"))
+        }
+        dst[start] = tag;
+        dst[start + 1..start + HEADER_LEN].copy_from_slice(&(len as u32).to_le_bytes());
+        Ok(())
+    }
+
+    /// Parses a message header, returning the tag and payload length.
+    fn decode_header(header: &[u8; HEADER_LEN]) -> io::Result<(u8, usize)> {
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&header[1..]);
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_MESSAGE_LEN {
+            Err(invalid_data("forwarded message too large"))
+        } else {
+            Ok((header[0], len))
+        }
+    }
+
+    /// Deserializes a message payload.
+    fn decode_payload(tag: u8, payload: &[u8]) -> io::Result<Self> {
+        match tag {
+            TAG_CAPABILITIES => decode_capabilities(payload).map(Message::Capabilities),
+            TAG_EVENTS => {
+                let events = payload.chunks_exact(CODEC.event_size());
+                if !events.remainder().is_empty() {
+                    return Err(invalid_data("truncated forwarded event"))
+                }
+                events.filter_map(|event| CODEC.decode_event(event).transpose())
+                    .collect::<io::Result<_>>()
+                    .map(Message::Events)
+            },
+            TAG_KEEPALIVE => Ok(Message::Keepalive),
+            TAG_RELEASE_ALL => Ok(Message::ReleaseAll),
+            _ => Err(invalid_data("unknown forwarded message")),
+        }
+    }
+
+    /// Attempts to deserialize a message from the start of `src`.
+    ///
+    /// Returns the message and the number of bytes it occupied, or `None` if
+    /// `src` does not yet contain a complete message.
+    pub fn decode(src: &[u8]) -> io::Result<Option<(Self, usize)>> {
+        if src.len() < HEADER_LEN {
+            return Ok(None)
+        }
+
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&src[..HEADER_LEN]);
+        let (tag, len) = Self::decode_header(&header)?;
+        match src.get(HEADER_LEN..HEADER_LEN + len) {
+            Some(payload) => Self::decode_payload(tag, payload)
+                .map(|msg| Some((msg, HEADER_LEN + len))),
+            None => Ok(None),
+        }
+    }
+}
+
+fn handshake() -> [u8; HANDSHAKE_LEN] {
+    let mut buf = [0u8; HANDSHAKE_LEN];
+    buf[..4].copy_from_slice(&MAGIC);
+    buf[4..].copy_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    buf
+}
+
+fn check_handshake(buf: &[u8; HANDSHAKE_LEN]) -> io::Result<()> {
+    if buf[..4] != MAGIC {
+        Err(invalid_data("not an input forwarding stream"))
+    } else if u16::from_le_bytes([buf[4], buf[5]]) != PROTOCOL_VERSION {
+        Err(invalid_data("unsupported input forwarding protocol version"))
+    } else {
+        Ok(())
+    }
+}
+
+fn encode_bytes(data: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+    encode_len(data.len(), dst)?;
+    dst.extend_from_slice(data);
+    Ok(())
+}
+
+fn encode_capabilities(caps: &DeviceCapabilities, dst: &mut Vec<u8>) -> io::Result<()> {
+    for v in &[caps.id.bustype, caps.id.vendor, caps.id.product, caps.id.version] {
+        dst.extend_from_slice(&v.to_le_bytes());
+    }
+    dst.extend_from_slice(&caps.ff_effects_max.to_le_bytes());
+    encode_bytes(&caps.name, dst)?;
+    encode_bytes(&caps.properties, dst)?;
+    encode_bytes(&caps.events, dst)?;
+    encode_bytes(&caps.keys, dst)?;
+    encode_bytes(&caps.relative, dst)?;
+    encode_bytes(&caps.misc, dst)?;
+    encode_bytes(&caps.switches, dst)?;
+    encode_bytes(&caps.leds, dst)?;
+    encode_bytes(&caps.sounds, dst)?;
+    encode_bytes(&caps.force_feedback, dst)?;
+    encode_len(caps.absolute.len(), dst)?;
+    for abs in &caps.absolute {
+        dst.extend_from_slice(&(abs.axis as u16).to_le_bytes());
+        let info = &abs.info;
+        for v in &[info.value, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution] {
+            dst.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Reads a message header, returning `false` if the stream ended cleanly
+/// before it.
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "forwarded stream timed out")
+}
+
+/// Records the result of reading part of a message header, returning `false`
+/// if the stream ended cleanly before the header began.
+fn header_progress(len: &mut usize, read: io::Result<usize>) -> io::Result<bool> {
+    match read {
+        Ok(0) if *len == 0 => Ok(false),
+        Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated forwarded message header")),
+        Ok(read) => {
+            *len += read;
+            Ok(true)
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R, header: &mut [u8; HEADER_LEN]) -> io::Result<bool> {
+    let mut len = 0;
+    while len < header.len() {
+        let read = reader.read(&mut header[len..]);
+        if !header_progress(&mut len, read)? {
+            return Ok(false)
+        }
+    }
+    Ok(true)
+}
+
+/// Decodes a received payload, tracking the keys it presses.
+fn decode_message(pressed: &mut KeyStateTracker, tag: u8, payload: &[u8]) -> io::Result<Message> {
+    let message = Message::decode_payload(tag, payload)?;
+    if let Message::Events(ref events) = message {
+        pressed.extend(events);
+    }
+    Ok(message)
+}
+
+/// Writes the events a received message should replay, if any.
+fn replay_message<F: AsRawFd>(pressed: &mut KeyStateTracker, message: Message, uinput: &UInputHandle<F>) -> io::Result<()> {
+    let events = match message {
+        Message::Events(events) => events,
+        Message::ReleaseAll => pressed.release_events(EventTime::default()),
+        Message::Keepalive => return Ok(()),
+        Message::Capabilities(..) => return Err(invalid_data("unexpected forwarded device capabilities")),
+    };
+    write_events(uinput, events)
+}
+
+fn write_events<F: AsRawFd>(uinput: &UInputHandle<F>, events: Vec<InputEvent>) -> io::Result<()> {
+    crate::stream::write_all(uinput, &events)
+}
+
+fn wait_readable<R: AsFd>(reader: &R, timeout: Duration) -> io::Result<bool> {
+    crate::evdev::wait_readable(reader.as_fd(), Some(timeout))
+}
+
+struct PayloadReader<'a>(&'a [u8]);
+
+impl<'a> PayloadReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_data("truncated forwarded capabilities"))
+        }
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(data)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.take(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+
+    fn bitmask<T: BitmaskTrait>(&mut self) -> io::Result<Bitmask<T>> {
+        let data = self.bytes()?;
+        let mut bitmask = Bitmask::<T>::default();
+        let len = data.len().min(bitmask.len());
+        bitmask[..len].copy_from_slice(&data[..len]);
+        Ok(bitmask)
+    }
+}
+
+fn decode_capabilities(payload: &[u8]) -> io::Result<DeviceCapabilities> {
+    let mut r = PayloadReader(payload);
+    let id = InputId {
+        bustype: r.u16()?,
+        vendor: r.u16()?,
+        product: r.u16()?,
+        version: r.u16()?,
+    };
+    let mut caps = DeviceCapabilities {
+        id,
+        ff_effects_max: r.u32()?,
+        name: r.bytes()?.into(),
+        properties: r.bitmask()?,
+        events: r.bitmask()?,
+        keys: r.bitmask()?,
+        relative: r.bitmask()?,
+        misc: r.bitmask()?,
+        switches: r.bitmask()?,
+        leds: r.bitmask()?,
+        sounds: r.bitmask()?,
+        force_feedback: r.bitmask()?,
+        absolute: Vec::new(),
+    };
+    for _ in 0..r.u16()? {
+        let axis = AbsoluteAxis::from_code(r.u16()?)?;
+        let mut values = [0i32; 6];
+        for v in &mut values {
+            *v = r.u32()? as i32;
+        }
+        let [value, minimum, maximum, fuzz, flat, resolution] = values;
+        caps.absolute.push(AbsoluteInfoSetup {
+            axis,
+            info: AbsoluteInfo { value, minimum, maximum, fuzz, flat, resolution },
+        });
+    }
+    Ok(caps)
+}
+
+/// The sending half of a forwarded device.
+pub struct Sender<W> {
+    writer: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Sender<W> {
+    /// Begins a stream by sending the handshake and the device's capabilities.
+    pub fn new(mut writer: W, caps: &DeviceCapabilities) -> io::Result<Self> {
+        let mut buffer = handshake().to_vec();
+        Message::Capabilities(caps.clone()).encode(&mut buffer)?;
+        writer.write_all(&buffer)?;
+        writer.flush()?;
+        buffer.clear();
+
+        Ok(Sender {
+            writer,
+            buffer,
+        })
+    }
+
+    /// Sends a single message.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] before writing anything if
+    /// the message is too large, see [`Message::encode`].
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.buffer.clear();
+        message.encode(&mut self.buffer)?;
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()
+    }
+
+    /// Sends a frame of events.
+    pub fn send_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.send(&Message::Events(events.into()))
+    }
+
+    /// Indicates that the sender is still alive.
+    pub fn keepalive(&mut self) -> io::Result<()> {
+        self.send(&Message::Keepalive)
+    }
+
+    /// Requests that the receiver release all pressed keys and returns the
+    /// underlying writer, ending the stream.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.send(&Message::ReleaseAll)
+            .map(|()| self.writer)
+    }
+
+    /// A reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+}
+
+/// The receiving half of a forwarded device.
+pub struct Receiver<R> {
+    reader: R,
+    capabilities: DeviceCapabilities,
+    pressed: KeyStateTracker,
+    buffer: Vec<u8>,
+    timeout: Option<(Duration, WaitReadable<R>)>,
+}
+
+/// Waits until a reader is readable, returning `false` on timeout.
+type WaitReadable<R> = fn(&R, Duration) -> io::Result<bool>;
+
+impl<R: AsFd> Receiver<R> {
+    /// Fails [`recv`](Self::recv) with [`io::ErrorKind::TimedOut`] if no
+    /// message arrives within `timeout`.
+    ///
+    /// The sender should call [`Sender::keepalive`] more often than this while
+    /// idle. The reader must not buffer data internally, since the timeout
+    /// waits on its file descriptor.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Receiver {
+            timeout: Some((timeout, wait_readable::<R>)),
+            ..self
+        }
+    }
+}
+
+impl<R: Read> Receiver<R> {
+    /// Accepts a stream by reading the handshake and the device's capabilities.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut handshake = [0u8; HANDSHAKE_LEN];
+        reader.read_exact(&mut handshake)?;
+        check_handshake(&handshake)?;
+
+        let mut receiver = Receiver {
+            reader,
+            capabilities: Default::default(),
+            pressed: Default::default(),
+            buffer: Vec::new(),
+            timeout: None,
+        };
+        match receiver.recv()? {
+            Some(Message::Capabilities(caps)) => receiver.capabilities = caps,
+            _ => return Err(invalid_data("expected forwarded device capabilities")),
+        }
+        Ok(receiver)
+    }
+
+    /// The capabilities of the forwarded device.
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    /// Receives the next message, or `None` if the stream has ended.
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        if let Some((timeout, wait)) = self.timeout {
+            if !wait(&self.reader, timeout)? {
+                return Err(timed_out())
+            }
+        }
+        let mut header = [0u8; HEADER_LEN];
+        if !read_header(&mut self.reader, &mut header)? {
+            return Ok(None)
+        }
+        let (tag, len) = Message::decode_header(&header)?;
+        self.buffer.resize(len, 0);
+        self.reader.read_exact(&mut self.buffer)?;
+        decode_message(&mut self.pressed, tag, &self.buffer).map(Some)
+    }
+
+    /// The events needed to release all keys the sender currently holds
+    /// down, followed by a `SYN_REPORT`.
+    ///
+    /// The keys are then considered released.
+    pub fn release_events(&mut self) -> Vec<InputEvent> {
+        self.pressed.release_events(EventTime::default())
+    }
+
+    /// Creates a virtual device matching the forwarded device and replays
+    /// all received events until the stream ends.
+    ///
+    /// All pressed keys are released when the stream ends, fails, times out,
+    /// or requests it via [`Message::ReleaseAll`].
+    pub fn run<F: AsRawFd>(&mut self, uinput: &UInputHandle<F>) -> io::Result<()> {
+        uinput.create_from_capabilities(&self.capabilities)?;
+        let res = self.replay(uinput);
+        let release = write_events(uinput, self.release_events());
+        res.and(release)
+    }
+
+    fn replay<F: AsRawFd>(&mut self, uinput: &UInputHandle<F>) -> io::Result<()> {
+        while let Some(message) = self.recv()? {
+            replay_message(&mut self.pressed, message, uinput)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio-1")]
+mod tokio_impl {
+    use super::*;
+    use tokio_1::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    /// The sending half of a forwarded device over an asynchronous stream.
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "tokio-1")))]
+    pub struct AsyncSender<W> {
+        writer: W,
+        buffer: Vec<u8>,
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncSender<W> {
+        /// Begins a stream by sending the handshake and the device's capabilities.
+        pub async fn new(mut writer: W, caps: &DeviceCapabilities) -> io::Result<Self> {
+            let mut buffer = handshake().to_vec();
+            Message::Capabilities(caps.clone()).encode(&mut buffer)?;
+            writer.write_all(&buffer).await?;
+            writer.flush().await?;
+            buffer.clear();
+
+            Ok(AsyncSender {
+                writer,
+                buffer,
+            })
+        }
+
+        /// Sends a single message.
+        ///
+        /// Fails with [`io::ErrorKind::InvalidInput`] before writing anything
+        /// if the message is too large, see [`Message::encode`].
+        pub async fn send(&mut self, message: &Message) -> io::Result<()> {
+            self.buffer.clear();
+            message.encode(&mut self.buffer)?;
+            self.writer.write_all(&self.buffer).await?;
+            self.writer.flush().await
+        }
+
+        /// Sends a frame of events.
+        pub async fn send_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            self.send(&Message::Events(events.into())).await
+        }
+
+        /// Indicates that the sender is still alive.
+        pub async fn keepalive(&mut self) -> io::Result<()> {
+            self.send(&Message::Keepalive).await
+        }
+
+        /// Requests that the receiver release all pressed keys and returns
+        /// the underlying writer, ending the stream.
+        pub async fn finish(mut self) -> io::Result<W> {
+            self.send(&Message::ReleaseAll).await
+                .map(|()| self.writer)
+        }
+    }
+
+    /// The receiving half of a forwarded device over an asynchronous stream.
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "tokio-1")))]
+    pub struct AsyncReceiver<R> {
+        reader: R,
+        capabilities: DeviceCapabilities,
+        pressed: KeyStateTracker,
+        buffer: Vec<u8>,
+        timeout: Option<Duration>,
+    }
+
+    async fn read_header<R: AsyncRead + Unpin>(reader: &mut R, header: &mut [u8; HEADER_LEN]) -> io::Result<bool> {
+        let mut len = 0;
+        while len < header.len() {
+            let read = reader.read(&mut header[len..]).await;
+            if !header_progress(&mut len, read)? {
+                return Ok(false)
+            }
+        }
+        Ok(true)
+    }
+
+    impl<R> AsyncReceiver<R> {
+        /// Fails [`recv`](Self::recv) with [`io::ErrorKind::TimedOut`] if no
+        /// message arrives within `timeout`.
+        ///
+        /// The sender should call [`AsyncSender::keepalive`] more often than
+        /// this while idle. This requires a tokio runtime with its timer
+        /// enabled.
+        pub fn with_timeout(self, timeout: Duration) -> Self {
+            AsyncReceiver {
+                timeout: Some(timeout),
+                ..self
+            }
+        }
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncReceiver<R> {
+        /// Accepts a stream by reading the handshake and the device's capabilities.
+        pub async fn new(mut reader: R) -> io::Result<Self> {
+            let mut handshake = [0u8; HANDSHAKE_LEN];
+            reader.read_exact(&mut handshake).await?;
+            check_handshake(&handshake)?;
+
+            let mut receiver = AsyncReceiver {
+                reader,
+                capabilities: Default::default(),
+                pressed: Default::default(),
+                buffer: Vec::new(),
+                timeout: None,
+            };
+            match receiver.recv().await? {
+                Some(Message::Capabilities(caps)) => receiver.capabilities = caps,
+                _ => return Err(invalid_data("expected forwarded device capabilities")),
+            }
+            Ok(receiver)
+        }
+
+        /// The capabilities of the forwarded device.
+        pub fn capabilities(&self) -> &DeviceCapabilities {
+            &self.capabilities
+        }
+
+        /// Receives the next message, or `None` if the stream has ended.
+        pub async fn recv(&mut self) -> io::Result<Option<Message>> {
+            let mut header = [0u8; HEADER_LEN];
+            let started = match self.timeout {
+                Some(timeout) => tokio_1::time::timeout(timeout, read_header(&mut self.reader, &mut header)).await
+                    .map_err(|_| timed_out())??,
+                None => read_header(&mut self.reader, &mut header).await?,
+            };
+            if !started {
+                return Ok(None)
+            }
+            let (tag, len) = Message::decode_header(&header)?;
+            self.buffer.resize(len, 0);
+            self.reader.read_exact(&mut self.buffer).await?;
+            decode_message(&mut self.pressed, tag, &self.buffer).map(Some)
+        }
+
+        /// The events needed to release all keys the sender currently holds
+        /// down, followed by a `SYN_REPORT`.
+        ///
+        /// The keys are then considered released.
+        pub fn release_events(&mut self) -> Vec<InputEvent> {
+            self.pressed.release_events(EventTime::default())
+        }
+
+        /// Creates a virtual device matching the forwarded device and replays
+        /// all received events until the stream ends.
+        ///
+        /// All pressed keys are released when the stream ends, fails, times
+        /// out, or requests it via [`Message::ReleaseAll`].
+        pub async fn run<F: AsRawFd>(&mut self, uinput: &UInputHandle<F>) -> io::Result<()> {
+            uinput.create_from_capabilities(&self.capabilities)?;
+            let res = self.replay(uinput).await;
+            let release = write_events(uinput, self.release_events());
+            res.and(release)
+        }
+
+        async fn replay<F: AsRawFd>(&mut self, uinput: &UInputHandle<F>) -> io::Result<()> {
+            while let Some(message) = self.recv().await? {
+                replay_message(&mut self.pressed, message, uinput)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "tokio-1")]
+pub use self::tokio_impl::{AsyncSender, AsyncReceiver};
+
+#[test]
+fn forward_socketpair() {
+    use std::os::unix::net::UnixStream;
+    use crate::{RelativeAxis, RelativeEvent, EventKind, Key, KeyEvent, KeyState, SynchronizeEvent};
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let mut caps = DeviceCapabilities::new(InputId { bustype: 3, vendor: 1, product: 2, version: 3 }, b"forwarded");
+    caps.insert_key(Key::A);
+    caps.insert_relative(RelativeAxis::X);
+    caps.insert_absolute(AbsoluteInfoSetup {
+        axis: AbsoluteAxis::Y,
+        info: AbsoluteInfo { minimum: -5, maximum: 5, .. Default::default() },
+    });
+
+    let time = EventTime::new(1, 2);
+    let frame: Vec<InputEvent> = vec![
+        KeyEvent::new(time, Key::A, KeyState::PRESSED).into(),
+        RelativeEvent::new(time, RelativeAxis::X, -3).into(),
+        SynchronizeEvent::report(time).into(),
+    ];
+
+    let sender = std::thread::spawn({
+        let frame = frame.clone();
+        move || {
+            let mut sender = Sender::new(tx, &caps)?;
+            sender.send_events(&frame)?;
+            sender.keepalive()?;
+            sender.finish().map(drop)
+        }
+    });
+
+    let mut receiver = Receiver::new(rx).unwrap();
+    let caps = receiver.capabilities();
+    assert_eq!(caps.name, b"forwarded");
+    assert!(caps.keys.get(Key::A) && caps.events.get(EventKind::Relative));
+    assert_eq!(caps.absolute_setup(AbsoluteAxis::Y).unwrap().info.minimum, -5);
+
+    match receiver.recv().unwrap() {
+        Some(Message::Events(events)) => assert_eq!(events, frame),
+        m => panic!("unexpected {:?}", m),
+    }
+    assert!(matches!(receiver.recv().unwrap(), Some(Message::Keepalive)));
+    assert!(matches!(receiver.recv().unwrap(), Some(Message::ReleaseAll)));
+    assert_eq!(receiver.release_events(), vec![
+        KeyEvent::new(EventTime::default(), Key::A, KeyState::RELEASED).into(),
+        SynchronizeEvent::report(EventTime::default()).into(),
+    ]);
+    assert!(receiver.recv().unwrap().is_none());
+    sender.join().unwrap().unwrap();
+}
+
+#[test]
+fn forward_limits() {
+    use crate::{Key, KeyEvent, KeyState};
+
+    let caps = DeviceCapabilities::new(InputId::default(), b"limits");
+    let mut sender = Sender::new(Vec::new(), &caps).unwrap();
+    let start = sender.get_ref().len();
+
+    let event = KeyEvent::new(EventTime::default(), Key::A, KeyState::PRESSED).into();
+    let events = vec![event; MAX_MESSAGE_LEN / CODEC.event_size() + 1];
+    assert_eq!(sender.send_events(&events).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    let caps = DeviceCapabilities::new(InputId::default(), &vec![b'a'; 0x10000]);
+    assert_eq!(sender.send(&Message::Capabilities(caps)).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(sender.get_ref().len(), start);
+
+    sender.send_events(&events[..MAX_MESSAGE_LEN / CODEC.event_size()]).unwrap();
+    sender.keepalive().unwrap();
+    let stream = sender.get_ref().clone();
+
+    let mut receiver = Receiver::new(&stream[..]).unwrap();
+    assert!(matches!(receiver.recv().unwrap(), Some(Message::Events(..))));
+    assert!(matches!(receiver.recv().unwrap(), Some(Message::Keepalive)));
+    assert!(receiver.recv().unwrap().is_none());
+
+    // a stream cut off within a header is not a clean end
+    let mut receiver = Receiver::new(&stream[..stream.len() - 2]).unwrap();
+    assert!(matches!(receiver.recv().unwrap(), Some(Message::Events(..))));
+    assert_eq!(receiver.recv().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn forward_timeout() {
+    use std::os::unix::net::UnixStream;
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let caps = DeviceCapabilities::new(InputId::default(), b"timeout");
+    let mut sender = Sender::new(tx, &caps).unwrap();
+    let mut receiver = Receiver::new(rx).unwrap().with_timeout(Duration::from_millis(10));
+    sender.keepalive().unwrap();
+    assert!(matches!(receiver.recv().unwrap(), Some(Message::Keepalive)));
+    assert_eq!(receiver.recv().unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+    #[cfg(feature = "tokio-1")]
+    {
+        let runtime = tokio_1::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let (tx, rx) = tokio_1::net::UnixStream::pair().unwrap();
+            let mut sender = AsyncSender::new(tx, &caps).await.unwrap();
+            let mut receiver = AsyncReceiver::new(rx).await.unwrap().with_timeout(Duration::from_millis(10));
+            sender.keepalive().await.unwrap();
+            assert!(matches!(receiver.recv().await.unwrap(), Some(Message::Keepalive)));
+            assert_eq!(receiver.recv().await.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        });
+    }
+}
@@ -0,0 +1,329 @@
+//! Virtual gamepads that mimic popular controllers.
+//!
+//! Games, Steam and SDL pick their button mappings based on the identity of a
+//! controller, so a virtual gamepad needs to match the `InputId`, buttons and
+//! axis ranges reported by the kernel driver of the real device.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+use crate::{
+    UInputHandle, UInputWriter, InputId, InputEvent, EventTime, Key, KeyEvent, KeyState,
+    AbsoluteAxis, AbsoluteEvent, AbsoluteInfo, AbsoluteInfoSetup,
+    DeviceCapabilities, sys,
+};
+
+/// A controller to mimic.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadKind {
+    /// A wired Xbox 360 controller, as reported by the `xpad` driver.
+    Xbox360,
+    /// An Xbox One controller, as reported by the `xpad` driver.
+    XboxOne,
+    /// A DualShock 4 controller, as reported by the `hid-playstation` driver.
+    DualShock4,
+    /// A DualSense controller, as reported by the `hid-playstation` driver.
+    DualSense,
+}
+
+const XBOX_STICK: AbsoluteInfo = AbsoluteInfo {
+    value: 0,
+    minimum: -32768,
+    maximum: 32767,
+    fuzz: 16,
+    flat: 128,
+    resolution: 0,
+};
+
+const PLAYSTATION_STICK: AbsoluteInfo = AbsoluteInfo {
+    value: 128,
+    minimum: 0,
+    maximum: 255,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+const TRIGGER: AbsoluteInfo = AbsoluteInfo {
+    value: 0,
+    minimum: 0,
+    maximum: 255,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+const HAT: AbsoluteInfo = AbsoluteInfo {
+    value: 0,
+    minimum: -1,
+    maximum: 1,
+    fuzz: 0,
+    flat: 0,
+    resolution: 0,
+};
+
+impl GamepadKind {
+    /// The identity of the controller.
+    pub const fn id(&self) -> InputId {
+        let (bustype, vendor, product, version) = match self {
+            GamepadKind::Xbox360 => (sys::BUS_USB, 0x045e, 0x028e, 0x0114),
+            GamepadKind::XboxOne => (sys::BUS_USB, 0x045e, 0x02ea, 0x0301),
+            GamepadKind::DualShock4 => (sys::BUS_USB, 0x054c, 0x09cc, 0x8111),
+            GamepadKind::DualSense => (sys::BUS_USB, 0x054c, 0x0ce6, 0x8111),
+        };
+        InputId { bustype, vendor, product, version }
+    }
+
+    /// The device name the kernel driver uses for the controller.
+    pub const fn name(&self) -> &'static [u8] {
+        match self {
+            GamepadKind::Xbox360 => b"Microsoft X-Box 360 pad",
+            GamepadKind::XboxOne => b"Microsoft X-Box One S pad",
+            GamepadKind::DualShock4 => b"Sony Interactive Entertainment Wireless Controller",
+            GamepadKind::DualSense => b"Sony Interactive Entertainment DualSense Wireless Controller",
+        }
+    }
+
+    const fn is_xbox(&self) -> bool {
+        matches!(self, GamepadKind::Xbox360 | GamepadKind::XboxOne)
+    }
+
+    /// The buttons of the controller, in the order they are reported.
+    ///
+    /// `xpad` reports the left face button as `BTN_X` and the top one as
+    /// `BTN_Y`, which share their codes with `BTN_NORTH` and `BTN_WEST`.
+    fn buttons(&self, state: &GamepadState) -> Vec<(Key, bool)> {
+        let (west, north) = match self.is_xbox() {
+            true => (Key::ButtonNorth, Key::ButtonWest),
+            false => (Key::ButtonWest, Key::ButtonNorth),
+        };
+        let mut buttons = vec![
+            (Key::ButtonSouth, state.south),
+            (Key::ButtonEast, state.east),
+            (west, state.west),
+            (north, state.north),
+            (Key::ButtonTL, state.left_shoulder),
+            (Key::ButtonTR, state.right_shoulder),
+        ];
+        if !self.is_xbox() {
+            buttons.push((Key::ButtonTL2, state.left_trigger > 0.0));
+            buttons.push((Key::ButtonTR2, state.right_trigger > 0.0));
+        }
+        buttons.extend_from_slice(&[
+            (Key::ButtonSelect, state.select),
+            (Key::ButtonStart, state.start),
+            (Key::ButtonMode, state.mode),
+            (Key::ButtonThumbl, state.left_thumb),
+            (Key::ButtonThumbr, state.right_thumb),
+        ]);
+        buttons
+    }
+
+    fn axes(&self) -> [AbsoluteInfoSetup; 8] {
+        let stick = match self.is_xbox() {
+            true => XBOX_STICK,
+            false => PLAYSTATION_STICK,
+        };
+        [
+            AbsoluteInfoSetup { axis: AbsoluteAxis::X, info: stick },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::Y, info: stick },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::Z, info: TRIGGER },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::RX, info: stick },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::RY, info: stick },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::RZ, info: TRIGGER },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::Hat0X, info: HAT },
+            AbsoluteInfoSetup { axis: AbsoluteAxis::Hat0Y, info: HAT },
+        ]
+    }
+
+    /// The capabilities of the controller.
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::new(self.id(), self.name());
+        for (key, _) in self.buttons(&Default::default()) {
+            caps.insert_key(key);
+        }
+        for setup in self.axes().iter() {
+            caps.insert_absolute(*setup);
+        }
+        caps
+    }
+
+    /// The raw axis values of a state.
+    fn axis_values(&self, state: &GamepadState) -> [(AbsoluteAxis, i32); 8] {
+        let axes = self.axes();
+        let stick = |i: usize, v: f32| (axes[i].axis, scale(&axes[i].info, (v + 1.0) / 2.0));
+        let trigger = |i: usize, v: f32| (axes[i].axis, scale(&axes[i].info, v));
+        [
+            stick(0, state.left_x),
+            stick(1, state.left_y),
+            trigger(2, state.left_trigger),
+            stick(3, state.right_x),
+            stick(4, state.right_y),
+            trigger(5, state.right_trigger),
+            (AbsoluteAxis::Hat0X, state.dpad_x.signum()),
+            (AbsoluteAxis::Hat0Y, state.dpad_y.signum()),
+        ]
+    }
+
+    /// Appends the events needed to move the controller from one state to
+    /// another, not including the `SYN_REPORT`.
+    pub fn events(&self, previous: &GamepadState, state: &GamepadState, time: EventTime, out: &mut Vec<InputEvent>) {
+        let buttons = self.buttons(previous).into_iter().zip(self.buttons(state));
+        for ((_, was), (key, pressed)) in buttons {
+            if was != pressed {
+                out.push(KeyEvent::new(time, key, KeyState::pressed(pressed)).into());
+            }
+        }
+        let (previous, state) = (self.axis_values(previous), self.axis_values(state));
+        for (&(_, was), &(axis, value)) in previous.iter().zip(state.iter()) {
+            if was != value {
+                out.push(AbsoluteEvent::new(time, axis, value).into());
+            }
+        }
+    }
+}
+
+/// Maps `0.0..=1.0` onto the range of an axis.
+fn scale(info: &AbsoluteInfo, value: f32) -> i32 {
+    let value = value.clamp(0.0, 1.0) as f64;
+    let range = info.maximum as f64 - info.minimum as f64;
+    (info.minimum as f64 + (value * range).round()) as i32
+}
+
+/// The state of every button and axis of a gamepad.
+///
+/// Face buttons are named by their position, so `south` is A on an Xbox
+/// controller and cross on a PlayStation controller. Axes follow the evdev
+/// convention of positive values pointing right and down.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct GamepadState {
+    /// The bottom face button.
+    pub south: bool,
+    /// The right face button.
+    pub east: bool,
+    /// The top face button.
+    pub north: bool,
+    /// The left face button.
+    pub west: bool,
+    /// The left shoulder button.
+    pub left_shoulder: bool,
+    /// The right shoulder button.
+    pub right_shoulder: bool,
+    /// The select, back, view or share button.
+    pub select: bool,
+    /// The start, menu or options button.
+    pub start: bool,
+    /// The guide or home button.
+    pub mode: bool,
+    /// Clicking the left stick.
+    pub left_thumb: bool,
+    /// Clicking the right stick.
+    pub right_thumb: bool,
+    /// The left stick's horizontal position, from `-1.0` to `1.0`.
+    pub left_x: f32,
+    /// The left stick's vertical position, from `-1.0` to `1.0`.
+    pub left_y: f32,
+    /// The right stick's horizontal position, from `-1.0` to `1.0`.
+    pub right_x: f32,
+    /// The right stick's vertical position, from `-1.0` to `1.0`.
+    pub right_y: f32,
+    /// The left trigger, from `0.0` to `1.0`.
+    pub left_trigger: f32,
+    /// The right trigger, from `0.0` to `1.0`.
+    pub right_trigger: f32,
+    /// The horizontal direction of the d-pad: `-1`, `0` or `1`.
+    pub dpad_x: i32,
+    /// The vertical direction of the d-pad: `-1`, `0` or `1`.
+    pub dpad_y: i32,
+}
+
+/// A uinput gamepad that mimics a popular controller.
+pub struct VirtualGamepad<F> {
+    writer: UInputWriter<F>,
+    kind: GamepadKind,
+    state: GamepadState,
+}
+
+impl<F> VirtualGamepad<F> {
+    /// The controller being mimicked.
+    pub const fn kind(&self) -> GamepadKind {
+        self.kind
+    }
+
+    /// The most recently written state.
+    pub const fn state(&self) -> &GamepadState {
+        &self.state
+    }
+
+    /// A reference to the underlying uinput handle.
+    pub const fn handle(&self) -> &UInputHandle<F> {
+        self.writer.handle()
+    }
+
+    /// A mutable reference to the writer used for each frame.
+    pub fn writer_mut(&mut self) -> &mut UInputWriter<F> {
+        &mut self.writer
+    }
+
+    /// Extracts the underlying uinput handle.
+    pub fn into_inner(self) -> UInputHandle<F> {
+        self.writer.into_inner()
+    }
+}
+
+impl<F: AsRawFd> VirtualGamepad<F> {
+    /// Creates a uinput device matching the controller.
+    pub fn create(handle: UInputHandle<F>, kind: GamepadKind) -> io::Result<Self> {
+        handle.create_from_capabilities(&kind.capabilities())?;
+        Ok(VirtualGamepad {
+            writer: UInputWriter::new(handle),
+            kind,
+            state: Default::default(),
+        })
+    }
+
+    /// Writes a single frame containing every change since the last update.
+    pub fn update(&mut self, state: &GamepadState) -> io::Result<()> {
+        let mut events = Vec::new();
+        self.kind.events(&self.state, state, EventTime::default(), &mut events);
+        if !events.is_empty() {
+            self.writer.write_frame(&events)?;
+        }
+        self.state = *state;
+        Ok(())
+    }
+
+    /// Releases every button and centers every axis.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.update(&Default::default())
+    }
+}
+
+#[test]
+fn gamepad_events() {
+    let kind = GamepadKind::DualShock4;
+    let caps = kind.capabilities();
+    assert!(caps.keys.get(Key::ButtonTL2));
+    assert_eq!(caps.absolute_setup(AbsoluteAxis::X).map(|abs| abs.info.maximum), Some(255));
+    assert!(!GamepadKind::Xbox360.capabilities().keys.get(Key::ButtonTL2));
+
+    let time = EventTime::default();
+    let mut events = Vec::new();
+    let state = GamepadState {
+        west: true,
+        left_x: 1.0,
+        right_trigger: 1.0,
+        dpad_y: -1,
+        .. Default::default()
+    };
+    GamepadKind::Xbox360.events(&Default::default(), &state, time, &mut events);
+    assert_eq!(events, [
+        KeyEvent::new(time, Key::ButtonNorth, KeyState::PRESSED).into(),
+        AbsoluteEvent::new(time, AbsoluteAxis::X, 32767).into(),
+        AbsoluteEvent::new(time, AbsoluteAxis::RZ, 255).into(),
+        AbsoluteEvent::new(time, AbsoluteAxis::Hat0Y, -1).into(),
+    ]);
+
+    events.clear();
+    kind.events(&state, &state, time, &mut events);
+    assert!(events.is_empty());
+}
@@ -0,0 +1,456 @@
+//! Mappings between USB HID usages and keys.
+//!
+//! The tables mirror those used by the kernel's `hid-input` driver, so a key
+//! translated from a HID usage matches what the kernel itself would report
+//! for the same device.
+
+use crate::{sys, EventTime, InputEvent, Key, KeyEvent, KeyState};
+
+/// The Generic Desktop usage page.
+pub const PAGE_GENERIC_DESKTOP: u16 = 0x01;
+/// The Keyboard/Keypad usage page.
+pub const PAGE_KEYBOARD: u16 = 0x07;
+/// The Button usage page.
+pub const PAGE_BUTTON: u16 = 0x09;
+/// The Consumer usage page.
+pub const PAGE_CONSUMER: u16 = 0x0c;
+
+/// A HID usage, made up of a usage page and an ID within that page.
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub struct Usage {
+    /// The usage page.
+    pub page: u16,
+    /// The usage ID.
+    pub id: u16,
+}
+
+impl Usage {
+    /// Creates a usage from its page and ID.
+    pub const fn new(page: u16, id: u16) -> Self {
+        Usage { page, id }
+    }
+
+    /// Splits an extended 32-bit usage into its page and ID.
+    pub const fn from_raw(usage: u32) -> Self {
+        Usage::new((usage >> 16) as u16, usage as u16)
+    }
+
+    /// The extended 32-bit representation of the usage.
+    pub const fn into_raw(self) -> u32 {
+        ((self.page as u32) << 16) | self.id as u32
+    }
+
+    /// The key the kernel reports for this usage.
+    ///
+    /// Buttons are mapped as they would be for a device without a specific
+    /// application, see [`button_key`] otherwise.
+    pub fn to_key(self) -> Option<Key> {
+        match self.page {
+            PAGE_GENERIC_DESKTOP => desktop_key(self.id),
+            PAGE_KEYBOARD => keyboard_key(self.id),
+            PAGE_BUTTON => button_key(ButtonApplication::Misc, self.id),
+            PAGE_CONSUMER => consumer_key(self.id),
+            _ => None,
+        }
+    }
+}
+
+const UNK: u16 = 0;
+
+/// The kernel's `hid_keyboard` table.
+const KEYBOARD: [u16; 256] = [
+      0,  0,  0,  0, 30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38,
+     50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45, 21, 44,  2,  3,
+      4,  5,  6,  7,  8,  9, 10, 11, 28,  1, 14, 15, 57, 12, 13, 26,
+     27, 43, 43, 39, 40, 41, 51, 52, 53, 58, 59, 60, 61, 62, 63, 64,
+     65, 66, 67, 68, 87, 88, 99, 70,119,110,102,104,111,107,109,106,
+    105,108,103, 69, 98, 55, 74, 78, 96, 79, 80, 81, 75, 76, 77, 71,
+     72, 73, 82, 83, 86,127,116,117,183,184,185,186,187,188,189,190,
+    191,192,193,194,134,138,130,132,128,129,131,137,133,135,136,113,
+    115,114,UNK,UNK,UNK,121,UNK, 89, 93,124, 92, 94, 95,UNK,UNK,UNK,
+    122,123, 90, 91, 85,UNK,UNK,UNK,UNK,UNK,UNK,UNK,111,UNK,UNK,UNK,
+    UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,
+    UNK,UNK,UNK,UNK,UNK,UNK,179,180,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,
+    UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,
+    UNK,UNK,UNK,UNK,UNK,UNK,UNK,UNK,111,UNK,UNK,UNK,UNK,UNK,UNK,UNK,
+     29, 42, 56,125, 97, 54,100,126,164,166,165,163,161,115,114,113,
+    150,158,159,128,136,177,178,176,142,152,173,140,UNK,UNK,UNK,UNK,
+];
+
+/// Consumer page usages.
+const CONSUMER: &[(u16, u16)] = &[
+    (0x030, sys::KEY_POWER as _),
+    (0x031, sys::KEY_RESTART as _),
+    (0x032, sys::KEY_SLEEP as _),
+    (0x034, sys::KEY_SLEEP as _),
+    (0x035, sys::KEY_KBDILLUMTOGGLE as _),
+    (0x040, sys::KEY_MENU as _),
+    (0x041, sys::KEY_SELECT as _),
+    (0x042, sys::KEY_UP as _),
+    (0x043, sys::KEY_DOWN as _),
+    (0x044, sys::KEY_LEFT as _),
+    (0x045, sys::KEY_RIGHT as _),
+    (0x060, sys::KEY_INFO as _),
+    (0x061, sys::KEY_SUBTITLE as _),
+    (0x065, sys::KEY_CAMERA as _),
+    (0x069, sys::KEY_RED as _),
+    (0x06a, sys::KEY_GREEN as _),
+    (0x06b, sys::KEY_BLUE as _),
+    (0x06c, sys::KEY_YELLOW as _),
+    (0x06f, sys::KEY_BRIGHTNESSUP as _),
+    (0x070, sys::KEY_BRIGHTNESSDOWN as _),
+    (0x072, sys::KEY_BRIGHTNESS_TOGGLE as _),
+    (0x073, sys::KEY_BRIGHTNESS_MIN as _),
+    (0x074, sys::KEY_BRIGHTNESS_MAX as _),
+    (0x075, sys::KEY_BRIGHTNESS_AUTO as _),
+    (0x079, sys::KEY_KBDILLUMUP as _),
+    (0x07a, sys::KEY_KBDILLUMDOWN as _),
+    (0x07c, sys::KEY_KBDILLUMTOGGLE as _),
+    (0x082, sys::KEY_VIDEO_NEXT as _),
+    (0x083, sys::KEY_LAST as _),
+    (0x084, sys::KEY_ENTER as _),
+    (0x088, sys::KEY_PC as _),
+    (0x089, sys::KEY_TV as _),
+    (0x08a, sys::KEY_WWW as _),
+    (0x08b, sys::KEY_DVD as _),
+    (0x08c, sys::KEY_PHONE as _),
+    (0x08d, sys::KEY_PROGRAM as _),
+    (0x08e, sys::KEY_VIDEOPHONE as _),
+    (0x08f, sys::KEY_GAMES as _),
+    (0x090, sys::KEY_MEMO as _),
+    (0x091, sys::KEY_CD as _),
+    (0x092, sys::KEY_VCR as _),
+    (0x093, sys::KEY_TUNER as _),
+    (0x094, sys::KEY_EXIT as _),
+    (0x095, sys::KEY_HELP as _),
+    (0x096, sys::KEY_TAPE as _),
+    (0x097, sys::KEY_TV2 as _),
+    (0x098, sys::KEY_SAT as _),
+    (0x09a, sys::KEY_PVR as _),
+    (0x09c, sys::KEY_CHANNELUP as _),
+    (0x09d, sys::KEY_CHANNELDOWN as _),
+    (0x0a0, sys::KEY_VCR2 as _),
+    (0x0b0, sys::KEY_PLAY as _),
+    (0x0b1, sys::KEY_PAUSE as _),
+    (0x0b2, sys::KEY_RECORD as _),
+    (0x0b3, sys::KEY_FASTFORWARD as _),
+    (0x0b4, sys::KEY_REWIND as _),
+    (0x0b5, sys::KEY_NEXTSONG as _),
+    (0x0b6, sys::KEY_PREVIOUSSONG as _),
+    (0x0b7, sys::KEY_STOPCD as _),
+    (0x0b8, sys::KEY_EJECTCD as _),
+    (0x0b9, sys::KEY_SHUFFLE as _),
+    (0x0bc, sys::KEY_MEDIA_REPEAT as _),
+    (0x0bf, sys::KEY_SLOW as _),
+    (0x0cd, sys::KEY_PLAYPAUSE as _),
+    (0x0cf, sys::KEY_VOICECOMMAND as _),
+    (0x0d8, sys::KEY_DICTATE as _),
+    (0x0d9, sys::KEY_EMOJI_PICKER as _),
+    (0x0e2, sys::KEY_MUTE as _),
+    (0x0e5, sys::KEY_BASSBOOST as _),
+    (0x0e9, sys::KEY_VOLUMEUP as _),
+    (0x0ea, sys::KEY_VOLUMEDOWN as _),
+    (0x0f5, sys::KEY_SLOW as _),
+    (0x181, sys::KEY_BUTTONCONFIG as _),
+    (0x182, sys::KEY_BOOKMARKS as _),
+    (0x183, sys::KEY_CONFIG as _),
+    (0x184, sys::KEY_WORDPROCESSOR as _),
+    (0x185, sys::KEY_EDITOR as _),
+    (0x186, sys::KEY_SPREADSHEET as _),
+    (0x187, sys::KEY_GRAPHICSEDITOR as _),
+    (0x188, sys::KEY_PRESENTATION as _),
+    (0x189, sys::KEY_DATABASE as _),
+    (0x18a, sys::KEY_MAIL as _),
+    (0x18b, sys::KEY_NEWS as _),
+    (0x18c, sys::KEY_VOICEMAIL as _),
+    (0x18d, sys::KEY_ADDRESSBOOK as _),
+    (0x18e, sys::KEY_CALENDAR as _),
+    (0x18f, sys::KEY_TASKMANAGER as _),
+    (0x190, sys::KEY_JOURNAL as _),
+    (0x191, sys::KEY_FINANCE as _),
+    (0x192, sys::KEY_CALC as _),
+    (0x193, sys::KEY_PLAYER as _),
+    (0x194, sys::KEY_FILE as _),
+    (0x196, sys::KEY_WWW as _),
+    (0x199, sys::KEY_CHAT as _),
+    (0x19c, sys::KEY_LOGOFF as _),
+    (0x19e, sys::KEY_COFFEE as _),
+    (0x19f, sys::KEY_CONTROLPANEL as _),
+    (0x1a2, sys::KEY_APPSELECT as _),
+    (0x1a3, sys::KEY_NEXT as _),
+    (0x1a4, sys::KEY_PREVIOUS as _),
+    (0x1a6, sys::KEY_HELP as _),
+    (0x1a7, sys::KEY_DOCUMENTS as _),
+    (0x1ab, sys::KEY_SPELLCHECK as _),
+    (0x1ae, sys::KEY_KEYBOARD as _),
+    (0x1b1, sys::KEY_SCREENSAVER as _),
+    (0x1b4, sys::KEY_FILE as _),
+    (0x1b6, sys::KEY_IMAGES as _),
+    (0x1b7, sys::KEY_AUDIO as _),
+    (0x1b8, sys::KEY_VIDEO as _),
+    (0x1bc, sys::KEY_MESSENGER as _),
+    (0x1bd, sys::KEY_INFO as _),
+    (0x1cb, sys::KEY_ASSISTANT as _),
+    (0x201, sys::KEY_NEW as _),
+    (0x202, sys::KEY_OPEN as _),
+    (0x203, sys::KEY_CLOSE as _),
+    (0x204, sys::KEY_EXIT as _),
+    (0x207, sys::KEY_SAVE as _),
+    (0x208, sys::KEY_PRINT as _),
+    (0x209, sys::KEY_PROPS as _),
+    (0x21a, sys::KEY_UNDO as _),
+    (0x21b, sys::KEY_COPY as _),
+    (0x21c, sys::KEY_CUT as _),
+    (0x21d, sys::KEY_PASTE as _),
+    (0x21f, sys::KEY_FIND as _),
+    (0x221, sys::KEY_SEARCH as _),
+    (0x222, sys::KEY_GOTO as _),
+    (0x223, sys::KEY_HOMEPAGE as _),
+    (0x224, sys::KEY_BACK as _),
+    (0x225, sys::KEY_FORWARD as _),
+    (0x226, sys::KEY_STOP as _),
+    (0x227, sys::KEY_REFRESH as _),
+    (0x22a, sys::KEY_BOOKMARKS as _),
+    (0x22d, sys::KEY_ZOOMIN as _),
+    (0x22e, sys::KEY_ZOOMOUT as _),
+    (0x22f, sys::KEY_ZOOMRESET as _),
+    (0x232, sys::KEY_FULL_SCREEN as _),
+    (0x233, sys::KEY_SCROLLUP as _),
+    (0x234, sys::KEY_SCROLLDOWN as _),
+    (0x23d, sys::KEY_EDIT as _),
+    (0x25f, sys::KEY_CANCEL as _),
+    (0x269, sys::KEY_INSERT as _),
+    (0x26a, sys::KEY_DELETE as _),
+    (0x279, sys::KEY_REDO as _),
+    (0x289, sys::KEY_REPLY as _),
+    (0x28b, sys::KEY_FORWARDMAIL as _),
+    (0x28c, sys::KEY_SEND as _),
+    (0x29d, sys::KEY_KBD_LAYOUT_NEXT as _),
+    (0x2a2, sys::KEY_ALL_APPLICATIONS as _),
+];
+
+/// Generic Desktop page usages.
+const DESKTOP: &[(u16, u16)] = &[
+    (0x081, sys::KEY_POWER as _),
+    (0x082, sys::KEY_SLEEP as _),
+    (0x083, sys::KEY_WAKEUP as _),
+    (0x084, sys::KEY_CONTEXT_MENU as _),
+    (0x085, sys::KEY_MENU as _),
+    (0x086, sys::KEY_PROG1 as _),
+    (0x087, sys::KEY_HELP as _),
+    (0x088, sys::KEY_EXIT as _),
+    (0x089, sys::KEY_SELECT as _),
+    (0x08a, sys::KEY_RIGHT as _),
+    (0x08b, sys::KEY_LEFT as _),
+    (0x08c, sys::KEY_UP as _),
+    (0x08d, sys::KEY_DOWN as _),
+    (0x08e, sys::KEY_POWER2 as _),
+    (0x08f, sys::KEY_RESTART as _),
+    (0x090, sys::BTN_DPAD_UP as _),
+    (0x091, sys::BTN_DPAD_DOWN as _),
+    (0x092, sys::BTN_DPAD_RIGHT as _),
+    (0x093, sys::BTN_DPAD_LEFT as _),
+    (0x0c6, sys::KEY_RFKILL as _),
+];
+
+fn key(code: u16) -> Option<Key> {
+    match code {
+        UNK => None,
+        code => Key::from_code(code).ok(),
+    }
+}
+
+fn lookup(table: &[(u16, u16)], usage: u16) -> Option<Key> {
+    table.iter()
+        .find(|&&(u, _)| u == usage)
+        .and_then(|&(_, code)| key(code))
+}
+
+fn reverse(table: &[(u16, u16)], key: Key) -> Option<u16> {
+    table.iter()
+        .find(|&&(_, code)| code == key.code())
+        .map(|&(usage, _)| usage)
+}
+
+/// The key reported for a Keyboard/Keypad page usage.
+pub fn keyboard_key(usage: u16) -> Option<Key> {
+    KEYBOARD.get(usage as usize).and_then(|&code| key(code))
+}
+
+/// The Keyboard/Keypad page usage of a key.
+///
+/// Where several usages map to the same key, the lowest is returned.
+pub fn keyboard_usage(key: Key) -> Option<u16> {
+    KEYBOARD.iter()
+        .position(|&code| code != UNK && code == key.code())
+        .map(|usage| usage as u16)
+}
+
+/// The key reported for a Consumer page usage.
+pub fn consumer_key(usage: u16) -> Option<Key> {
+    lookup(CONSUMER, usage)
+}
+
+/// The Consumer page usage of a key.
+pub fn consumer_usage(key: Key) -> Option<u16> {
+    reverse(CONSUMER, key)
+}
+
+/// The key reported for a Generic Desktop page usage.
+pub fn desktop_key(usage: u16) -> Option<Key> {
+    lookup(DESKTOP, usage)
+}
+
+/// The Generic Desktop page usage of a key.
+pub fn desktop_usage(key: Key) -> Option<u16> {
+    reverse(DESKTOP, key)
+}
+
+/// The application of a HID collection, which determines how its buttons
+/// are reported.
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum ButtonApplication {
+    /// Buttons starting at `BTN_MISC`.
+    #[default]
+    Misc,
+    /// A mouse or pointer, with buttons starting at `BTN_MOUSE`.
+    Mouse,
+    /// A joystick, with buttons starting at `BTN_JOYSTICK`.
+    Joystick,
+    /// A gamepad, with buttons starting at `BTN_GAMEPAD`.
+    Gamepad,
+}
+
+impl ButtonApplication {
+    const fn base(&self) -> u16 {
+        (match self {
+            ButtonApplication::Misc => sys::BTN_MISC,
+            ButtonApplication::Mouse => sys::BTN_MOUSE,
+            ButtonApplication::Joystick => sys::BTN_JOYSTICK,
+            ButtonApplication::Gamepad => sys::BTN_GAMEPAD,
+        }) as u16
+    }
+}
+
+/// The key reported for a Button page usage.
+///
+/// Button usages start at 1.
+pub fn button_key(application: ButtonApplication, usage: u16) -> Option<Key> {
+    let index = usage.checked_sub(1)?;
+    let code = match application {
+        ButtonApplication::Joystick | ButtonApplication::Gamepad if index > 0xf =>
+            (sys::BTN_TRIGGER_HAPPY as u16).checked_add(index - 0x10)?,
+        application => application.base().checked_add(index)?,
+    };
+    key(code)
+}
+
+/// The Button page usage of a key, along with the application it is
+/// reported for.
+pub fn button_usage(key: Key) -> Option<(ButtonApplication, u16)> {
+    let code = key.code();
+    let trigger_happy = sys::BTN_TRIGGER_HAPPY as u16;
+    let (application, index) = if code >= trigger_happy {
+        (ButtonApplication::Gamepad, code - trigger_happy + 0x10)
+    } else {
+        let application = [
+            ButtonApplication::Gamepad, ButtonApplication::Joystick,
+            ButtonApplication::Mouse, ButtonApplication::Misc,
+        ].iter().cloned().find(|application| code >= application.base() && code < application.base() + 0x10)?;
+        (application, code - application.base())
+    };
+    Some((application, index + 1))
+}
+
+impl Key {
+    /// The key the kernel reports for a HID usage, see [`Usage::to_key`].
+    pub fn from_hid_usage(usage: Usage) -> Option<Self> {
+        usage.to_key()
+    }
+
+    /// The HID usage that produces this key.
+    pub fn hid_usage(&self) -> Option<Usage> {
+        keyboard_usage(*self).map(|id| Usage::new(PAGE_KEYBOARD, id))
+            .or_else(|| consumer_usage(*self).map(|id| Usage::new(PAGE_CONSUMER, id)))
+            .or_else(|| desktop_usage(*self).map(|id| Usage::new(PAGE_GENERIC_DESKTOP, id)))
+            .or_else(|| button_usage(*self).map(|(_, id)| Usage::new(PAGE_BUTTON, id)))
+    }
+}
+
+/// A boot protocol keyboard input report.
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub struct KeyboardReport {
+    /// The state of the eight modifier keys, one bit per usage starting at
+    /// `0xe0`.
+    pub modifiers: u8,
+    /// The Keyboard/Keypad page usages of the keys that are held down.
+    pub keys: [u8; 6],
+}
+
+impl KeyboardReport {
+    /// The size of the report.
+    pub const SIZE: usize = 8;
+
+    /// Parses a report.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let mut keys = [0u8; 6];
+        keys.copy_from_slice(&bytes[2..]);
+        KeyboardReport {
+            modifiers: bytes[0],
+            keys,
+        }
+    }
+
+    /// Serializes the report.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0] = self.modifiers;
+        bytes[2..].copy_from_slice(&self.keys);
+        bytes
+    }
+
+    /// Whether the keyboard reports that too many keys are held.
+    pub fn is_rollover(&self) -> bool {
+        self.keys.iter().all(|&usage| usage == 1)
+    }
+
+    /// The keys held down, including modifiers.
+    pub fn keys(&self) -> impl Iterator<Item=Key> + '_ {
+        (0..8).filter(move |bit| self.modifiers & (1 << bit) != 0)
+            .map(|bit| 0xe0 + bit)
+            .chain(self.keys.iter().map(|&usage| usage as u16))
+            .filter_map(keyboard_key)
+    }
+
+    /// The key events that lead from a previous report to this one.
+    ///
+    /// Reports that indicate a rollover error produce no events.
+    pub fn events(&self, previous: &Self, time: EventTime) -> Vec<InputEvent> {
+        if self.is_rollover() {
+            return Vec::new()
+        }
+        let released = previous.keys().filter(|key| !self.keys().any(|k| k == *key))
+            .map(|key| KeyEvent::new(time, key, KeyState::RELEASED).into());
+        let pressed = self.keys().filter(|key| !previous.keys().any(|k| k == *key))
+            .map(|key| KeyEvent::new(time, key, KeyState::PRESSED).into());
+        released.chain(pressed).collect()
+    }
+}
+
+#[test]
+fn hid_usages() {
+    assert_eq!(keyboard_key(0x04), Some(Key::A));
+    assert_eq!(keyboard_key(0xe4), Some(Key::RightCtrl));
+    assert_eq!(Key::Backslash.hid_usage(), Some(Usage::new(PAGE_KEYBOARD, 0x31)));
+    assert_eq!(Usage::from_raw(0x000c_00cd).to_key().map(|key| key.code()), Some(sys::KEY_PLAYPAUSE as u16));
+    assert_eq!(button_key(ButtonApplication::Mouse, 1), Some(Key::ButtonLeft));
+    assert_eq!(button_usage(Key::ButtonLeft), Some((ButtonApplication::Mouse, 1)));
+
+    let previous = KeyboardReport { modifiers: 0x02, keys: [0x04, 0, 0, 0, 0, 0] };
+    let report = KeyboardReport { modifiers: 0x00, keys: [0x04, 0x05, 0, 0, 0, 0] };
+    let time = EventTime::default();
+    assert_eq!(report.events(&previous, time), [
+        InputEvent::from(KeyEvent::new(time, Key::LeftShift, KeyState::RELEASED)),
+        KeyEvent::new(time, Key::B, KeyState::PRESSED).into(),
+    ]);
+}
@@ -0,0 +1,184 @@
+//! A virtual keyboard for typing keys and text.
+
+use std::{io, thread};
+use std::time::Duration;
+use std::os::unix::io::AsRawFd;
+use crate::{
+    UInputHandle, UInputWriter, InputId, InputEvent, EventKind, EventTime, Key, KeyEvent,
+    KeyState, KeyboardLayout, KeyStroke,
+};
+
+/// Types keys and text into a uinput device.
+pub struct VirtualKeyboard<F> {
+    writer: UInputWriter<F>,
+    delay: Duration,
+}
+
+impl<F> VirtualKeyboard<F> {
+    /// Wraps a uinput device that has already been created with the
+    /// necessary keys enabled.
+    pub const fn new(handle: UInputHandle<F>) -> Self {
+        VirtualKeyboard {
+            writer: UInputWriter::new(handle),
+            delay: Duration::from_millis(0),
+        }
+    }
+
+    /// Sets the delay between each frame of events written.
+    ///
+    /// Some applications drop keys when they are typed too quickly.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        VirtualKeyboard { delay, ..self }
+    }
+
+    /// The delay between each frame of events written.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// A reference to the underlying uinput handle.
+    pub const fn handle(&self) -> &UInputHandle<F> {
+        self.writer.handle()
+    }
+
+    /// A mutable reference to the writer used for each frame.
+    pub fn writer_mut(&mut self) -> &mut UInputWriter<F> {
+        &mut self.writer
+    }
+
+    /// Extracts the underlying uinput handle.
+    pub fn into_inner(self) -> UInputHandle<F> {
+        self.writer.into_inner()
+    }
+}
+
+impl<F: AsRawFd> VirtualKeyboard<F> {
+    /// Creates a uinput device supporting every keyboard key.
+    pub fn create(handle: UInputHandle<F>, id: &InputId, name: &[u8]) -> io::Result<Self> {
+        handle.set_evbit(EventKind::Key)?;
+        for key in Key::iter().filter(|key| key.is_key() && *key != Key::Reserved && key.code() < 0x100) {
+            handle.set_keybit(key)?;
+        }
+        handle.create(id, name, 0, &[])?;
+        Ok(Self::new(handle))
+    }
+
+    fn frame(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        if let Err(err) = self.writer.write_frame(events) {
+            // don't let a failed frame leak into the next one
+            self.writer.clear();
+            return Err(err)
+        }
+        if self.delay > Duration::from_millis(0) {
+            thread::sleep(self.delay);
+        }
+        Ok(())
+    }
+
+    fn key_frame<I: IntoIterator<Item=Key>>(&mut self, keys: I, state: KeyState) -> io::Result<()> {
+        let events: Vec<InputEvent> = keys.into_iter()
+            .map(|key| KeyEvent::new(EventTime::default(), key, state).into())
+            .collect();
+        match events.is_empty() {
+            true => Ok(()),
+            false => self.frame(&events),
+        }
+    }
+
+    /// Presses a key.
+    pub fn press(&mut self, key: Key) -> io::Result<()> {
+        self.key_frame(Some(key), KeyState::PRESSED)
+    }
+
+    /// Releases a key.
+    pub fn release(&mut self, key: Key) -> io::Result<()> {
+        self.key_frame(Some(key), KeyState::RELEASED)
+    }
+
+    /// Presses and releases a key.
+    pub fn tap(&mut self, key: Key) -> io::Result<()> {
+        self.press(key)?;
+        self.release(key)
+    }
+
+    /// Presses and releases a key while holding its modifiers.
+    ///
+    /// The modifiers are released even if tapping the key fails, in which
+    /// case the first error is returned.
+    pub fn stroke(&mut self, stroke: KeyStroke) -> io::Result<()> {
+        self.key_frame(stroke.modifiers.keys(), KeyState::PRESSED)?;
+        let res = self.tap(stroke.key);
+        let mut release: Vec<_> = stroke.modifiers.keys().collect();
+        release.reverse();
+        if res.is_err() {
+            release.insert(0, stroke.key);
+        }
+        let release = self.key_frame(release, KeyState::RELEASED);
+        res.and(release)
+    }
+
+    /// Types a string using the given layout.
+    ///
+    /// Fails without typing anything if the layout cannot produce every
+    /// character of the string.
+    pub fn type_str(&mut self, text: &str, layout: &KeyboardLayout) -> io::Result<()> {
+        let strokes = text.chars()
+            .map(|c| layout.keystrokes(c).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no keystroke for {:?} in layout", c),
+            )))
+            .collect::<io::Result<Vec<_>>>()?;
+        for &stroke in strokes.iter().flat_map(|strokes| strokes.iter()) {
+            self.stroke(stroke)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn keyboard_type_str() {
+    use std::mem::MaybeUninit;
+    use std::os::unix::net::UnixStream;
+    use crate::{EvdevHandle, SynchronizeEvent};
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    rx.set_nonblocking(true).unwrap();
+    let rx = EvdevHandle::new(rx);
+    let mut keyboard = VirtualKeyboard::new(UInputHandle::new(tx));
+    let mut read = || {
+        let mut events = [MaybeUninit::uninit(); 32];
+        match rx.read_input_events(&mut events) {
+            Ok(events) => events.iter().map(|e| InputEvent { time: EventTime::default(), ..*e }).collect(),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Vec::new(),
+            Err(err) => panic!("{}", err),
+        }
+    };
+    let time = EventTime::default();
+    let key = |key, state| InputEvent::from(KeyEvent::new(time, key, state));
+    let syn = InputEvent::from(SynchronizeEvent::report(time));
+
+    keyboard.type_str("A", &KeyboardLayout::us()).unwrap();
+    assert_eq!(read(), [
+        key(Key::LeftShift, KeyState::PRESSED), syn,
+        key(Key::A, KeyState::PRESSED), syn,
+        key(Key::A, KeyState::RELEASED), syn,
+        key(Key::LeftShift, KeyState::RELEASED), syn,
+    ]);
+
+    keyboard.type_str("é", &KeyboardLayout::de()).unwrap();
+    assert_eq!(read(), [
+        key(Key::Equal, KeyState::PRESSED), syn,
+        key(Key::Equal, KeyState::RELEASED), syn,
+        key(Key::E, KeyState::PRESSED), syn,
+        key(Key::E, KeyState::RELEASED), syn,
+    ]);
+
+    let err = keyboard.type_str("aé", &KeyboardLayout::us()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(read().is_empty());
+
+    drop(read);
+    drop(rx);
+    assert!(keyboard.stroke(KeyStroke::new(Key::A, crate::Modifiers::LEFT_SHIFT)).is_err());
+    assert!(keyboard.writer_mut().pending().is_empty());
+}
@@ -0,0 +1,549 @@
+//! Keyboard layouts relating keys to the characters they produce.
+
+use std::collections::HashMap;
+use crate::{InputEvent, Key, KeyEvent, KeyState, KeyStateTracker, Modifiers, GenericEvent};
+
+/// A key along with the modifiers held while pressing it.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub struct KeyStroke {
+    /// The key that is pressed.
+    pub key: Key,
+    /// The modifiers that are held.
+    pub modifiers: Modifiers,
+}
+
+impl KeyStroke {
+    /// A keystroke with the given modifiers.
+    pub const fn new(key: Key, modifiers: Modifiers) -> Self {
+        KeyStroke { key, modifiers }
+    }
+}
+
+impl From<Key> for KeyStroke {
+    fn from(key: Key) -> Self {
+        KeyStroke::new(key, Modifiers::empty())
+    }
+}
+
+/// The modifiers for each shift level of a layout table.
+const LEVELS: [Modifiers; 4] = [
+    Modifiers::empty(),
+    Modifiers::LEFT_SHIFT,
+    Modifiers::ALTGR,
+    Modifiers::LEFT_SHIFT.union(Modifiers::ALTGR),
+];
+
+/// What a keystroke produces.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum Symbol {
+    /// A character.
+    Char(char),
+    /// A dead key that modifies the next character typed.
+    ///
+    /// The accent is identified by its combining diacritical mark, such as
+    /// `'\u{302}'` for a circumflex.
+    Dead(char),
+}
+
+impl From<char> for Symbol {
+    /// Combining diacritical marks become dead keys.
+    fn from(c: char) -> Self {
+        match c {
+            '\u{300}'..='\u{36f}' => Symbol::Dead(c),
+            c => Symbol::Char(c),
+        }
+    }
+}
+
+/// Dead key accents, the character they produce on their own, and the
+/// characters they combine with.
+const DEAD_KEYS: &[(char, char, &str, &str)] = &[
+    ('\u{300}', '`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ('\u{301}', '´', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    ('\u{302}', '^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ('\u{303}', '~', "anoANO", "ãñõÃÑÕ"),
+    ('\u{308}', '¨', "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+];
+
+/// Applies a dead key accent to a character.
+///
+/// A space or the accent itself produces the accent on its own.
+pub fn compose(accent: char, c: char) -> Option<char> {
+    let &(_, spacing, bases, composed) = DEAD_KEYS.iter()
+        .find(|&&(a, ..)| a == accent)?;
+    match c {
+        ' ' => Some(spacing),
+        c if c == accent => Some(spacing),
+        c => bases.chars().position(|b| b == c)
+            .and_then(|i| composed.chars().nth(i)),
+    }
+}
+
+/// Maps between characters and the keystrokes that produce them.
+#[derive(Clone, Debug, Default)]
+pub struct KeyboardLayout {
+    symbols: HashMap<KeyStroke, Symbol>,
+    strokes: HashMap<char, Vec<KeyStroke>>,
+    dead: Vec<(KeyStroke, char)>,
+}
+
+impl KeyboardLayout {
+    /// An empty layout.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Builds a layout from a table of keys and the characters they produce.
+    ///
+    /// Each entry lists the characters of a key for every shift level in
+    /// order: unmodified, shift, AltGr, and shift+AltGr. Trailing levels may
+    /// be omitted, and `'\0'` marks a level that produces nothing.
+    /// Combining diacritical marks describe dead keys.
+    pub fn from_table(table: &[(Key, &str)]) -> Self {
+        let mut layout = Self::new();
+        layout.extend_from_table(table);
+        layout
+    }
+
+    /// Adds the entries of a table to the layout, see
+    /// [`from_table`](Self::from_table).
+    pub fn extend_from_table(&mut self, table: &[(Key, &str)]) {
+        for &(key, chars) in table {
+            for (c, &modifiers) in chars.chars().zip(&LEVELS) {
+                if c != '\0' {
+                    self.insert(KeyStroke::new(key, modifiers), c);
+                }
+            }
+        }
+    }
+
+    fn with_common(table: &[(Key, &str)]) -> Self {
+        let mut layout = Self::from_table(table);
+        layout.extend_from_table(COMMON);
+        layout
+    }
+
+    /// The US QWERTY layout.
+    pub fn us() -> Self {
+        Self::with_common(US)
+    }
+
+    /// The UK QWERTY layout.
+    pub fn uk() -> Self {
+        Self::with_common(UK)
+    }
+
+    /// The German QWERTZ layout, with dead keys.
+    pub fn de() -> Self {
+        Self::with_common(DE)
+    }
+
+    /// The French AZERTY layout, with dead keys.
+    pub fn fr() -> Self {
+        Self::with_common(FR)
+    }
+
+    /// Adds a keystroke to the layout.
+    ///
+    /// When several keystrokes produce the same character, the first one
+    /// added is preferred when typing, and keystrokes that produce it
+    /// directly are preferred over dead key sequences.
+    pub fn insert<S: Into<Symbol>>(&mut self, stroke: KeyStroke, symbol: S) {
+        let symbol = symbol.into();
+        self.symbols.insert(stroke, symbol);
+        match symbol {
+            Symbol::Char(c) => {
+                let direct = self.strokes.get(&c).map(|strokes| strokes.len() == 1).unwrap_or(false);
+                if !direct {
+                    self.strokes.insert(c, vec![stroke]);
+                }
+                for &(dead, accent) in &self.dead {
+                    if let Some(composed) = compose(accent, c) {
+                        self.strokes.entry(composed).or_insert_with(|| vec![dead, stroke]);
+                    }
+                }
+            },
+            Symbol::Dead(accent) => {
+                self.dead.push((stroke, accent));
+                let composed: Vec<_> = self.strokes.iter()
+                    .filter(|(_, strokes)| strokes.len() == 1)
+                    .filter_map(|(&c, strokes)| compose(accent, c).map(|composed| (composed, strokes[0])))
+                    .collect();
+                for (composed, base) in composed {
+                    self.strokes.entry(composed).or_insert_with(|| vec![stroke, base]);
+                }
+            },
+        }
+    }
+
+    /// What a keystroke produces.
+    pub fn symbol(&self, stroke: KeyStroke) -> Option<Symbol> {
+        self.symbols.get(&stroke).cloned()
+    }
+
+    /// The character produced directly by a keystroke.
+    pub fn char(&self, stroke: KeyStroke) -> Option<char> {
+        match self.symbol(stroke) {
+            Some(Symbol::Char(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// The keystrokes needed to type a character, in order.
+    pub fn keystrokes(&self, c: char) -> Option<&[KeyStroke]> {
+        self.strokes.get(&c).map(|strokes| &strokes[..])
+    }
+}
+
+/// Decodes the text typed by a stream of key events.
+///
+/// Modifiers are tracked from the events themselves, and caps lock from the
+/// device's LED events. Keys pressed while control, alt or meta are held are
+/// treated as shortcuts and produce no text.
+#[derive(Clone, Debug)]
+pub struct TextDecoder<'a> {
+    layout: &'a KeyboardLayout,
+    keys: KeyStateTracker,
+    dead: Option<char>,
+}
+
+impl<'a> TextDecoder<'a> {
+    /// Creates a decoder for the given layout.
+    pub fn new(layout: &'a KeyboardLayout) -> Self {
+        TextDecoder {
+            layout,
+            keys: KeyStateTracker::new(),
+            dead: None,
+        }
+    }
+
+    /// The state of the keys seen so far.
+    pub fn keys(&self) -> &KeyStateTracker {
+        &self.keys
+    }
+
+    /// Processes an event, appending any text it produces to `out`.
+    pub fn process(&mut self, event: &InputEvent, out: &mut String) {
+        self.keys.update(event);
+        let event = match KeyEvent::from_ref(event) {
+            Ok(event) if event.value != KeyState::RELEASED => event,
+            _ => return,
+        };
+        let held = self.keys.modifiers();
+        if held.intersects(Modifiers::CTRL | Modifiers::LEFT_ALT | Modifiers::META) {
+            return
+        }
+        let mut modifiers = held & Modifiers::ALTGR;
+        if held.shift() {
+            modifiers |= Modifiers::LEFT_SHIFT;
+        }
+        let symbol = match self.layout.symbol(KeyStroke::new(event.key, modifiers)) {
+            Some(Symbol::Char(c)) if self.keys.caps_lock() => Symbol::Char(match modifiers.shift() {
+                true => single_char(c.to_lowercase()),
+                false => single_char(c.to_uppercase()),
+            }.unwrap_or(c)),
+            Some(symbol) => symbol,
+            None => return,
+        };
+        match (self.dead.take(), symbol) {
+            (None, Symbol::Char(c)) => out.push(c),
+            (None, Symbol::Dead(accent)) => self.dead = Some(accent),
+            (Some(accent), Symbol::Char(c)) => match compose(accent, c) {
+                Some(c) => out.push(c),
+                None => {
+                    out.extend(compose(accent, ' '));
+                    out.push(c);
+                },
+            },
+            (Some(accent), Symbol::Dead(next)) => {
+                out.extend(compose(accent, ' '));
+                if accent != next {
+                    self.dead = Some(next);
+                }
+            },
+        }
+    }
+
+    /// Decodes the text typed by a sequence of events.
+    pub fn decode<'e, I: IntoIterator<Item=&'e InputEvent>>(&mut self, events: I) -> String {
+        let mut out = String::new();
+        for event in events {
+            self.process(event, &mut out);
+        }
+        out
+    }
+}
+
+/// The case mapping of a character, unless it maps to several characters.
+fn single_char<I: Iterator<Item=char>>(mut chars: I) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Keys common to most layouts.
+const COMMON: &[(Key, &str)] = &[
+    (Key::Space, "  "),
+    (Key::Enter, "\n"),
+    (Key::Tab, "\t"),
+    (Key::Backspace, "\u{8}"),
+    (Key::Esc, "\u{1b}"),
+    (Key::Delete, "\u{7f}"),
+    (Key::KpAsterisk, "*"),
+    (Key::KpMinus, "-"),
+    (Key::KpPlus, "+"),
+    (Key::KpSlash, "/"),
+    (Key::KpEnter, "\n"),
+];
+
+const US: &[(Key, &str)] = &[
+    (Key::Grave, "`~"),
+    (Key::Num1, "1!"),
+    (Key::Num2, "2@"),
+    (Key::Num3, "3#"),
+    (Key::Num4, "4$"),
+    (Key::Num5, "5%"),
+    (Key::Num6, "6^"),
+    (Key::Num7, "7&"),
+    (Key::Num8, "8*"),
+    (Key::Num9, "9("),
+    (Key::Num0, "0)"),
+    (Key::Minus, "-_"),
+    (Key::Equal, "=+"),
+    (Key::Q, "qQ"),
+    (Key::W, "wW"),
+    (Key::E, "eE"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "yY"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBrace, "[{"),
+    (Key::RightBrace, "]}"),
+    (Key::Backslash, "\\|"),
+    (Key::A, "aA"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, ";:"),
+    (Key::Apostrophe, "'\""),
+    (Key::Z, "zZ"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, "mM"),
+    (Key::Comma, ",<"),
+    (Key::Dot, ".>"),
+    (Key::Slash, "/?"),
+];
+
+const UK: &[(Key, &str)] = &[
+    (Key::Grave, "`¬\0¦"),
+    (Key::Num1, "1!"),
+    (Key::Num2, "2\""),
+    (Key::Num3, "3£"),
+    (Key::Num4, "4$€"),
+    (Key::Num5, "5%"),
+    (Key::Num6, "6^"),
+    (Key::Num7, "7&"),
+    (Key::Num8, "8*"),
+    (Key::Num9, "9("),
+    (Key::Num0, "0)"),
+    (Key::Minus, "-_"),
+    (Key::Equal, "=+"),
+    (Key::Q, "qQ"),
+    (Key::W, "wW"),
+    (Key::E, "eEé"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "yY"),
+    (Key::U, "uUú"),
+    (Key::I, "iIí"),
+    (Key::O, "oOó"),
+    (Key::P, "pP"),
+    (Key::LeftBrace, "[{"),
+    (Key::RightBrace, "]}"),
+    (Key::A, "aAá"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, ";:"),
+    (Key::Apostrophe, "'@"),
+    (Key::Backslash, "#~"),
+    (Key::NonUsBackslashAndPipe, "\\|"),
+    (Key::Z, "zZ"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, "mM"),
+    (Key::Comma, ",<"),
+    (Key::Dot, ".>"),
+    (Key::Slash, "/?"),
+];
+
+const DE: &[(Key, &str)] = &[
+    (Key::Grave, "\u{302}°"),
+    (Key::Num1, "1!¹"),
+    (Key::Num2, "2\"²"),
+    (Key::Num3, "3§³"),
+    (Key::Num4, "4$"),
+    (Key::Num5, "5%"),
+    (Key::Num6, "6&"),
+    (Key::Num7, "7/{"),
+    (Key::Num8, "8(["),
+    (Key::Num9, "9)]"),
+    (Key::Num0, "0=}"),
+    (Key::Minus, "ß?\\"),
+    (Key::Equal, "\u{301}\u{300}"),
+    (Key::Q, "qQ@"),
+    (Key::W, "wW"),
+    (Key::E, "eE€"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "zZ"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBrace, "üÜ"),
+    (Key::RightBrace, "+*~"),
+    (Key::A, "aA"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, "öÖ"),
+    (Key::Apostrophe, "äÄ"),
+    (Key::Backslash, "#'"),
+    (Key::NonUsBackslashAndPipe, "<>|"),
+    (Key::Z, "yY"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, "mMµ"),
+    (Key::Comma, ",;"),
+    (Key::Dot, ".:"),
+    (Key::Slash, "-_"),
+];
+
+const FR: &[(Key, &str)] = &[
+    (Key::Grave, "²"),
+    (Key::Num1, "&1"),
+    (Key::Num2, "é2\u{303}"),
+    (Key::Num3, "\"3#"),
+    (Key::Num4, "'4{"),
+    (Key::Num5, "(5["),
+    (Key::Num6, "-6|"),
+    (Key::Num7, "è7\u{300}"),
+    (Key::Num8, "_8\\"),
+    (Key::Num9, "ç9^"),
+    (Key::Num0, "à0@"),
+    (Key::Minus, ")°]"),
+    (Key::Equal, "=+}"),
+    (Key::Q, "aA"),
+    (Key::W, "zZ"),
+    (Key::E, "eE€"),
+    (Key::R, "rR"),
+    (Key::T, "tT"),
+    (Key::Y, "yY"),
+    (Key::U, "uU"),
+    (Key::I, "iI"),
+    (Key::O, "oO"),
+    (Key::P, "pP"),
+    (Key::LeftBrace, "\u{302}\u{308}"),
+    (Key::RightBrace, "$£¤"),
+    (Key::A, "qQ"),
+    (Key::S, "sS"),
+    (Key::D, "dD"),
+    (Key::F, "fF"),
+    (Key::G, "gG"),
+    (Key::H, "hH"),
+    (Key::J, "jJ"),
+    (Key::K, "kK"),
+    (Key::L, "lL"),
+    (Key::Semicolon, "mM"),
+    (Key::Apostrophe, "ù%"),
+    (Key::Backslash, "*µ"),
+    (Key::NonUsBackslashAndPipe, "<>"),
+    (Key::Z, "wW"),
+    (Key::X, "xX"),
+    (Key::C, "cC"),
+    (Key::V, "vV"),
+    (Key::B, "bB"),
+    (Key::N, "nN"),
+    (Key::M, ",?"),
+    (Key::Comma, ";."),
+    (Key::Dot, ":/"),
+    (Key::Slash, "!§"),
+];
+
+#[test]
+fn layout_us() {
+    let us = KeyboardLayout::us();
+    assert_eq!(us.keystrokes('?'), Some(&[KeyStroke::new(Key::Slash, Modifiers::LEFT_SHIFT)][..]));
+    assert_eq!(us.keystrokes('-'), Some(&[KeyStroke::from(Key::Minus)][..]));
+    assert_eq!(us.char(KeyStroke::from(Key::KpMinus)), Some('-'));
+    assert_eq!(us.keystrokes('é'), None);
+}
+
+#[test]
+fn layout_dead_keys() {
+    let fr = KeyboardLayout::fr();
+    assert_eq!(fr.keystrokes('ê'), Some(&[KeyStroke::from(Key::LeftBrace), KeyStroke::from(Key::E)][..]));
+    assert_eq!(fr.keystrokes('é'), Some(&[KeyStroke::from(Key::Num2)][..]));
+
+    let de = KeyboardLayout::de();
+    let time = crate::EventTime::default();
+    let events: Vec<InputEvent> = [
+        (Key::Equal, KeyState::PRESSED), (Key::Equal, KeyState::RELEASED),
+        (Key::E, KeyState::PRESSED), (Key::E, KeyState::RELEASED),
+        (Key::LeftShift, KeyState::PRESSED), (Key::Y, KeyState::PRESSED),
+        (Key::Y, KeyState::RELEASED), (Key::LeftShift, KeyState::RELEASED),
+        (Key::Grave, KeyState::PRESSED), (Key::Grave, KeyState::RELEASED),
+        (Key::Space, KeyState::PRESSED), (Key::Space, KeyState::RELEASED),
+    ].iter().map(|&(key, state)| KeyEvent::new(time, key, state).into()).collect();
+    assert_eq!(TextDecoder::new(&de).decode(&events), "éZ^");
+}
+
+#[test]
+fn layout_caps_lock() {
+    use crate::{LedEvent, LedKind};
+
+    let time = crate::EventTime::default();
+    let decode = |layout: &KeyboardLayout, held: &[Key], keys: &[Key]| {
+        let mut events: Vec<InputEvent> = vec![LedEvent::new(time, LedKind::CapsLock, 1).into()];
+        events.extend(held.iter().map(|&key| InputEvent::from(KeyEvent::new(time, key, KeyState::PRESSED))));
+        for &key in keys {
+            events.push(KeyEvent::new(time, key, KeyState::PRESSED).into());
+            events.push(KeyEvent::new(time, key, KeyState::RELEASED).into());
+        }
+        TextDecoder::new(layout).decode(&events)
+    };
+    assert_eq!(decode(&KeyboardLayout::us(), &[], &[Key::A, Key::Num1]), "A1");
+    assert_eq!(decode(&KeyboardLayout::de(), &[], &[Key::Minus, Key::Semicolon, Key::Y]), "ßÖZ");
+    assert_eq!(decode(&KeyboardLayout::fr(), &[], &[Key::Num2, Key::Num1, Key::Q]), "É&A");
+    assert_eq!(decode(&KeyboardLayout::de(), &[Key::LeftShift], &[Key::Minus, Key::Semicolon]), "?ö");
+}
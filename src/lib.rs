@@ -17,7 +17,7 @@ mod kinds;
 pub use crate::kinds::*;
 
 mod time;
-pub use crate::time::EventTime;
+pub use crate::time::{EventTime, ClockId};
 
 mod events;
 pub use crate::events::*;
@@ -26,22 +26,82 @@ mod keys;
 pub use crate::keys::Key;
 
 pub mod evdev;
-pub use crate::evdev::EvdevHandle;
+pub use crate::evdev::{EvdevHandle, EvdevDevice};
 
 pub mod uinput;
-pub use crate::uinput::UInputHandle;
+pub use crate::uinput::{UInputHandle, UInputDevice, UInputWriter};
+
+pub mod uhid;
+pub use crate::uhid::UHidHandle;
+
+pub mod loopback;
+
+pub mod mock;
+
+pub mod stream;
+pub use crate::stream::{InputSource, InputSink};
+#[cfg(feature = "tokio-1")]
+pub use crate::stream::{AsyncInputSource, AsyncInputSink};
+
+pub mod debounce;
+pub use crate::debounce::{Debouncer, DebounceMode};
+
+pub mod pipeline;
+pub use crate::pipeline::{Pipeline, Stage};
+
+pub mod report_descriptor;
 
 pub mod enum_iterator;
 
 pub mod bitmask;
 pub use crate::bitmask::Bitmask;
 
+pub mod abi;
+
+pub mod capabilities;
+pub use crate::capabilities::DeviceCapabilities;
+
+pub mod tracker;
+pub use crate::tracker::{KeyStateTracker, Modifiers};
+
+pub mod remap;
+pub use crate::remap::{Remapper, Layer, Mapping};
+
+pub mod taphold;
+pub use crate::taphold::{TapHold, TapHoldProcessor};
+
+pub mod chord;
+pub use crate::chord::{ChordDetector, ChordOutput, Combo};
+
+pub mod layout;
+pub use crate::layout::{KeyboardLayout, KeyStroke, Symbol, TextDecoder};
+
+pub mod keyboard;
+pub use crate::keyboard::VirtualKeyboard;
+
+pub mod mouse;
+pub use crate::mouse::VirtualMouse;
+
+pub mod gamepad;
+pub use crate::gamepad::{GamepadKind, GamepadState, VirtualGamepad};
+
+pub mod tablet;
+pub use crate::tablet::{VirtualTablet, VirtualTouchscreen};
+
+pub mod hid;
+
+pub mod xkb;
+
 #[cfg(feature = "codec")]
 #[cfg_attr(feature = "dox", doc(cfg(feature = "codec")))]
 mod codec;
 
 #[cfg(feature = "codec")]
-pub use crate::codec::EventCodec;
+pub use crate::codec::{EventCodec, FrameCodec, Validation, InvalidEvents};
+
+#[cfg(feature = "codec")]
+#[cfg_attr(feature = "dox", doc(cfg(feature = "codec")))]
+pub mod forward;
 
 #[repr(C)]
 #[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
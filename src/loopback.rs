@@ -0,0 +1,109 @@
+//! A uinput device paired with its own evdev device, for testing.
+//!
+//! Events written to the uinput side of a [`Loopback`] come back out of the
+//! evdev side after passing through the kernel, which makes it possible to
+//! test code that consumes an [`EvdevHandle`] without any real hardware.
+
+use std::{io, fs};
+use std::time::{Duration, Instant};
+use std::os::unix::fs::OpenOptionsExt;
+use nix::libc::O_NONBLOCK;
+use crate::{
+    EvdevHandle, UInputHandle, UInputDevice, DeviceCapabilities, InputEvent,
+    SynchronizeEvent, EventKind, SynchronizeKind,
+};
+
+/// The location of the uinput device node.
+pub const UINPUT_PATH: &str = "/dev/uinput";
+
+/// Both ends of a virtual input device.
+pub struct Loopback {
+    uinput: UInputDevice<fs::File>,
+    evdev: EvdevHandle<fs::File>,
+}
+
+impl Loopback {
+    /// How long to wait for the evdev device to appear by default.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Creates a uinput device with the given capabilities and opens the
+    /// matching evdev device.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] or
+    /// [`io::ErrorKind::PermissionDenied`] when uinput is unavailable.
+    pub fn new(caps: &DeviceCapabilities) -> io::Result<Self> {
+        Self::with_timeout(caps, Self::DEFAULT_TIMEOUT)
+    }
+
+    /// Creates a loopback device, waiting up to `timeout` for the evdev
+    /// device to become available.
+    pub fn with_timeout(caps: &DeviceCapabilities, timeout: Duration) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_NONBLOCK)
+            .open(UINPUT_PATH)?;
+        let mut uinput = UInputHandle::new(file).create_device_from_capabilities(caps)?;
+        let path = uinput.wait(timeout)?;
+        let evdev = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(O_NONBLOCK)
+            .open(path)?;
+        Ok(Loopback {
+            uinput,
+            evdev: EvdevHandle::new(evdev),
+        })
+    }
+
+    /// The uinput side of the device.
+    pub fn uinput(&self) -> &UInputHandle<fs::File> {
+        self.uinput.handle()
+    }
+
+    /// The owned uinput device, which is destroyed along with the loopback.
+    pub fn device(&self) -> &UInputDevice<fs::File> {
+        &self.uinput
+    }
+
+    /// The evdev side of the device.
+    pub fn evdev(&self) -> &EvdevHandle<fs::File> {
+        &self.evdev
+    }
+
+    /// Writes events to the uinput device as they are.
+    pub fn write(&self, events: &[InputEvent]) -> io::Result<()> {
+        let events: Vec<_> = events.iter().map(|e| *e.as_raw()).collect();
+        self.uinput().write(&events).map(drop)
+    }
+
+    /// Writes events to the uinput device followed by a `SYN_REPORT`.
+    pub fn write_frame(&self, events: &[InputEvent]) -> io::Result<()> {
+        let time = events.last().map(|e| e.time).unwrap_or_default();
+        let mut frame = events.to_vec();
+        frame.push(SynchronizeEvent::report(time).into());
+        self.write(&frame)
+    }
+
+    /// Reads events from the evdev device up to the next `SYN_REPORT`.
+    ///
+    /// The `SYN_REPORT` itself is not included. Fails with
+    /// [`io::ErrorKind::TimedOut`] if the frame is not complete in time.
+    pub fn read_frame(&self, timeout: Duration) -> io::Result<Vec<InputEvent>> {
+        let deadline = Instant::now() + timeout;
+        let mut frame = Vec::new();
+        loop {
+            match self.evdev.read_input_event() {
+                Ok(event) if event.kind == EventKind::Synchronize && event.code == SynchronizeKind::Report as u16 =>
+                    return Ok(frame),
+                Ok(event) => frame.push(event),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if !self.evdev.wait_readable(Some(remaining))? {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for events"))
+                    }
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
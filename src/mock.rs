@@ -0,0 +1,166 @@
+//! An in-memory input device for testing.
+//!
+//! [`MockDevice`] implements [`EvdevDevice`] using scripted capabilities and
+//! a queue of events, so code written against the trait can be tested
+//! without access to any real or virtual devices.
+
+use std::io;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+use std::time::Duration;
+use crate::{
+    AbsoluteAxis, AbsoluteInfo, DeviceCapabilities, EventRef, InputEvent, Key,
+    LedKind, SynchronizeEvent,
+};
+use crate::bitmask::Bitmask;
+use crate::evdev::EvdevDevice;
+
+/// A scripted input device.
+///
+/// Events are queued up front or while the device is in use, and are read
+/// back in order. Reading from an empty queue fails with
+/// [`io::ErrorKind::WouldBlock`], like a non-blocking evdev device.
+#[derive(Debug, Default)]
+pub struct MockDevice {
+    caps: RefCell<DeviceCapabilities>,
+    queue: RefCell<VecDeque<InputEvent>>,
+    keys: RefCell<Bitmask<Key>>,
+    leds: RefCell<Bitmask<LedKind>>,
+    grabbed: Cell<bool>,
+}
+
+impl MockDevice {
+    /// Creates a device with the given capabilities and no pending events.
+    pub fn new(caps: DeviceCapabilities) -> Self {
+        MockDevice {
+            caps: RefCell::new(caps),
+            .. Default::default()
+        }
+    }
+
+    /// Queues an event, updating the state of the device to match.
+    pub fn push_event(&self, event: InputEvent) {
+        match EventRef::new(&event) {
+            Ok(EventRef::Key(key)) => match key.value.value {
+                0 => self.keys.borrow_mut().remove(key.key),
+                _ => self.keys.borrow_mut().insert(key.key),
+            },
+            Ok(EventRef::Led(led)) => match led.value {
+                0 => self.leds.borrow_mut().remove(led.led),
+                _ => self.leds.borrow_mut().insert(led.led),
+            },
+            Ok(EventRef::Absolute(abs)) => {
+                let mut caps = self.caps.borrow_mut();
+                if let Some(setup) = caps.absolute.iter_mut().find(|setup| setup.axis == abs.axis) {
+                    setup.info.value = abs.value;
+                }
+            },
+            _ => (),
+        }
+        self.queue.borrow_mut().push_back(event);
+    }
+
+    /// Queues several events.
+    pub fn push_events<I: IntoIterator<Item=InputEvent>>(&self, events: I) {
+        for event in events {
+            self.push_event(event);
+        }
+    }
+
+    /// Queues a frame of events followed by a `SYN_REPORT`.
+    pub fn push_frame(&self, events: &[InputEvent]) {
+        let time = events.last().map(|e| e.time).unwrap_or_default();
+        self.push_events(events.iter().cloned());
+        self.push_event(SynchronizeEvent::report(time).into());
+    }
+
+    /// The number of events that have not been read yet.
+    pub fn pending(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    /// Whether the device is currently grabbed.
+    pub fn is_grabbed(&self) -> bool {
+        self.grabbed.get()
+    }
+}
+
+impl EvdevDevice for MockDevice {
+    fn read_input_events<'e>(&self, events: &'e mut [MaybeUninit<InputEvent>]) -> io::Result<&'e mut [InputEvent]> {
+        let mut queue = self.queue.borrow_mut();
+        if queue.is_empty() && !events.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "no events queued"))
+        }
+        let count = events.len().min(queue.len());
+        for (slot, event) in events.iter_mut().zip(queue.drain(..count)) {
+            *slot = MaybeUninit::new(event);
+        }
+        let events = &mut events[..count];
+        Ok(unsafe {
+            &mut *(events as *mut [MaybeUninit<InputEvent>] as *mut [InputEvent])
+        })
+    }
+
+    fn wait_readable(&self, _timeout: Option<Duration>) -> io::Result<bool> {
+        Ok(!self.queue.borrow().is_empty())
+    }
+
+    fn capabilities(&self) -> io::Result<DeviceCapabilities> {
+        Ok(self.caps.borrow().clone())
+    }
+
+    fn absolute_info(&self, abs: AbsoluteAxis) -> io::Result<AbsoluteInfo> {
+        self.caps.borrow().absolute_setup(abs)
+            .map(|setup| setup.info)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported absolute axis"))
+    }
+
+    fn pressed_keys(&self) -> io::Result<Bitmask<Key>> {
+        Ok(*self.keys.borrow())
+    }
+
+    fn active_leds(&self) -> io::Result<Bitmask<LedKind>> {
+        Ok(*self.leds.borrow())
+    }
+
+    fn grab(&self, grab: bool) -> io::Result<()> {
+        match (self.grabbed.get(), grab) {
+            (true, true) => Err(io::Error::from_raw_os_error(nix::libc::EBUSY)),
+            _ => {
+                self.grabbed.set(grab);
+                Ok(())
+            },
+        }
+    }
+}
+
+#[test]
+fn mock_device() {
+    use crate::{EventTime, KeyEvent, KeyState, AbsoluteEvent, AbsoluteInfoSetup, InputId, KeyStateTracker};
+
+    let mut caps = DeviceCapabilities::new(InputId::default(), b"mock");
+    caps.insert_key(Key::A);
+    caps.insert_absolute(AbsoluteInfoSetup { axis: AbsoluteAxis::X, info: AbsoluteInfo { maximum: 100, ..Default::default() } });
+    let mock = MockDevice::new(caps);
+    assert!(mock.read_input_event().is_err());
+
+    let time = EventTime::default();
+    let frame = [
+        KeyEvent::new(time, Key::A, KeyState::PRESSED).into(),
+        AbsoluteEvent::new(time, AbsoluteAxis::X, 42).into(),
+    ];
+    mock.push_frame(&frame);
+    assert_eq!(mock.absolute_info(AbsoluteAxis::X).unwrap().value, 42);
+    assert!(KeyStateTracker::from_evdev(&mock).unwrap().is_pressed(Key::A));
+
+    let mut events = [MaybeUninit::uninit(); 8];
+    let read = mock.read_input_events(&mut events).unwrap();
+    assert_eq!(read.len(), 3);
+    assert_eq!(read[..2], frame);
+    assert_eq!(mock.pending(), 0);
+
+    mock.grab(true).unwrap();
+    assert!(mock.grab(true).is_err());
+    mock.grab(false).unwrap();
+}
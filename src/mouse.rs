@@ -0,0 +1,213 @@
+//! A virtual mouse for moving the pointer, clicking and scrolling.
+
+use std::{io, thread};
+use std::time::{Duration, Instant};
+use std::os::unix::io::AsRawFd;
+use crate::{
+    UInputHandle, UInputWriter, InputId, InputEvent, EventTime, Key, KeyEvent, KeyState,
+    RelativeAxis, RelativeEvent, DeviceCapabilities,
+};
+
+/// The number of high-resolution scroll units in a single wheel detent.
+pub const WHEEL_DETENT: i32 = 120;
+
+/// The buttons enabled by [`VirtualMouse::create`].
+pub const BUTTONS: [Key; 5] = [
+    Key::ButtonLeft, Key::ButtonRight, Key::ButtonMiddle, Key::ButtonSide, Key::ButtonExtra,
+];
+
+/// The capabilities of a five button mouse with high-resolution scrolling.
+pub fn capabilities(id: InputId, name: &[u8]) -> DeviceCapabilities {
+    let mut caps = DeviceCapabilities::new(id, name);
+    for &key in &BUTTONS {
+        caps.insert_key(key);
+    }
+    for &axis in &[
+        RelativeAxis::X, RelativeAxis::Y,
+        RelativeAxis::Wheel, RelativeAxis::HorizontalWheel,
+        RelativeAxis::WheelHiRes, RelativeAxis::HorizontalWheelHiRes,
+    ] {
+        caps.insert_relative(axis);
+    }
+    caps
+}
+
+/// Splits high-resolution scrolling into legacy wheel detents.
+#[derive(Copy, Clone, Default, Debug)]
+struct Wheel {
+    remainder: i32,
+}
+
+impl Wheel {
+    fn scroll(&mut self, hi_res: RelativeAxis, axis: RelativeAxis, value: i32, time: EventTime, out: &mut Vec<InputEvent>) {
+        if value == 0 {
+            return
+        }
+        // a change of direction discards any partial detent
+        if self.remainder.signum() == -value.signum() {
+            self.remainder = 0;
+        }
+        self.remainder = self.remainder.saturating_add(value);
+        let detents = self.remainder / WHEEL_DETENT;
+        self.remainder -= detents * WHEEL_DETENT;
+        out.push(RelativeEvent::new(time, hi_res, value).into());
+        if detents != 0 {
+            out.push(RelativeEvent::new(time, axis, detents).into());
+        }
+    }
+}
+
+/// Moves, clicks and scrolls a uinput mouse.
+pub struct VirtualMouse<F> {
+    writer: UInputWriter<F>,
+    interval: Duration,
+    vertical: Wheel,
+    horizontal: Wheel,
+}
+
+impl<F> VirtualMouse<F> {
+    /// The default interval between steps of smooth motion.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(8);
+
+    /// Wraps a uinput device that has already been created with the
+    /// necessary buttons and axes enabled.
+    pub const fn new(handle: UInputHandle<F>) -> Self {
+        VirtualMouse {
+            writer: UInputWriter::new(handle),
+            interval: Self::DEFAULT_INTERVAL,
+            vertical: Wheel { remainder: 0 },
+            horizontal: Wheel { remainder: 0 },
+        }
+    }
+
+    /// Sets the interval between each step of smooth motion.
+    pub fn with_interval(self, interval: Duration) -> Self {
+        VirtualMouse { interval, ..self }
+    }
+
+    /// The interval between each step of smooth motion.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// A reference to the underlying uinput handle.
+    pub const fn handle(&self) -> &UInputHandle<F> {
+        self.writer.handle()
+    }
+
+    /// A mutable reference to the writer used for each frame.
+    pub fn writer_mut(&mut self) -> &mut UInputWriter<F> {
+        &mut self.writer
+    }
+
+    /// Extracts the underlying uinput handle.
+    pub fn into_inner(self) -> UInputHandle<F> {
+        self.writer.into_inner()
+    }
+}
+
+impl<F: AsRawFd> VirtualMouse<F> {
+    /// Creates a uinput mouse with the capabilities of [`capabilities`].
+    pub fn create(handle: UInputHandle<F>, id: &InputId, name: &[u8]) -> io::Result<Self> {
+        handle.create_from_capabilities(&capabilities(*id, name))?;
+        Ok(Self::new(handle))
+    }
+
+    /// Moves the pointer by a relative amount.
+    pub fn move_by(&mut self, x: i32, y: i32) -> io::Result<()> {
+        let time = EventTime::default();
+        let events: Vec<InputEvent> = [(RelativeAxis::X, x), (RelativeAxis::Y, y)].iter()
+            .filter(|&&(_, value)| value != 0)
+            .map(|&(axis, value)| RelativeEvent::new(time, axis, value).into())
+            .collect();
+        match events.is_empty() {
+            true => Ok(()),
+            false => self.writer.write_frame(&events),
+        }
+    }
+
+    /// Moves the pointer by a relative amount, spread out evenly over the
+    /// given duration.
+    pub fn move_smooth(&mut self, x: i32, y: i32, duration: Duration) -> io::Result<()> {
+        let interval = self.interval.max(Duration::from_millis(1));
+        let steps = (duration.as_nanos() / interval.as_nanos()).max(1) as i64;
+        let start = Instant::now();
+        let (mut moved_x, mut moved_y) = (0, 0);
+        for step in 1..=steps {
+            let target_x = (x as i64 * step / steps) as i32;
+            let target_y = (y as i64 * step / steps) as i32;
+            self.move_by(target_x - moved_x, target_y - moved_y)?;
+            moved_x = target_x;
+            moved_y = target_y;
+            if step < steps {
+                // sleep until the next step is due, so time spent writing doesn't add up
+                let due = start + interval * step as u32;
+                if let Some(delay) = due.checked_duration_since(Instant::now()) {
+                    thread::sleep(delay);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Presses a button.
+    pub fn press(&mut self, button: Key) -> io::Result<()> {
+        self.writer.write_frame(&[KeyEvent::new(EventTime::default(), button, KeyState::PRESSED).into()])
+    }
+
+    /// Releases a button.
+    pub fn release(&mut self, button: Key) -> io::Result<()> {
+        self.writer.write_frame(&[KeyEvent::new(EventTime::default(), button, KeyState::RELEASED).into()])
+    }
+
+    /// Presses and releases a button.
+    pub fn click(&mut self, button: Key) -> io::Result<()> {
+        self.press(button)?;
+        self.release(button)
+    }
+
+    /// Scrolls by a number of wheel detents.
+    ///
+    /// Positive values scroll up and right.
+    pub fn scroll(&mut self, vertical: i32, horizontal: i32) -> io::Result<()> {
+        self.scroll_hi_res(vertical.saturating_mul(WHEEL_DETENT), horizontal.saturating_mul(WHEEL_DETENT))
+    }
+
+    /// Scrolls by a number of high-resolution units, where [`WHEEL_DETENT`]
+    /// units make up a single detent.
+    ///
+    /// Legacy wheel events are emitted whenever the accumulated high-resolution
+    /// motion crosses a full detent.
+    pub fn scroll_hi_res(&mut self, vertical: i32, horizontal: i32) -> io::Result<()> {
+        let time = EventTime::default();
+        let mut events = Vec::new();
+        self.vertical.scroll(RelativeAxis::WheelHiRes, RelativeAxis::Wheel, vertical, time, &mut events);
+        self.horizontal.scroll(RelativeAxis::HorizontalWheelHiRes, RelativeAxis::HorizontalWheel, horizontal, time, &mut events);
+        match events.is_empty() {
+            true => Ok(()),
+            false => self.writer.write_frame(&events),
+        }
+    }
+}
+
+#[test]
+fn wheel_detents() {
+    let time = EventTime::default();
+    let hi_res = |value| InputEvent::from(RelativeEvent::new(time, RelativeAxis::WheelHiRes, value));
+    let legacy = |value| InputEvent::from(RelativeEvent::new(time, RelativeAxis::Wheel, value));
+
+    let mut wheel = Wheel::default();
+    let mut events = Vec::new();
+    wheel.scroll(RelativeAxis::WheelHiRes, RelativeAxis::Wheel, 60, time, &mut events);
+    wheel.scroll(RelativeAxis::WheelHiRes, RelativeAxis::Wheel, 90, time, &mut events);
+    wheel.scroll(RelativeAxis::WheelHiRes, RelativeAxis::Wheel, 240, time, &mut events);
+    wheel.scroll(RelativeAxis::WheelHiRes, RelativeAxis::Wheel, -60, time, &mut events);
+    wheel.scroll(RelativeAxis::WheelHiRes, RelativeAxis::Wheel, -60, time, &mut events);
+    assert_eq!(events, [
+        hi_res(60),
+        hi_res(90), legacy(1),
+        hi_res(240), legacy(2),
+        hi_res(-60),
+        hi_res(-60), legacy(-1),
+    ]);
+}
@@ -0,0 +1,568 @@
+//! Declarative transforms between input sources and sinks.
+//!
+//! A [`Pipeline`] reads whole frames from an [`InputSource`], passes them
+//! through a sequence of [`Stage`]s, and writes whatever comes out the other
+//! end to an [`InputSink`]. A frame is a run of events terminated by a
+//! `SYN_REPORT`, and every stage consumes and produces complete frames, so
+//! transforms never split a frame that the kernel reported atomically.
+//!
+//! Sources and sinks include evdev and uinput devices as well as recordings
+//! read and written with the `EventReader` and `EventWriter` types of the
+//! [`stream`](crate::stream) module.
+//!
+//! Stages that hold events back report a [`deadline`](Stage::deadline), and
+//! [`Pipeline::run`] calls [`timeout`](Stage::timeout) once it passes while
+//! waiting for the source.
+
+use std::io;
+use std::time::Duration;
+use crate::{
+    InputSource, InputSink, InputEvent, EventKind, EventTime, ClockId, SynchronizeEvent,
+    AbsoluteAxis, AbsoluteInfo, RelativeAxis, Remapper, Debouncer,
+};
+use crate::bitmask::Bitmask;
+use crate::stream::is_report;
+
+/// The time of a frame, taken from its `SYN_REPORT`.
+fn frame_time(frame: &[InputEvent]) -> EventTime {
+    frame.last().map(|e| e.time).unwrap_or_default()
+}
+
+/// Splits a sequence of events into frames, skipping any that are empty.
+fn frames(events: &[InputEvent]) -> impl Iterator<Item=&[InputEvent]> {
+    events.split_inclusive(is_report)
+        .filter(|frame| !frame.iter().all(is_report))
+}
+
+/// A transform applied to each frame of a [`Pipeline`].
+pub trait Stage {
+    /// Processes a frame ending in a `SYN_REPORT`, appending zero or more
+    /// resulting frames to `out`.
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>);
+
+    /// The time at which events that are being held back are due, if any.
+    fn deadline(&self) -> Option<EventTime> {
+        None
+    }
+
+    /// Releases any events that are due by `now`, appending them to `out` as
+    /// complete frames.
+    fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        let _ = (now, out);
+    }
+}
+
+impl<S: Stage + ?Sized> Stage for &'_ mut S {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        (**self).process(frame, out)
+    }
+
+    fn deadline(&self) -> Option<EventTime> {
+        (**self).deadline()
+    }
+
+    fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        (**self).timeout(now, out)
+    }
+}
+
+impl<S: Stage + ?Sized> Stage for Box<S> {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        (**self).process(frame, out)
+    }
+
+    fn deadline(&self) -> Option<EventTime> {
+        (**self).deadline()
+    }
+
+    fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        (**self).timeout(now, out)
+    }
+}
+
+/// Remaps keys, forwarding all other events.
+impl Stage for Remapper {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        for event in frame {
+            Remapper::process(self, event, out);
+        }
+    }
+}
+
+/// Debounces keys and switches.
+impl Stage for Debouncer {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        for event in frame {
+            Debouncer::process(self, event, out);
+        }
+    }
+
+    fn deadline(&self) -> Option<EventTime> {
+        Debouncer::deadline(self)
+    }
+
+    fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        Debouncer::timeout(self, now, out)
+    }
+}
+
+/// A chain of stages.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    clock: ClockId,
+    input: Vec<InputEvent>,
+    output: Vec<InputEvent>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline that passes frames through unchanged.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Appends a stage to the end of the pipeline.
+    pub fn stage<S: Stage + 'static>(mut self, stage: S) -> Self {
+        self.push(stage);
+        self
+    }
+
+    /// Appends a stage to the end of the pipeline.
+    pub fn push<S: Stage + 'static>(&mut self, stage: S) {
+        self.stages.push(Box::new(stage))
+    }
+
+    /// Sets the clock that the timestamps of the source are based on,
+    /// which is the realtime clock by default.
+    pub fn with_clock(self, clock: ClockId) -> Self {
+        Pipeline { clock, ..self }
+    }
+
+    /// Reads frames from `source` until it ends, writing the transformed
+    /// frames to `sink`.
+    ///
+    /// While a stage has a deadline, the source is only waited on until it
+    /// passes. Sources that are always ready, such as recordings, instead
+    /// reach deadlines through the timestamps of later events. Non-blocking
+    /// sources are waited on until they become readable, and a frame split
+    /// across reads is completed once the rest of it arrives. Anything still
+    /// held back when the source ends is released. The sink is flushed after
+    /// each frame is written.
+    pub fn run<I: InputSource, O: InputSink>(&mut self, mut source: I, mut sink: O) -> io::Result<()> {
+        let mut frame = Vec::new();
+        let mut out = Vec::new();
+        loop {
+            out.clear();
+            let wait = match self.deadline() {
+                Some(deadline) => Some(deadline.saturating_duration_since(EventTime::now(self.clock)?)),
+                None => None,
+            };
+            let end = match source.wait_readable(wait)? {
+                true => loop {
+                    match source.read_event() {
+                        Ok(Some(event)) => {
+                            frame.push(event);
+                            if is_report(&event) {
+                                self.process(&frame, &mut out);
+                                frame.clear();
+                                break false
+                            }
+                        },
+                        Ok(None) => {
+                            while let Some(deadline) = self.deadline() {
+                                self.timeout(deadline, &mut out);
+                            }
+                            break true
+                        },
+                        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break false,
+                        Err(err) => return Err(err),
+                    }
+                },
+                false => {
+                    self.timeout(EventTime::now(self.clock)?, &mut out);
+                    false
+                },
+            };
+            if !out.is_empty() {
+                sink.write_events(&out)?;
+                sink.flush()?;
+            }
+            if end {
+                return Ok(())
+            }
+        }
+    }
+
+    /// Passes the frames in `input` through each stage, calling `timeout` on
+    /// each stage after its input has been processed if `now` is given.
+    fn apply(&mut self, now: Option<EventTime>, out: &mut Vec<InputEvent>) {
+        for stage in &mut self.stages {
+            self.output.clear();
+            for frame in frames(&self.input) {
+                stage.process(frame, &mut self.output);
+            }
+            if let Some(now) = now {
+                stage.timeout(now, &mut self.output);
+            }
+            std::mem::swap(&mut self.input, &mut self.output);
+        }
+        for frame in frames(&self.input) {
+            out.extend_from_slice(frame);
+        }
+    }
+}
+
+impl Stage for Pipeline {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        self.input.clear();
+        self.input.extend_from_slice(frame);
+        self.apply(None, out);
+    }
+
+    fn deadline(&self) -> Option<EventTime> {
+        self.stages.iter().filter_map(|stage| stage.deadline()).min()
+    }
+
+    fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        self.input.clear();
+        self.apply(Some(now), out);
+    }
+}
+
+/// Keeps or discards events by their [`EventKind`].
+///
+/// `SYN_REPORT` events are always kept.
+#[derive(Copy, Clone, Debug)]
+pub struct FilterKinds {
+    kinds: Bitmask<EventKind>,
+    keep: bool,
+}
+
+impl FilterKinds {
+    /// Keeps only events of the given kinds.
+    pub fn allow<I: IntoIterator<Item=EventKind>>(kinds: I) -> Self {
+        let mut mask = Bitmask::default();
+        mask.or(kinds);
+        FilterKinds { kinds: mask, keep: true }
+    }
+
+    /// Discards events of the given kinds.
+    pub fn deny<I: IntoIterator<Item=EventKind>>(kinds: I) -> Self {
+        FilterKinds { keep: false, ..Self::allow(kinds) }
+    }
+}
+
+impl Stage for FilterKinds {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        out.extend(frame.iter()
+            .filter(|&e| is_report(e) || self.kinds.get(e.kind) == self.keep)
+        );
+    }
+}
+
+/// Keeps only the events matching a predicate.
+///
+/// `SYN_REPORT` events are always kept.
+#[derive(Copy, Clone, Debug)]
+pub struct Filter<F> {
+    filter: F,
+}
+
+impl<F: FnMut(&InputEvent) -> bool> Filter<F> {
+    /// Creates a stage that keeps the events for which `filter` returns
+    /// `true`.
+    pub fn new(filter: F) -> Self {
+        Filter { filter }
+    }
+}
+
+impl<F: FnMut(&InputEvent) -> bool> Stage for Filter<F> {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        let filter = &mut self.filter;
+        out.extend(frame.iter().filter(|&e| is_report(e) || filter(e)));
+    }
+}
+
+/// Replaces or discards each event.
+///
+/// `SYN_REPORT` events are passed through unchanged.
+#[derive(Copy, Clone, Debug)]
+pub struct Map<F> {
+    map: F,
+}
+
+impl<F: FnMut(InputEvent) -> Option<InputEvent>> Map<F> {
+    /// Creates a stage that replaces each event with the result of `map`,
+    /// discarding it when `None` is returned.
+    pub fn new(map: F) -> Self {
+        Map { map }
+    }
+}
+
+impl<F: FnMut(InputEvent) -> Option<InputEvent>> Stage for Map<F> {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        for &event in frame {
+            match is_report(&event) {
+                true => out.push(event),
+                false => out.extend((self.map)(event)),
+            }
+        }
+    }
+}
+
+/// Rescales an absolute axis from one range to another.
+///
+/// Values outside of the input range are clamped to it before scaling.
+#[derive(Copy, Clone, Debug)]
+pub struct ScaleAbsolute {
+    axis: AbsoluteAxis,
+    from: (i32, i32),
+    to: (i32, i32),
+}
+
+impl ScaleAbsolute {
+    /// Maps the `minimum..=maximum` range of `from` onto that of `to`.
+    pub fn new(axis: AbsoluteAxis, from: &AbsoluteInfo, to: &AbsoluteInfo) -> Self {
+        ScaleAbsolute {
+            axis,
+            from: (from.minimum, from.maximum),
+            to: (to.minimum, to.maximum),
+        }
+    }
+
+    /// Scales a single value.
+    pub fn scale(&self, value: i32) -> i32 {
+        let (from_min, from_max) = (self.from.0 as i128, self.from.1 as i128);
+        let (to_min, to_max) = (self.to.0 as i128, self.to.1 as i128);
+        let range = from_max - from_min;
+        if range == 0 {
+            return self.to.0
+        }
+        let value = (value as i128).clamp(from_min.min(from_max), from_min.max(from_max));
+        let offset = (value - from_min) * (to_max - to_min);
+        // round to nearest rather than towards zero
+        let scaled = to_min + (offset + offset.signum() * range.abs() / 2) / range;
+        scaled.clamp(to_min.min(to_max), to_min.max(to_max)) as i32
+    }
+}
+
+impl Stage for ScaleAbsolute {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        for &event in frame {
+            match event.kind == EventKind::Absolute && event.code == self.axis as u16 {
+                true => out.push(InputEvent { value: self.scale(event.value), ..event }),
+                false => out.push(event),
+            }
+        }
+    }
+}
+
+/// Multiplies the motion of a relative axis by a constant factor.
+///
+/// Fractional motion is carried over into later events rather than lost, and
+/// events that round to no motion at all are discarded.
+#[derive(Copy, Clone, Debug)]
+pub struct ScaleRelative {
+    axis: RelativeAxis,
+    factor: f64,
+    remainder: f64,
+}
+
+impl ScaleRelative {
+    /// Scales `axis` by `factor`.
+    pub fn new(axis: RelativeAxis, factor: f64) -> Self {
+        ScaleRelative {
+            axis,
+            factor,
+            remainder: 0.0,
+        }
+    }
+}
+
+impl Stage for ScaleRelative {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        for &event in frame {
+            if event.kind != EventKind::Relative || event.code != self.axis as u16 {
+                out.push(event);
+                continue
+            }
+            let value = event.value as f64 * self.factor + self.remainder;
+            let whole = value.trunc();
+            self.remainder = value - whole;
+            if whole != 0.0 {
+                out.push(InputEvent { value: whole as i32, ..event });
+            }
+        }
+    }
+}
+
+/// Limits how often motion is reported.
+///
+/// Frames that contain nothing but relative or absolute motion and arrive
+/// within `interval` of the last forwarded frame are held back and merged, so
+/// that relative motion is summed and only the latest absolute values are
+/// kept. Held motion is forwarded with the next frame outside of the
+/// interval or once the interval has passed, and any other frame is
+/// forwarded immediately.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    interval: Duration,
+    last: Option<EventTime>,
+    pending: Vec<InputEvent>,
+}
+
+impl RateLimit {
+    /// Creates a stage that forwards motion at most once per `interval`.
+    pub fn new(interval: Duration) -> Self {
+        RateLimit {
+            interval,
+            last: None,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Stage for RateLimit {
+    fn process(&mut self, frame: &[InputEvent], out: &mut Vec<InputEvent>) {
+        let time = frame_time(frame);
+        let motion = frame.iter()
+            .all(|e| is_report(e) || e.kind == EventKind::Relative || e.kind == EventKind::Absolute);
+        for event in frame.iter().filter(|e| !is_report(e)) {
+            if !crate::uinput::coalesce(&mut self.pending, event) {
+                self.pending.push(*event);
+            }
+        }
+        let limited = match self.last {
+            Some(last) => time.saturating_duration_since(last) < self.interval,
+            None => false,
+        };
+        if motion && limited {
+            return
+        }
+        self.last = Some(time);
+        out.append(&mut self.pending);
+        out.extend(frame.last().filter(|e| is_report(e)));
+    }
+
+    fn deadline(&self) -> Option<EventTime> {
+        match (self.last, self.pending.is_empty()) {
+            (Some(last), false) => Some(last.checked_add(self.interval).unwrap_or(last)),
+            _ => None,
+        }
+    }
+
+    fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => (),
+            _ => return,
+        }
+        self.last = Some(now);
+        out.append(&mut self.pending);
+        out.push(SynchronizeEvent::report(now).into());
+    }
+}
+
+#[test]
+fn pipeline_stages() {
+    use std::collections::VecDeque;
+    use crate::{Key, KeyEvent, KeyState, RelativeEvent, SynchronizeEvent, Layer, Mapping, DebounceMode};
+
+    let at = |ms: i64| EventTime::new(0, ms * 1000);
+    let key = |ms, key, state| InputEvent::from(KeyEvent::new(at(ms), key, state));
+    let rel = |ms, value| InputEvent::from(RelativeEvent::new(at(ms), RelativeAxis::X, value));
+    let misc = |ms| InputEvent { time: at(ms), kind: EventKind::Misc, code: 4, value: 30 };
+    let syn = |ms| InputEvent::from(SynchronizeEvent::report(at(ms)));
+
+    let mut source: VecDeque<InputEvent> = vec![
+        misc(0), key(0, Key::A, KeyState::PRESSED), syn(0),
+        key(2, Key::A, KeyState::RELEASED), syn(2),
+        key(4, Key::A, KeyState::PRESSED), syn(4),
+        rel(12, 1), syn(12),
+        rel(15, 1), syn(15),
+        rel(18, 1), syn(18),
+        key(20, Key::A, KeyState::RELEASED), syn(20),
+        rel(40, 1), syn(40),
+        rel(45, 1), syn(45),
+    ].into();
+
+    let mut layer = Layer::new();
+    layer.insert(Key::A, Mapping::Key(Key::B));
+    let mut pipeline = Pipeline::new()
+        .stage(FilterKinds::deny(Some(EventKind::Misc)))
+        .stage(Debouncer::new(DebounceMode::Eager(Duration::from_millis(10))))
+        .stage(Remapper::new(layer))
+        .stage(ScaleRelative::new(RelativeAxis::X, 2.0))
+        .stage(RateLimit::new(Duration::from_millis(10)));
+    let mut sink = Vec::new();
+    pipeline.run(&mut source, &mut sink).unwrap();
+
+    assert_eq!(sink, [
+        key(0, Key::B, KeyState::PRESSED), syn(0),
+        rel(12, 2), syn(12),
+        rel(15, 4), key(20, Key::B, KeyState::RELEASED), syn(20),
+        rel(40, 2), syn(40),
+        rel(45, 2), syn(50),
+    ]);
+
+    let mut pipeline = Pipeline::new()
+        .stage(Debouncer::new(DebounceMode::Deferred(Duration::from_millis(5))))
+        .stage(RateLimit::new(Duration::from_millis(10)));
+    let mut out = Vec::new();
+    pipeline.process(&[rel(0, 1), syn(0)], &mut out);
+    pipeline.process(&[rel(3, 1), syn(3)], &mut out);
+    pipeline.process(&[key(4, Key::A, KeyState::PRESSED), syn(4)], &mut out);
+    assert_eq!(out, [rel(0, 1), syn(0)]);
+    assert_eq!(pipeline.deadline(), Some(at(9)));
+    out.clear();
+    pipeline.timeout(at(9), &mut out);
+    assert_eq!(out, [rel(3, 1), key(9, Key::A, KeyState::PRESSED), syn(9)]);
+    assert_eq!(pipeline.deadline(), None);
+}
+
+#[test]
+fn pipeline_scale_absolute() {
+    let info = |minimum, maximum| AbsoluteInfo { minimum, maximum, ..Default::default() };
+
+    let scale = ScaleAbsolute::new(AbsoluteAxis::X, &info(0, 100), &info(0, 1000));
+    assert_eq!(scale.scale(50), 500);
+    assert_eq!(scale.scale(-50), 0);
+    assert_eq!(scale.scale(i32::MAX), 1000);
+
+    let scale = ScaleAbsolute::new(AbsoluteAxis::X, &info(i32::MIN, i32::MAX), &info(i32::MAX, i32::MIN));
+    assert_eq!(scale.scale(i32::MIN), i32::MAX);
+    assert_eq!(scale.scale(i32::MAX), i32::MIN);
+}
+
+#[test]
+fn pipeline_run_nonblocking() {
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use crate::{EvdevHandle, UInputHandle, Key, KeyEvent, KeyState, Layer, Mapping};
+
+    let time = EventTime::default();
+    let key = move |key, state| InputEvent::from(KeyEvent::new(time, key, state));
+    let syn = move || InputEvent::from(SynchronizeEvent::report(time));
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    rx.set_nonblocking(true).unwrap();
+    let writer = thread::spawn(move || {
+        let mut tx = UInputHandle::new(tx);
+        // the source is empty until the first write, then a frame is split
+        thread::sleep(Duration::from_millis(20));
+        tx.write_events(&[key(Key::A, KeyState::PRESSED)]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        tx.write_events(&[key(Key::C, KeyState::PRESSED), syn()]).unwrap();
+        tx.write_events(&[key(Key::A, KeyState::RELEASED), syn()]).unwrap();
+    });
+
+    let mut pipeline = Pipeline::new()
+        .stage(Remapper::new(Layer::new().with(Key::A, Mapping::Key(Key::B))));
+    let mut sink = Vec::new();
+    pipeline.run(EvdevHandle::new(rx), &mut sink).unwrap();
+    writer.join().unwrap();
+
+    assert_eq!(sink, [
+        key(Key::B, KeyState::PRESSED), key(Key::C, KeyState::PRESSED), syn(),
+        key(Key::B, KeyState::RELEASED), syn(),
+    ]);
+}
@@ -0,0 +1,413 @@
+//! Remapping keys between an input device and a virtual device.
+//!
+//! A [`Remapper`] consumes events from a source such as an
+//! [`EvdevHandle`](crate::EvdevHandle) and produces the remapped events to be
+//! written to a [`UInputHandle`](crate::UInputHandle). Mappings are organized
+//! into [`Layer`]s that can be activated while a key is held, toggled, or
+//! switched programmatically.
+//!
+//! Every key press remembers what it produced, so the matching release
+//! always releases the same output keys even if the mappings or active
+//! layers change while the key is held.
+
+use std::io;
+use std::mem::MaybeUninit;
+use std::collections::HashMap;
+use crate::{
+    EvdevDevice, InputSink, EventTime, InputEvent, EventKind,
+    Key, KeyEvent, KeyState, SynchronizeEvent, SynchronizeKind, GenericEvent,
+};
+use crate::bitmask::Bitmask;
+
+/// Describes what a key produces when pressed.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Mapping {
+    /// Another key.
+    Key(Key),
+    /// Several keys held together, such as a modifier combination.
+    ///
+    /// The keys are pressed in order and released in reverse order.
+    Keys(Vec<Key>),
+    /// A sequence of keys tapped one after another when pressed.
+    Sequence(Vec<Key>),
+    /// Activates a layer for as long as the key is held.
+    Layer(usize),
+    /// Toggles a layer on or off each time the key is pressed.
+    ToggleLayer(usize),
+    /// The key produces nothing.
+    Disabled,
+}
+
+/// A set of key mappings.
+///
+/// Keys without a mapping fall through to the next active layer below, and
+/// ultimately map to themselves.
+#[derive(Clone, Debug, Default)]
+pub struct Layer {
+    mappings: HashMap<Key, Mapping>,
+}
+
+impl Layer {
+    /// An empty layer that maps nothing.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a mapping to the layer.
+    pub fn with(mut self, key: Key, mapping: Mapping) -> Self {
+        self.insert(key, mapping);
+        self
+    }
+
+    /// Adds a mapping to the layer, returning the previous mapping.
+    pub fn insert(&mut self, key: Key, mapping: Mapping) -> Option<Mapping> {
+        self.mappings.insert(key, mapping)
+    }
+
+    /// Removes a mapping from the layer.
+    pub fn remove(&mut self, key: Key) -> Option<Mapping> {
+        self.mappings.remove(&key)
+    }
+
+    /// Looks up the mapping of a key.
+    pub fn get(&self, key: Key) -> Option<&Mapping> {
+        self.mappings.get(&key)
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+struct LayerState {
+    held: u32,
+    toggled: bool,
+}
+
+impl LayerState {
+    fn is_active(&self) -> bool {
+        self.held > 0 || self.toggled
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Held {
+    Keys(Vec<Key>),
+    Layer(usize),
+    Nothing,
+}
+
+/// Applies layered key mappings to a stream of events.
+#[derive(Clone, Debug)]
+pub struct Remapper {
+    layers: Vec<Layer>,
+    states: Vec<LayerState>,
+    held: HashMap<Key, Held>,
+    outputs: HashMap<Key, u32>,
+    sequence: Vec<Key>,
+}
+
+impl Remapper {
+    /// Creates a remapper with a base layer that is always active.
+    pub fn new(base: Layer) -> Self {
+        Remapper {
+            layers: vec![base],
+            states: vec![LayerState { held: 0, toggled: true }],
+            held: Default::default(),
+            outputs: Default::default(),
+            sequence: Default::default(),
+        }
+    }
+
+    /// Adds an inactive layer above all existing layers, returning its index.
+    pub fn add_layer(&mut self, layer: Layer) -> usize {
+        self.layers.push(layer);
+        self.states.push(Default::default());
+        self.layers.len() - 1
+    }
+
+    /// A reference to a layer.
+    pub fn layer(&self, index: usize) -> Option<&Layer> {
+        self.layers.get(index)
+    }
+
+    /// A mutable reference to a layer.
+    ///
+    /// Changing mappings does not affect keys that are already held.
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut Layer> {
+        self.layers.get_mut(index)
+    }
+
+    /// Whether a layer is currently active.
+    pub fn is_layer_active(&self, index: usize) -> bool {
+        self.states.get(index).map(LayerState::is_active).unwrap_or(false)
+    }
+
+    /// Turns a layer on or off, independently of any keys holding it active.
+    pub fn set_layer_active(&mut self, index: usize, active: bool) {
+        if let Some(state) = self.states.get_mut(index) {
+            state.toggled = active;
+        }
+    }
+
+    /// Resolves the current mapping of a key.
+    pub fn resolve(&self, key: Key) -> Mapping {
+        self.layers.iter().zip(&self.states).rev()
+            .filter(|(_, state)| state.is_active())
+            .find_map(|(layer, _)| layer.get(key))
+            .cloned()
+            .unwrap_or(Mapping::Key(key))
+    }
+
+    fn press(&mut self, time: EventTime, key: Key, out: &mut Vec<InputEvent>) {
+        let count = self.outputs.entry(key).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            out.push(KeyEvent::new(time, key, KeyState::PRESSED).into());
+        }
+    }
+
+    fn release(&mut self, time: EventTime, key: Key, out: &mut Vec<InputEvent>) {
+        match self.outputs.get_mut(&key) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(..) => {
+                self.outputs.remove(&key);
+                out.push(KeyEvent::new(time, key, KeyState::RELEASED).into());
+            },
+            None => (),
+        }
+    }
+
+    /// Processes a single input event, appending the resulting events to `out`.
+    ///
+    /// Events other than keys are passed through unchanged. Sequences are
+    /// tapped in frames of their own after the `SYN_REPORT` that ends the
+    /// frame containing the key press.
+    pub fn process(&mut self, event: &InputEvent, out: &mut Vec<InputEvent>) {
+        if event.kind == EventKind::Synchronize && event.code == SynchronizeKind::Report as u16 {
+            out.push(*event);
+            for key in std::mem::take(&mut self.sequence) {
+                self.press(event.time, key, out);
+                out.push(SynchronizeEvent::report(event.time).into());
+                self.release(event.time, key, out);
+                out.push(SynchronizeEvent::report(event.time).into());
+            }
+            return
+        }
+        let event = match KeyEvent::from_ref(event) {
+            Ok(event) => event,
+            Err(..) => return out.push(*event),
+        };
+        let time = event.time;
+
+        match event.value {
+            KeyState::PRESSED => {
+                if self.held.contains_key(&event.key) {
+                    return
+                }
+                let held = match self.resolve(event.key) {
+                    Mapping::Key(key) => Held::Keys(vec![key]),
+                    Mapping::Keys(keys) => Held::Keys(keys),
+                    Mapping::Sequence(keys) => {
+                        self.sequence.extend(keys);
+                        Held::Nothing
+                    },
+                    Mapping::Layer(layer) => match self.states.get_mut(layer) {
+                        Some(state) => {
+                            state.held += 1;
+                            Held::Layer(layer)
+                        },
+                        None => Held::Nothing,
+                    },
+                    Mapping::ToggleLayer(layer) => {
+                        if let Some(state) = self.states.get_mut(layer) {
+                            state.toggled = !state.toggled;
+                        }
+                        Held::Nothing
+                    },
+                    Mapping::Disabled => Held::Nothing,
+                };
+                if let Held::Keys(ref keys) = held {
+                    for &key in keys {
+                        self.press(time, key, out);
+                    }
+                }
+                self.held.insert(event.key, held);
+            },
+            KeyState::RELEASED => match self.held.remove(&event.key) {
+                Some(Held::Keys(keys)) => for &key in keys.iter().rev() {
+                    self.release(time, key, out);
+                },
+                Some(Held::Layer(layer)) => if let Some(state) = self.states.get_mut(layer) {
+                    state.held = state.held.saturating_sub(1);
+                },
+                Some(Held::Nothing) => (),
+                // pressed before we started watching, so pass it along as-is
+                None => out.push(*event.as_event()),
+            },
+            _ => match self.held.get(&event.key) {
+                Some(Held::Keys(keys)) => if let Some(&key) = keys.last() {
+                    out.push(KeyEvent::new(time, key, event.value).into());
+                },
+                Some(..) => (),
+                None => out.push(*event.as_event()),
+            },
+        }
+    }
+
+    /// Releases every output key that is currently held, followed by a
+    /// `SYN_REPORT`.
+    ///
+    /// Layers held active by a key are deactivated, and sequences that have
+    /// not been tapped yet are discarded.
+    pub fn release_all(&mut self, time: EventTime, out: &mut Vec<InputEvent>) {
+        self.sequence.clear();
+        let mut keys: Vec<_> = self.outputs.drain().map(|(key, _)| key).collect();
+        keys.sort();
+        out.extend(keys.into_iter().map(|key| InputEvent::from(KeyEvent::new(time, key, KeyState::RELEASED))));
+        out.push(SynchronizeEvent::report(time).into());
+        self.held.clear();
+        for state in &mut self.states {
+            state.held = 0;
+        }
+    }
+
+    /// Resynchronizes the held keys with the keys actually `pressed` on the
+    /// input device, such as after a `SYN_DROPPED`.
+    ///
+    /// Keys that were released in the meantime are released, and keys that
+    /// were pressed are pressed, followed by a `SYN_REPORT`.
+    pub fn sync(&mut self, pressed: &Bitmask<Key>, time: EventTime, out: &mut Vec<InputEvent>) {
+        let mut released: Vec<_> = self.held.keys()
+            .filter(|&&key| !pressed.get(key))
+            .cloned()
+            .collect();
+        released.sort();
+        for key in released {
+            self.process(&KeyEvent::new(time, key, KeyState::RELEASED).into(), out);
+        }
+        let newly_pressed: Vec<_> = pressed.iter()
+            .filter(|key| !self.held.contains_key(key))
+            .collect();
+        for key in newly_pressed {
+            self.process(&KeyEvent::new(time, key, KeyState::PRESSED).into(), out);
+        }
+        self.process(&SynchronizeEvent::report(time).into(), out);
+    }
+
+    /// Remaps all events read from `evdev` and writes them to `sink` until
+    /// an error occurs.
+    ///
+    /// The input device should usually be [grabbed](EvdevDevice::grab) first
+    /// so that the original events are not seen by other applications. When
+    /// events are dropped, the rest of the incomplete frame is discarded and
+    /// the held keys are resynchronized with the device.
+    pub fn run<D: EvdevDevice + ?Sized, O: InputSink>(&mut self, evdev: &D, mut sink: O) -> io::Result<()> {
+        let mut events = [MaybeUninit::<InputEvent>::uninit(); 64];
+        let mut out = Vec::new();
+        let mut dropped = false;
+        loop {
+            out.clear();
+            for event in evdev.read_input_events(&mut events)?.iter() {
+                match (event.kind, SynchronizeKind::from_code(event.code)) {
+                    (EventKind::Synchronize, Ok(SynchronizeKind::Dropped)) => dropped = true,
+                    (EventKind::Synchronize, Ok(SynchronizeKind::Report)) if dropped => {
+                        dropped = false;
+                        self.sync(&evdev.pressed_keys()?, event.time, &mut out);
+                    },
+                    _ if dropped => (),
+                    _ => self.process(event, &mut out),
+                }
+            }
+            if !out.is_empty() {
+                sink.write_events(&out)?;
+                sink.flush()?;
+            }
+        }
+    }
+}
+
+#[test]
+fn remap_layers() {
+    let time = EventTime::default();
+    let key = |key, value| InputEvent::from(KeyEvent::new(time, key, value));
+
+    let mut remap = Remapper::new(Layer::new()
+        .with(Key::CapsLock, Mapping::Key(Key::LeftCtrl))
+        .with(Key::RightAlt, Mapping::Layer(1))
+    );
+    remap.add_layer(Layer::new().with(Key::H, Mapping::Key(Key::Left)));
+
+    let mut out = Vec::new();
+    remap.process(&key(Key::CapsLock, KeyState::PRESSED), &mut out);
+    assert_eq!(out, [key(Key::LeftCtrl, KeyState::PRESSED)]);
+
+    // the release pairs with the press even after the mapping changes
+    remap.layer_mut(0).unwrap().remove(Key::CapsLock);
+    out.clear();
+    remap.process(&key(Key::CapsLock, KeyState::RELEASED), &mut out);
+    assert_eq!(out, [key(Key::LeftCtrl, KeyState::RELEASED)]);
+
+    out.clear();
+    remap.process(&key(Key::RightAlt, KeyState::PRESSED), &mut out);
+    remap.process(&key(Key::H, KeyState::PRESSED), &mut out);
+    remap.process(&key(Key::RightAlt, KeyState::RELEASED), &mut out);
+    assert!(!remap.is_layer_active(1));
+    remap.process(&key(Key::H, KeyState::RELEASED), &mut out);
+    assert_eq!(out, [key(Key::Left, KeyState::PRESSED), key(Key::Left, KeyState::RELEASED)]);
+}
+
+#[test]
+fn remap_frames() {
+    let time = EventTime::default();
+    let key = |key, value| InputEvent::from(KeyEvent::new(time, key, value));
+    let syn = || InputEvent::from(SynchronizeEvent::report(time));
+
+    let mut remap = Remapper::new(Layer::new()
+        .with(Key::F1, Mapping::Sequence(vec![Key::X, Key::Y]))
+        .with(Key::CapsLock, Mapping::Key(Key::LeftCtrl))
+    );
+    let mut out = Vec::new();
+    for event in &[key(Key::F1, KeyState::PRESSED), key(Key::B, KeyState::PRESSED), syn()] {
+        remap.process(event, &mut out);
+    }
+    assert_eq!(out, [
+        key(Key::B, KeyState::PRESSED), syn(),
+        key(Key::X, KeyState::PRESSED), syn(), key(Key::X, KeyState::RELEASED), syn(),
+        key(Key::Y, KeyState::PRESSED), syn(), key(Key::Y, KeyState::RELEASED), syn(),
+    ]);
+
+    // B and F1 were released and caps lock pressed while events were dropped
+    out.clear();
+    let mut pressed = Bitmask::default();
+    pressed.insert(Key::CapsLock);
+    remap.sync(&pressed, time, &mut out);
+    assert_eq!(out, [
+        key(Key::B, KeyState::RELEASED),
+        key(Key::LeftCtrl, KeyState::PRESSED),
+        syn(),
+    ]);
+}
+
+#[test]
+fn remap_run() {
+    use crate::mock::MockDevice;
+    use crate::DeviceCapabilities;
+
+    let time = EventTime::default();
+    let key = |key, value| InputEvent::from(KeyEvent::new(time, key, value));
+    let syn = || InputEvent::from(SynchronizeEvent::report(time));
+
+    let device = MockDevice::new(DeviceCapabilities::default());
+    device.push_frame(&[key(Key::CapsLock, KeyState::PRESSED)]);
+    // caps lock was released and A pressed while events were dropped
+    device.push_event(SynchronizeEvent::new(time, SynchronizeKind::Dropped, 0).into());
+    device.push_event(key(Key::CapsLock, KeyState::RELEASED));
+    device.push_frame(&[key(Key::A, KeyState::PRESSED)]);
+
+    let mut remap = Remapper::new(Layer::new().with(Key::CapsLock, Mapping::Key(Key::LeftCtrl)));
+    let mut out = Vec::new();
+    let err = remap.run(&device, &mut out).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(out, [
+        key(Key::LeftCtrl, KeyState::PRESSED), syn(),
+        key(Key::LeftCtrl, KeyState::RELEASED), key(Key::A, KeyState::PRESSED), syn(),
+    ]);
+}
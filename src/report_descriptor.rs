@@ -0,0 +1,611 @@
+//! Building HID report descriptors and input reports.
+//!
+//! A [`ReportDescriptor`] describes a single input report, along with an
+//! optional LED output report, using the same capability model as uinput.
+//! It produces the descriptor bytes for [`UHidDeviceInfo`], and a
+//! [`ReportEncoder`] that turns frames of [`InputEvent`]s into matching input
+//! reports. The kernel then reports the same events from the resulting evdev
+//! device.
+//!
+//! [`UHidDeviceInfo`]: crate::uhid::UHidDeviceInfo
+
+use crate::{
+    AbsoluteAxis, AbsoluteInfo, AbsoluteInfoSetup, DeviceCapabilities, EventRef,
+    EventTime, InputEvent, Key, LedEvent, LedKind, RelativeAxis, SynchronizeKind,
+};
+use crate::hid::{self, ButtonApplication, Usage, PAGE_BUTTON, PAGE_GENERIC_DESKTOP, PAGE_CONSUMER};
+
+const PAGE_SIMULATION: u16 = 0x02;
+const PAGE_LED: u16 = 0x08;
+const PAGE_DIGITIZER: u16 = 0x0d;
+
+const USAGE_HAT_SWITCH: u16 = 0x39;
+
+/// The top-level application of a HID device.
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum Application {
+    /// A keyboard.
+    Keyboard,
+    /// A mouse.
+    Mouse,
+    /// A joystick.
+    Joystick,
+    /// A gamepad.
+    Gamepad,
+    /// A pen digitizer, such as a drawing tablet.
+    Pen,
+    /// A single-touch touch screen.
+    TouchScreen,
+}
+
+impl Application {
+    /// The usage of the application collection.
+    pub const fn usage(&self) -> Usage {
+        match self {
+            Application::Keyboard => Usage::new(PAGE_GENERIC_DESKTOP, 0x06),
+            Application::Mouse => Usage::new(PAGE_GENERIC_DESKTOP, 0x02),
+            Application::Joystick => Usage::new(PAGE_GENERIC_DESKTOP, 0x04),
+            Application::Gamepad => Usage::new(PAGE_GENERIC_DESKTOP, 0x05),
+            Application::Pen => Usage::new(PAGE_DIGITIZER, 0x02),
+            Application::TouchScreen => Usage::new(PAGE_DIGITIZER, 0x04),
+        }
+    }
+
+    fn key_usage(&self, key: Key) -> Option<Usage> {
+        let digitizer = match key {
+            Key::ButtonTouch => Some(0x42),
+            Key::ButtonToolPen => Some(0x32),
+            Key::ButtonToolRubber => Some(0x3c),
+            Key::ButtonStylus => Some(0x44),
+            Key::ButtonStylus2 => Some(0x5a),
+            _ => None,
+        };
+        match (self, digitizer) {
+            (Application::Pen, Some(id)) | (Application::TouchScreen, Some(id)) =>
+                return Some(Usage::new(PAGE_DIGITIZER, id)),
+            _ => (),
+        }
+        if !key.is_button() {
+            return key.hid_usage()
+        }
+        if let Some(id) = hid::desktop_usage(key) {
+            return Some(Usage::new(PAGE_GENERIC_DESKTOP, id))
+        }
+        let (application, id) = hid::button_usage(key)?;
+        let compatible = match (self, application) {
+            (Application::Mouse, ButtonApplication::Mouse) => true,
+            (Application::Joystick, ButtonApplication::Joystick) => true,
+            (Application::Joystick, ButtonApplication::Gamepad) => id > 0x10,
+            (Application::Gamepad, ButtonApplication::Gamepad) => true,
+            (Application::Keyboard, ButtonApplication::Misc) => true,
+            _ => false,
+        };
+        match compatible {
+            true => Some(Usage::new(PAGE_BUTTON, id)),
+            false => None,
+        }
+    }
+}
+
+fn relative_usage(axis: RelativeAxis) -> Option<Usage> {
+    Some(match axis {
+        RelativeAxis::X => Usage::new(PAGE_GENERIC_DESKTOP, 0x30),
+        RelativeAxis::Y => Usage::new(PAGE_GENERIC_DESKTOP, 0x31),
+        RelativeAxis::Z => Usage::new(PAGE_GENERIC_DESKTOP, 0x32),
+        RelativeAxis::RX => Usage::new(PAGE_GENERIC_DESKTOP, 0x33),
+        RelativeAxis::RY => Usage::new(PAGE_GENERIC_DESKTOP, 0x34),
+        RelativeAxis::RZ => Usage::new(PAGE_GENERIC_DESKTOP, 0x35),
+        RelativeAxis::Dial => Usage::new(PAGE_GENERIC_DESKTOP, 0x37),
+        RelativeAxis::Wheel => Usage::new(PAGE_GENERIC_DESKTOP, 0x38),
+        RelativeAxis::HorizontalWheel => Usage::new(PAGE_CONSUMER, 0x238),
+        _ => return None,
+    })
+}
+
+fn absolute_usage(axis: AbsoluteAxis) -> Option<Usage> {
+    Some(match axis {
+        AbsoluteAxis::X => Usage::new(PAGE_GENERIC_DESKTOP, 0x30),
+        AbsoluteAxis::Y => Usage::new(PAGE_GENERIC_DESKTOP, 0x31),
+        AbsoluteAxis::Z => Usage::new(PAGE_GENERIC_DESKTOP, 0x32),
+        AbsoluteAxis::RX => Usage::new(PAGE_GENERIC_DESKTOP, 0x33),
+        AbsoluteAxis::RY => Usage::new(PAGE_GENERIC_DESKTOP, 0x34),
+        AbsoluteAxis::RZ => Usage::new(PAGE_GENERIC_DESKTOP, 0x35),
+        AbsoluteAxis::Wheel => Usage::new(PAGE_GENERIC_DESKTOP, 0x38),
+        AbsoluteAxis::Rudder => Usage::new(PAGE_SIMULATION, 0xba),
+        AbsoluteAxis::Throttle => Usage::new(PAGE_SIMULATION, 0xbb),
+        AbsoluteAxis::Gas => Usage::new(PAGE_SIMULATION, 0xc4),
+        AbsoluteAxis::Brake => Usage::new(PAGE_SIMULATION, 0xc5),
+        AbsoluteAxis::Pressure => Usage::new(PAGE_DIGITIZER, 0x30),
+        AbsoluteAxis::TiltX => Usage::new(PAGE_DIGITIZER, 0x3d),
+        AbsoluteAxis::TiltY => Usage::new(PAGE_DIGITIZER, 0x3e),
+        AbsoluteAxis::Volume => Usage::new(PAGE_CONSUMER, 0xe0),
+        _ => return None,
+    })
+}
+
+fn led_usage(led: LedKind) -> Option<u16> {
+    Some(match led {
+        LedKind::NumLock => 0x01,
+        LedKind::CapsLock => 0x02,
+        LedKind::ScrollLock => 0x03,
+        LedKind::Compose => 0x04,
+        LedKind::Kana => 0x05,
+        LedKind::Mute => 0x09,
+        LedKind::Sleep => 0x27,
+        LedKind::Misc => 0x4b,
+        LedKind::Suspend => 0x4c,
+        LedKind::Mail => 0x19,
+        LedKind::Charging => 0x4d,
+        _ => return None,
+    })
+}
+
+// item prefixes, including the item type
+const USAGE_PAGE: u8 = 0x04;
+const LOGICAL_MINIMUM: u8 = 0x14;
+const LOGICAL_MAXIMUM: u8 = 0x24;
+const REPORT_SIZE: u8 = 0x74;
+const REPORT_ID: u8 = 0x84;
+const REPORT_COUNT: u8 = 0x94;
+const USAGE: u8 = 0x08;
+const INPUT: u8 = 0x80;
+const OUTPUT: u8 = 0x90;
+const COLLECTION: u8 = 0xa0;
+const END_COLLECTION: u8 = 0xc0;
+
+// main item flags
+const CONSTANT: u32 = 0x01;
+const VARIABLE: u32 = 0x02;
+const RELATIVE: u32 = 0x04;
+const NULL_STATE: u32 = 0x40;
+
+struct Items(Vec<u8>);
+
+impl Items {
+    fn unsigned(&mut self, prefix: u8, value: u32) {
+        let bytes = value.to_le_bytes();
+        match value {
+            0..=0xff => self.data(prefix | 1, &bytes[..1]),
+            0x100..=0xffff => self.data(prefix | 2, &bytes[..2]),
+            _ => self.data(prefix | 3, &bytes),
+        }
+    }
+
+    fn signed(&mut self, prefix: u8, value: i32) {
+        let bytes = value.to_le_bytes();
+        match value {
+            -0x80..=0x7f => self.data(prefix | 1, &bytes[..1]),
+            -0x8000..=0x7fff => self.data(prefix | 2, &bytes[..2]),
+            _ => self.data(prefix | 3, &bytes),
+        }
+    }
+
+    fn data(&mut self, prefix: u8, data: &[u8]) {
+        self.0.push(prefix);
+        self.0.extend_from_slice(data);
+    }
+
+    fn main(&mut self, prefix: u8, flags: u32, size: u32, count: u32, minimum: i32, maximum: i32) {
+        self.signed(LOGICAL_MINIMUM, minimum);
+        self.signed(LOGICAL_MAXIMUM, maximum);
+        self.unsigned(REPORT_SIZE, size);
+        self.unsigned(REPORT_COUNT, count);
+        self.unsigned(prefix, flags);
+    }
+
+    fn padding(&mut self, prefix: u8, bits: usize) {
+        let remainder = bits % 8;
+        if remainder > 0 {
+            self.unsigned(REPORT_SIZE, (8 - remainder) as u32);
+            self.unsigned(REPORT_COUNT, 1);
+            self.unsigned(prefix, CONSTANT);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Target {
+    Key(Key),
+    Relative(RelativeAxis),
+    Absolute(AbsoluteAxis),
+    Hat,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    target: Target,
+    offset: usize,
+    size: usize,
+}
+
+/// The size in bits of a field able to hold the range of an axis.
+fn field_size(info: &AbsoluteInfo) -> usize {
+    let fits = |bits: u32| if info.minimum < 0 {
+        let max = (1i64 << (bits - 1)) - 1;
+        info.minimum as i64 >= -max - 1 && info.maximum as i64 <= max
+    } else {
+        (info.maximum as i64) < (1i64 << bits)
+    };
+    [8, 16].iter().cloned().find(|&bits| fits(bits)).unwrap_or(32) as usize
+}
+
+/// Describes the reports of a HID device.
+#[derive(Clone, Debug)]
+pub struct ReportDescriptor {
+    application: Application,
+    report_id: Option<u8>,
+    keys: Vec<(Key, Usage)>,
+    relative: Vec<(RelativeAxis, Usage)>,
+    absolute: Vec<(AbsoluteAxis, AbsoluteInfo, Usage)>,
+    hat: bool,
+    leds: Vec<(LedKind, u16)>,
+}
+
+impl ReportDescriptor {
+    /// An empty descriptor for the given application.
+    pub fn new(application: Application) -> Self {
+        ReportDescriptor {
+            application,
+            report_id: None,
+            keys: Default::default(),
+            relative: Default::default(),
+            absolute: Default::default(),
+            hat: false,
+            leds: Default::default(),
+        }
+    }
+
+    /// Describes as many of a device's capabilities as possible.
+    ///
+    /// Capabilities without a HID equivalent for the application, such as
+    /// multitouch axes, are left out.
+    pub fn from_capabilities(application: Application, caps: &DeviceCapabilities) -> Self {
+        let mut desc = Self::new(application);
+        for key in &caps.keys {
+            desc.add_key(key);
+        }
+        for axis in &caps.relative {
+            desc.add_relative(axis);
+        }
+        for abs in &caps.absolute {
+            desc.add_absolute(abs);
+        }
+        for led in &caps.leds {
+            desc.add_led(led);
+        }
+        desc
+    }
+
+    /// Prefixes the reports with a report ID.
+    pub fn with_report_id(self, report_id: u8) -> Self {
+        ReportDescriptor { report_id: Some(report_id), ..self }
+    }
+
+    /// The application of the device.
+    pub fn application(&self) -> Application {
+        self.application
+    }
+
+    /// Adds a key or button, returning whether it can be described.
+    pub fn add_key(&mut self, key: Key) -> bool {
+        match self.application.key_usage(key) {
+            Some(usage) if !self.keys.iter().any(|&(k, _)| k == key) => {
+                self.keys.push((key, usage));
+                true
+            },
+            Some(..) => true,
+            None => false,
+        }
+    }
+
+    /// Adds a relative axis, returning whether it can be described.
+    pub fn add_relative(&mut self, axis: RelativeAxis) -> bool {
+        match relative_usage(axis) {
+            Some(usage) => {
+                self.relative.retain(|&(a, _)| a != axis);
+                self.relative.push((axis, usage));
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Adds an absolute axis, returning whether it can be described.
+    ///
+    /// `ABS_HAT0X` and `ABS_HAT0Y` are described together as a hat switch.
+    pub fn add_absolute(&mut self, setup: &AbsoluteInfoSetup) -> bool {
+        match (setup.axis, absolute_usage(setup.axis)) {
+            (AbsoluteAxis::Hat0X, _) | (AbsoluteAxis::Hat0Y, _) => {
+                self.hat = true;
+                true
+            },
+            (axis, Some(usage)) => {
+                self.absolute.retain(|&(a, ..)| a != axis);
+                self.absolute.push((axis, setup.info, usage));
+                true
+            },
+            (_, None) => false,
+        }
+    }
+
+    /// Adds an LED to the output report, returning whether it can be
+    /// described.
+    pub fn add_led(&mut self, led: LedKind) -> bool {
+        match led_usage(led) {
+            Some(usage) => {
+                self.leds.retain(|&(l, _)| l != led);
+                self.leds.push((led, usage));
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn build(&self) -> (Vec<u8>, Vec<Slot>, usize) {
+        let mut items = Items(Vec::new());
+        let mut slots = Vec::new();
+        let usage = self.application.usage();
+        items.unsigned(USAGE_PAGE, usage.page as u32);
+        items.unsigned(USAGE, usage.id as u32);
+        items.unsigned(COLLECTION, 0x01);
+        let mut bit = 0;
+        if let Some(id) = self.report_id {
+            items.unsigned(REPORT_ID, id as u32);
+            bit += 8;
+        }
+
+        let mut keys = self.keys.clone();
+        keys.sort_by_key(|&(key, usage)| (usage, key));
+        let mut keys = &keys[..];
+        while let Some(&(_, first)) = keys.first() {
+            let len = keys.iter().take_while(|&&(_, usage)| usage.page == first.page).count();
+            let (page, rest) = keys.split_at(len);
+            items.unsigned(USAGE_PAGE, first.page as u32);
+            for &(key, usage) in page {
+                items.unsigned(USAGE, usage.id as u32);
+                slots.push(Slot { target: Target::Key(key), offset: bit, size: 1 });
+                bit += 1;
+            }
+            items.main(INPUT, VARIABLE, 1, len as u32, 0, 1);
+            keys = rest;
+        }
+        items.padding(INPUT, bit);
+        bit += match bit % 8 {
+            0 => 0,
+            remainder => 8 - remainder,
+        };
+
+        for &(axis, usage) in &self.relative {
+            items.unsigned(USAGE_PAGE, usage.page as u32);
+            items.unsigned(USAGE, usage.id as u32);
+            items.main(INPUT, VARIABLE | RELATIVE, 16, 1, -0x7fff, 0x7fff);
+            slots.push(Slot { target: Target::Relative(axis), offset: bit, size: 16 });
+            bit += 16;
+        }
+
+        for &(axis, ref info, usage) in &self.absolute {
+            let size = field_size(info);
+            items.unsigned(USAGE_PAGE, usage.page as u32);
+            items.unsigned(USAGE, usage.id as u32);
+            items.main(INPUT, VARIABLE, size as u32, 1, info.minimum, info.maximum);
+            slots.push(Slot { target: Target::Absolute(axis), offset: bit, size });
+            bit += size;
+        }
+
+        if self.hat {
+            items.unsigned(USAGE_PAGE, PAGE_GENERIC_DESKTOP as u32);
+            items.unsigned(USAGE, USAGE_HAT_SWITCH as u32);
+            items.main(INPUT, VARIABLE | NULL_STATE, 8, 1, 0, 7);
+            slots.push(Slot { target: Target::Hat, offset: bit, size: 8 });
+            bit += 8;
+        }
+
+        if !self.leds.is_empty() {
+            items.unsigned(USAGE_PAGE, PAGE_LED as u32);
+            for &(_, usage) in &self.leds {
+                items.unsigned(USAGE, usage as u32);
+            }
+            items.main(OUTPUT, VARIABLE, 1, self.leds.len() as u32, 0, 1);
+            items.padding(OUTPUT, self.leds.len());
+        }
+
+        items.0.push(END_COLLECTION);
+        (items.0, slots, bit / 8)
+    }
+
+    /// The report descriptor.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.build().0
+    }
+
+    /// The size of an input report, including the report ID.
+    pub fn input_report_len(&self) -> usize {
+        self.build().2
+    }
+
+    /// Creates an encoder for the input reports of this descriptor.
+    pub fn encoder(&self) -> ReportEncoder {
+        let (_, slots, len) = self.build();
+        let mut report = vec![0u8; len];
+        if let Some(id) = self.report_id {
+            report[0] = id;
+        }
+        let mut encoder = ReportEncoder {
+            slots,
+            report,
+            hat: (0, 0),
+        };
+        encoder.set_hat(0, 0);
+        encoder
+    }
+
+    /// Decodes an LED output report into LED events.
+    pub fn led_events(&self, report: &[u8], time: EventTime) -> Vec<InputEvent> {
+        let report = match self.report_id {
+            Some(id) => match report.split_first() {
+                Some((&first, rest)) if first == id => rest,
+                _ => return Vec::new(),
+            },
+            None => report,
+        };
+        self.leds.iter().enumerate()
+            .filter(|&(bit, _)| bit / 8 < report.len())
+            .map(|(bit, &(led, _))| {
+                let on = report[bit / 8] & (1 << (bit % 8)) != 0;
+                LedEvent::new(time, led, on as i32).into()
+            }).collect()
+    }
+}
+
+/// Encodes frames of input events into HID input reports.
+#[derive(Clone, Debug)]
+pub struct ReportEncoder {
+    slots: Vec<Slot>,
+    report: Vec<u8>,
+    hat: (i32, i32),
+}
+
+fn write_bits(report: &mut [u8], offset: usize, size: usize, value: u32) {
+    for i in 0..size {
+        let bit = offset + i;
+        let mask = 1 << (bit % 8);
+        if value & (1 << i) != 0 {
+            report[bit / 8] |= mask;
+        } else {
+            report[bit / 8] &= !mask;
+        }
+    }
+}
+
+fn read_bits(report: &[u8], offset: usize, size: usize) -> u32 {
+    (0..size).fold(0, |value, i| {
+        let bit = offset + i;
+        value | (((report[bit / 8] >> (bit % 8)) as u32 & 1) << i)
+    })
+}
+
+impl ReportEncoder {
+    fn slot(&self, f: impl Fn(&Target) -> bool) -> Option<Slot> {
+        self.slots.iter().find(|slot| f(&slot.target)).cloned()
+    }
+
+    fn set_hat(&mut self, x: i32, y: i32) {
+        self.hat = (x, y);
+        let value = match (x.signum(), y.signum()) {
+            (0, -1) => 0,
+            (1, -1) => 1,
+            (1, 0) => 2,
+            (1, 1) => 3,
+            (0, 1) => 4,
+            (-1, 1) => 5,
+            (-1, 0) => 6,
+            (-1, -1) => 7,
+            _ => 8,
+        };
+        if let Some(slot) = self.slot(|t| matches!(t, Target::Hat)) {
+            write_bits(&mut self.report, slot.offset, slot.size, value);
+        }
+    }
+
+    /// Updates the pending report with an event.
+    ///
+    /// Returns the report when a `SYN_REPORT` is received. Relative axes are
+    /// accumulated until then, and reset afterwards.
+    pub fn push_event(&mut self, event: &InputEvent) -> Option<Vec<u8>> {
+        match EventRef::new(event) {
+            Ok(EventRef::Key(event)) => if let Some(slot) = self.slot(|t| matches!(t, Target::Key(k) if *k == event.key)) {
+                write_bits(&mut self.report, slot.offset, 1, (event.value.value != 0) as u32);
+            },
+            Ok(EventRef::Relative(event)) => if let Some(slot) = self.slot(|t| matches!(t, Target::Relative(a) if *a == event.axis)) {
+                let value = read_bits(&self.report, slot.offset, slot.size) as u16 as i16 as i32;
+                let value = (value + event.value).clamp(-0x7fff, 0x7fff);
+                write_bits(&mut self.report, slot.offset, slot.size, value as u32);
+            },
+            Ok(EventRef::Absolute(event)) => match event.axis {
+                AbsoluteAxis::Hat0X => self.set_hat(event.value, self.hat.1),
+                AbsoluteAxis::Hat0Y => self.set_hat(self.hat.0, event.value),
+                axis => if let Some(slot) = self.slot(|t| matches!(t, Target::Absolute(a) if *a == axis)) {
+                    write_bits(&mut self.report, slot.offset, slot.size, event.value as u32);
+                },
+            },
+            Ok(EventRef::Synchronize(sync)) if sync.kind == SynchronizeKind::Report => {
+                let report = self.report.clone();
+                for slot in &self.slots {
+                    if let Target::Relative(..) = slot.target {
+                        write_bits(&mut self.report, slot.offset, slot.size, 0);
+                    }
+                }
+                return Some(report)
+            },
+            _ => (),
+        }
+        None
+    }
+
+    /// The current state of the input report.
+    pub fn report(&self) -> &[u8] {
+        &self.report
+    }
+}
+
+#[test]
+fn report_descriptor_mouse() {
+    use crate::{KeyEvent, KeyState, RelativeEvent, SynchronizeEvent};
+
+    let mut desc = ReportDescriptor::new(Application::Mouse);
+    assert!(desc.add_key(Key::ButtonLeft));
+    assert!(desc.add_key(Key::ButtonRight));
+    assert!(!desc.add_key(Key::ButtonSouth));
+    assert!(desc.add_relative(RelativeAxis::X));
+    assert!(desc.add_relative(RelativeAxis::Y));
+    assert_eq!(desc.to_bytes(), [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01,
+        0x05, 0x09, 0x09, 0x01, 0x09, 0x02,
+        0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x02, 0x81, 0x02,
+        0x75, 0x06, 0x95, 0x01, 0x81, 0x01,
+        0x05, 0x01, 0x09, 0x30, 0x16, 0x01, 0x80, 0x26, 0xff, 0x7f, 0x75, 0x10, 0x95, 0x01, 0x81, 0x06,
+        0x05, 0x01, 0x09, 0x31, 0x16, 0x01, 0x80, 0x26, 0xff, 0x7f, 0x75, 0x10, 0x95, 0x01, 0x81, 0x06,
+        0xc0,
+    ][..]);
+
+    let time = EventTime::default();
+    let mut encoder = desc.encoder();
+    assert_eq!(encoder.push_event(&KeyEvent::new(time, Key::ButtonRight, KeyState::PRESSED).into()), None);
+    encoder.push_event(&RelativeEvent::new(time, RelativeAxis::X, 3).into());
+    encoder.push_event(&RelativeEvent::new(time, RelativeAxis::X, 2).into());
+    encoder.push_event(&RelativeEvent::new(time, RelativeAxis::Y, -1).into());
+    let report = encoder.push_event(&SynchronizeEvent::report(time).into());
+    assert_eq!(report.as_deref(), Some(&[0x02, 0x05, 0x00, 0xff, 0xff][..]));
+    assert_eq!(encoder.report(), [0x02, 0x00, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn report_descriptor_leds() {
+    let mut desc = ReportDescriptor::new(Application::Keyboard).with_report_id(2);
+    assert!(desc.add_led(LedKind::NumLock));
+    assert!(desc.add_led(LedKind::CapsLock));
+    assert!(desc.add_led(LedKind::Sleep));
+    assert!(desc.add_led(LedKind::Charging));
+    assert!(desc.add_led(LedKind::Misc));
+    assert!(!desc.add_led(LedKind::UnknownB));
+    assert_eq!(desc.to_bytes(), [
+        0x05, 0x01, 0x09, 0x06, 0xa1, 0x01,
+        0x85, 0x02,
+        0x05, 0x08, 0x09, 0x01, 0x09, 0x02, 0x09, 0x27, 0x09, 0x4d, 0x09, 0x4b,
+        0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x05, 0x91, 0x02,
+        0x75, 0x03, 0x95, 0x01, 0x91, 0x01,
+        0xc0,
+    ][..]);
+
+    let time = EventTime::default();
+    let led = |led, value| InputEvent::from(LedEvent::new(time, led, value));
+    assert_eq!(desc.led_events(&[0x02, 0b11010], time), [
+        led(LedKind::NumLock, 0),
+        led(LedKind::CapsLock, 1),
+        led(LedKind::Sleep, 0),
+        led(LedKind::Charging, 1),
+        led(LedKind::Misc, 1),
+    ]);
+    assert!(desc.led_events(&[0x01, 0xff], time).is_empty());
+    assert!(desc.led_events(&[0x02], time).is_empty());
+}
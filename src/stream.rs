@@ -0,0 +1,506 @@
+//! Generic sources and sinks of input events.
+//!
+//! [`InputSource`] and [`InputSink`] abstract over where events come from and
+//! where they go, such as evdev and uinput devices, recordings serialized with
+//! an [`EventCodec`](crate::EventCodec), or in-memory mocks. Enabling the
+//! `tokio-1` feature additionally provides [`AsyncInputSource`] and
+//! [`AsyncInputSink`], implemented by devices registered with tokio's
+//! [`AsyncFd`](tokio_1::io::unix::AsyncFd) and, along with a `tokio-util`
+//! feature, by `FramedRead` and `FramedWrite` using an `EventCodec`.
+
+use std::io;
+use std::time::Duration;
+use std::collections::VecDeque;
+use std::os::unix::io::AsRawFd;
+use crate::{
+    EvdevHandle, UInputHandle, UInputWriter, InputEvent, EventKind,
+    SynchronizeKind,
+};
+use crate::evdev::EvdevDevice;
+use crate::mock::MockDevice;
+
+/// Whether the event is a `SYN_REPORT` ending a frame.
+pub(crate) fn is_report(event: &InputEvent) -> bool {
+    event.kind == EventKind::Synchronize && event.code == SynchronizeKind::Report as u16
+}
+
+/// Writes all events to `uinput`, retrying short writes.
+pub(crate) fn write_all<F: AsRawFd>(uinput: &UInputHandle<F>, events: &[InputEvent]) -> io::Result<()> {
+    let events: Vec<_> = events.iter().map(|e| *e.as_raw()).collect();
+    let mut written = 0;
+    while written < events.len() {
+        match uinput.write(&events[written..]) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write events")),
+            Ok(count) => written += count,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Produces input events.
+pub trait InputSource {
+    /// Reads the next event, or `None` once the source has ended.
+    fn read_event(&mut self) -> io::Result<Option<InputEvent>>;
+
+    /// Reads events up to and including the next `SYN_REPORT`.
+    ///
+    /// Returns `None` once the source has ended, discarding any incomplete
+    /// frame.
+    fn read_frame(&mut self) -> io::Result<Option<Vec<InputEvent>>> {
+        let mut frame = Vec::new();
+        while let Some(event) = self.read_event()? {
+            frame.push(event);
+            if is_report(&event) {
+                return Ok(Some(frame))
+            }
+        }
+        Ok(None)
+    }
+
+    /// Waits until an event can be read, returning `false` if the timeout
+    /// elapsed first. A timeout of `None` waits indefinitely.
+    ///
+    /// Sources that never block, such as recordings, are always ready.
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let _ = timeout;
+        Ok(true)
+    }
+}
+
+/// Consumes input events.
+pub trait InputSink {
+    /// Writes a sequence of events.
+    fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()>;
+
+    /// Writes a single event.
+    fn write_event(&mut self, event: &InputEvent) -> io::Result<()> {
+        self.write_events(std::slice::from_ref(event))
+    }
+
+    /// Flushes any buffered events.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: InputSource + ?Sized> InputSource for &'_ mut S {
+    fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+        (**self).read_event()
+    }
+
+    fn read_frame(&mut self) -> io::Result<Option<Vec<InputEvent>>> {
+        (**self).read_frame()
+    }
+
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        (**self).wait_readable(timeout)
+    }
+}
+
+impl<S: InputSink + ?Sized> InputSink for &'_ mut S {
+    fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        (**self).write_events(events)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<F: AsRawFd> InputSource for EvdevHandle<F> {
+    fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+        match self.read_input_event() {
+            Ok(event) => Ok(Some(event)),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        EvdevHandle::wait_readable(self, timeout)
+    }
+}
+
+impl<F: AsRawFd> InputSink for UInputHandle<F> {
+    fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        write_all(self, events)
+    }
+}
+
+impl<F: AsRawFd> InputSink for UInputWriter<F> {
+    fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        events.iter().try_for_each(|event| self.emit(event))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sync()
+    }
+}
+
+/// Reads scripted events until the queue is empty, at which point the mock
+/// source ends.
+impl InputSource for MockDevice {
+    fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+        match self.read_input_event() {
+            Ok(event) => Ok(Some(event)),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl InputSource for VecDeque<InputEvent> {
+    fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+        Ok(self.pop_front())
+    }
+}
+
+/// Collects every written event.
+impl InputSink for Vec<InputEvent> {
+    fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.extend_from_slice(events);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "codec")]
+mod codec_impl {
+    use super::*;
+    use std::io::{Read, Write};
+    use crate::EventCodec;
+
+    /// Reads events serialized by an [`EventCodec`], such as a recording.
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "codec")))]
+    pub struct EventReader<R> {
+        reader: R,
+        codec: EventCodec,
+        buffer: Vec<u8>,
+    }
+
+    impl<R> EventReader<R> {
+        /// Reads events in the native layout.
+        pub fn new(reader: R) -> Self {
+            Self::with_codec(reader, EventCodec::new())
+        }
+
+        /// Reads events using the given codec.
+        pub fn with_codec(reader: R, codec: EventCodec) -> Self {
+            EventReader {
+                reader,
+                buffer: vec![0; codec.event_size()],
+                codec,
+            }
+        }
+
+        /// Extracts the underlying reader.
+        pub fn into_inner(self) -> R {
+            self.reader
+        }
+    }
+
+    impl<R: Read> InputSource for EventReader<R> {
+        fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+            loop {
+                let mut len = 0;
+                while len < self.buffer.len() {
+                    match self.reader.read(&mut self.buffer[len..]) {
+                        Ok(0) if len == 0 => return Ok(None),
+                        Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated event")),
+                        Ok(read) => len += read,
+                        Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                        Err(err) => return Err(err),
+                    }
+                }
+                if let Some(event) = self.codec.decode_event(&self.buffer)? {
+                    return Ok(Some(event))
+                }
+            }
+        }
+    }
+
+    /// Writes events serialized by an [`EventCodec`], such as a recording.
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "codec")))]
+    pub struct EventWriter<W> {
+        writer: W,
+        codec: EventCodec,
+        buffer: Vec<u8>,
+    }
+
+    impl<W> EventWriter<W> {
+        /// Writes events in the native layout.
+        pub fn new(writer: W) -> Self {
+            Self::with_codec(writer, EventCodec::new())
+        }
+
+        /// Writes events using the given codec.
+        pub fn with_codec(writer: W, codec: EventCodec) -> Self {
+            EventWriter {
+                writer,
+                codec,
+                buffer: Vec::new(),
+            }
+        }
+
+        /// Extracts the underlying writer.
+        pub fn into_inner(self) -> W {
+            self.writer
+        }
+
+        fn encode(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            let size = self.codec.event_size();
+            self.buffer.clear();
+            self.buffer.resize(size * events.len(), 0);
+            for (event, dst) in events.iter().zip(self.buffer.chunks_exact_mut(size)) {
+                self.codec.try_encode_slice(event, dst)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl<W: Write> InputSink for EventWriter<W> {
+        fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            self.encode(events)?;
+            self.writer.write_all(&self.buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.writer.flush()
+        }
+    }
+
+    #[cfg(feature = "tokio-1")]
+    mod tokio_impl {
+        use super::*;
+        use std::future::Future;
+        use tokio_1::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+        use crate::stream::{AsyncInputSource, AsyncInputSink};
+
+        impl<R: AsyncRead + Unpin + Send> AsyncInputSource for EventReader<R> {
+            async fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+                loop {
+                    let mut len = 0;
+                    while len < self.buffer.len() {
+                        match self.reader.read(&mut self.buffer[len..]).await {
+                            Ok(0) if len == 0 => return Ok(None),
+                            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated event")),
+                            Ok(read) => len += read,
+                            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                            Err(err) => return Err(err),
+                        }
+                    }
+                    if let Some(event) = self.codec.decode_event(&self.buffer)? {
+                        return Ok(Some(event))
+                    }
+                }
+            }
+        }
+
+        impl<W: AsyncWrite + Unpin + Send> AsyncInputSink for EventWriter<W> {
+            async fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+                self.encode(events)?;
+                self.writer.write_all(&self.buffer).await
+            }
+
+            fn flush(&mut self) -> impl Future<Output=io::Result<()>> + Send {
+                self.writer.flush()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "codec")]
+pub use self::codec_impl::{EventReader, EventWriter};
+
+#[cfg(feature = "tokio-1")]
+mod tokio_impl {
+    use super::*;
+    use std::future::Future;
+    use tokio_1::io::unix::AsyncFd;
+
+    /// Asynchronously produces input events.
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "tokio-1")))]
+    pub trait AsyncInputSource: Send {
+        /// Reads the next event, or `None` once the source has ended.
+        fn read_event(&mut self) -> impl Future<Output=io::Result<Option<InputEvent>>> + Send;
+
+        /// Reads events up to and including the next `SYN_REPORT`.
+        fn read_frame(&mut self) -> impl Future<Output=io::Result<Option<Vec<InputEvent>>>> + Send {
+            async move {
+                let mut frame = Vec::new();
+                while let Some(event) = self.read_event().await? {
+                    frame.push(event);
+                    if is_report(&event) {
+                        return Ok(Some(frame))
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Asynchronously consumes input events.
+    #[cfg_attr(feature = "dox", doc(cfg(feature = "tokio-1")))]
+    pub trait AsyncInputSink: Send {
+        /// Writes a sequence of events.
+        fn write_events(&mut self, events: &[InputEvent]) -> impl Future<Output=io::Result<()>> + Send;
+
+        /// Flushes any buffered events.
+        fn flush(&mut self) -> impl Future<Output=io::Result<()>> + Send {
+            async { Ok(()) }
+        }
+    }
+
+    impl<S: AsyncInputSource + ?Sized> AsyncInputSource for &'_ mut S {
+        fn read_event(&mut self) -> impl Future<Output=io::Result<Option<InputEvent>>> + Send {
+            (**self).read_event()
+        }
+    }
+
+    impl<S: AsyncInputSink + ?Sized> AsyncInputSink for &'_ mut S {
+        fn write_events(&mut self, events: &[InputEvent]) -> impl Future<Output=io::Result<()>> + Send {
+            (**self).write_events(events)
+        }
+
+        fn flush(&mut self) -> impl Future<Output=io::Result<()>> + Send {
+            (**self).flush()
+        }
+    }
+
+    /// Reads from an evdev device opened with `O_NONBLOCK`.
+    impl<F: AsRawFd + Send + Sync> AsyncInputSource for AsyncFd<EvdevHandle<F>> {
+        async fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+            loop {
+                let mut guard = self.readable().await?;
+                match guard.try_io(|evdev| evdev.get_ref().read_input_event()) {
+                    Ok(Ok(event)) => return Ok(Some(event)),
+                    Ok(Err(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Ok(Err(err)) => return Err(err),
+                    Err(_would_block) => (),
+                }
+            }
+        }
+    }
+
+    /// Writes to a uinput device opened with `O_NONBLOCK`.
+    impl<F: AsRawFd + Send + Sync> AsyncInputSink for AsyncFd<UInputHandle<F>> {
+        async fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            let events: Vec<_> = events.iter().map(|e| *e.as_raw()).collect();
+            let mut written = 0;
+            while written < events.len() {
+                let mut guard = self.writable().await?;
+                match guard.try_io(|uinput| uinput.get_ref().write(&events[written..])) {
+                    Ok(Ok(0)) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write events")),
+                    Ok(Ok(count)) => written += count,
+                    Ok(Err(err)) => return Err(err),
+                    Err(_would_block) => (),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl AsyncInputSource for MockDevice {
+        async fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+            InputSource::read_event(self)
+        }
+    }
+
+    impl AsyncInputSource for VecDeque<InputEvent> {
+        async fn read_event(&mut self) -> io::Result<Option<InputEvent>> {
+            Ok(self.pop_front())
+        }
+    }
+
+    impl AsyncInputSink for Vec<InputEvent> {
+        async fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            InputSink::write_events(self, events)
+        }
+    }
+}
+
+#[cfg(feature = "tokio-1")]
+pub use self::tokio_impl::{AsyncInputSource, AsyncInputSink};
+
+#[cfg(all(feature = "tokio-1", feature = "tokio-util-0_6"))]
+mod tokio_util_impl_0_6 {
+    use tokio_util_0_6::codec::{FramedRead, FramedWrite};
+    include!("stream_framed.rs");
+}
+
+#[cfg(all(feature = "tokio-1", feature = "tokio-util-0_7"))]
+mod tokio_util_impl_0_7 {
+    use tokio_util_0_7::codec::{FramedRead, FramedWrite};
+    include!("stream_framed.rs");
+}
+
+#[test]
+fn input_source_frames() {
+    use crate::{EventTime, Key, KeyEvent, KeyState, SynchronizeEvent};
+
+    let time = EventTime::default();
+    let frame: Vec<InputEvent> = vec![
+        KeyEvent::new(time, Key::A, KeyState::PRESSED).into(),
+        SynchronizeEvent::report(time).into(),
+    ];
+    let mut source: VecDeque<InputEvent> = frame.iter().cloned()
+        .chain(Some(KeyEvent::new(time, Key::A, KeyState::RELEASED).into()))
+        .collect();
+    let mut sink = Vec::new();
+    while let Some(frame) = InputSource::read_frame(&mut source).unwrap() {
+        InputSink::write_events(&mut sink, &frame).unwrap();
+    }
+    assert_eq!(sink, frame);
+}
+
+#[cfg(feature = "tokio-1")]
+#[test]
+fn async_round_trip() {
+    use std::future::Future;
+    use std::os::unix::net::UnixStream;
+    use tokio_1::io::unix::AsyncFd;
+    use crate::{EventTime, Key, KeyEvent, KeyState, SynchronizeEvent};
+
+    fn send<F: Future + Send>(future: F) -> F {
+        future
+    }
+
+    let time = EventTime::new(1, 2);
+    let frame: Vec<InputEvent> = vec![
+        KeyEvent::new(time, Key::A, KeyState::PRESSED).into(),
+        SynchronizeEvent::report(time).into(),
+    ];
+    let runtime = tokio_1::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+    runtime.block_on(async {
+        let mut writer = EventWriter::new(Vec::new());
+        send(AsyncInputSink::write_events(&mut writer, &frame)).await.unwrap();
+        send(AsyncInputSink::flush(&mut writer)).await.unwrap();
+        let bytes = writer.into_inner();
+        let mut reader = EventReader::new(&bytes[..]);
+        assert_eq!(send(AsyncInputSource::read_frame(&mut reader)).await.unwrap(), Some(frame.clone()));
+        assert_eq!(AsyncInputSource::read_event(&mut reader).await.unwrap(), None);
+
+        let (tx, rx) = UnixStream::pair().unwrap();
+        tx.set_nonblocking(true).unwrap();
+        rx.set_nonblocking(true).unwrap();
+        let mut uinput = AsyncFd::new(UInputHandle::new(tx)).unwrap();
+        let mut evdev = AsyncFd::new(EvdevHandle::new(rx)).unwrap();
+        send(uinput.write_events(&frame)).await.unwrap();
+        assert_eq!(send(evdev.read_frame()).await.unwrap(), Some(frame.clone()));
+
+        #[cfg(feature = "tokio-util-0_7")]
+        {
+            use tokio_util_0_7::codec::{FramedRead, FramedWrite};
+            use crate::EventCodec;
+
+            let mut writer = FramedWrite::new(Vec::new(), EventCodec::new());
+            send(writer.write_events(&frame)).await.unwrap();
+            send(AsyncInputSink::flush(&mut writer)).await.unwrap();
+            assert_eq!(writer.get_ref(), &bytes);
+            let mut reader = FramedRead::new(&bytes[..], EventCodec::new());
+            assert_eq!(send(reader.read_frame()).await.unwrap(), Some(frame.clone()));
+            assert_eq!(reader.read_event().await.unwrap(), None);
+        }
+    });
+}
@@ -0,0 +1,28 @@
+use crate::{EventCodec, InputEvent};
+use crate::stream::{AsyncInputSource, AsyncInputSink};
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio_1::io::{AsyncRead, AsyncWrite};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::io;
+
+impl<R: AsyncRead + Unpin + Send> AsyncInputSource for FramedRead<R, EventCodec> {
+    fn read_event(&mut self) -> impl Future<Output=io::Result<Option<InputEvent>>> + Send {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_next(cx).map(Option::transpose))
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncInputSink for FramedWrite<W, EventCodec> {
+    async fn write_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        for &event in events {
+            poll_fn(|cx| Sink::<InputEvent>::poll_ready(Pin::new(&mut *self), cx)).await?;
+            Pin::new(&mut *self).start_send(event)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> impl Future<Output=io::Result<()>> + Send {
+        poll_fn(move |cx| Sink::<InputEvent>::poll_flush(Pin::new(&mut *self), cx))
+    }
+}
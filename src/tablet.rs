@@ -0,0 +1,498 @@
+//! Virtual absolute pointing devices: pen tablets and touchscreens.
+//!
+//! These are mainly useful for injecting absolute pointer motion, such as
+//! from a remote desktop server.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+use crate::{
+    UInputHandle, UInputWriter, InputId, InputEvent, InputProperty, EventTime, Key, KeyEvent,
+    KeyState, AbsoluteAxis, AbsoluteEvent, AbsoluteInfo, AbsoluteInfoSetup,
+    DeviceCapabilities,
+};
+
+/// The maximum pressure reported by a [`VirtualTablet`].
+pub const PRESSURE_MAX: i32 = 4095;
+
+/// The maximum tilt in degrees reported by a [`VirtualTablet`].
+pub const TILT_MAX: i32 = 90;
+
+fn axis(axis: AbsoluteAxis, minimum: i32, maximum: i32, resolution: i32) -> AbsoluteInfoSetup {
+    AbsoluteInfoSetup {
+        axis,
+        info: AbsoluteInfo {
+            minimum,
+            maximum,
+            resolution,
+            .. Default::default()
+        },
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The capabilities of a pen tablet with pressure, tilt and an eraser.
+///
+/// `width` and `height` are the size of the surface in device units, and
+/// `resolution` is the number of units per millimeter.
+pub fn tablet_capabilities(id: InputId, name: &[u8], width: i32, height: i32, resolution: i32) -> DeviceCapabilities {
+    let mut caps = DeviceCapabilities::new(id, name);
+    caps.properties.insert(InputProperty::Pointer);
+    for &key in &[Key::ButtonToolPen, Key::ButtonToolRubber, Key::ButtonTouch, Key::ButtonStylus, Key::ButtonStylus2] {
+        caps.insert_key(key);
+    }
+    caps.insert_absolute(axis(AbsoluteAxis::X, 0, width - 1, resolution));
+    caps.insert_absolute(axis(AbsoluteAxis::Y, 0, height - 1, resolution));
+    caps.insert_absolute(axis(AbsoluteAxis::Pressure, 0, PRESSURE_MAX, 0));
+    // tilt resolution is in units per radian
+    caps.insert_absolute(axis(AbsoluteAxis::TiltX, -TILT_MAX, TILT_MAX, 57));
+    caps.insert_absolute(axis(AbsoluteAxis::TiltY, -TILT_MAX, TILT_MAX, 57));
+    caps
+}
+
+/// The capabilities of a touchscreen supporting `slots` simultaneous
+/// contacts.
+pub fn touchscreen_capabilities(id: InputId, name: &[u8], width: i32, height: i32, resolution: i32, slots: usize) -> DeviceCapabilities {
+    let mut caps = DeviceCapabilities::new(id, name);
+    caps.properties.insert(InputProperty::Direct);
+    caps.insert_key(Key::ButtonTouch);
+    caps.insert_absolute(axis(AbsoluteAxis::X, 0, width - 1, resolution));
+    caps.insert_absolute(axis(AbsoluteAxis::Y, 0, height - 1, resolution));
+    caps.insert_absolute(axis(AbsoluteAxis::MultitouchSlot, 0, slots as i32 - 1, 0));
+    caps.insert_absolute(axis(AbsoluteAxis::MultitouchTrackingId, 0, 0xffff, 0));
+    caps.insert_absolute(axis(AbsoluteAxis::MultitouchPositionX, 0, width - 1, resolution));
+    caps.insert_absolute(axis(AbsoluteAxis::MultitouchPositionY, 0, height - 1, resolution));
+    caps
+}
+
+/// A uinput pen tablet.
+pub struct VirtualTablet<F> {
+    writer: UInputWriter<F>,
+    tool: Key,
+    in_range: bool,
+    touching: bool,
+}
+
+impl<F> VirtualTablet<F> {
+    /// Wraps a uinput device that has already been created with the
+    /// capabilities of [`tablet_capabilities`].
+    pub const fn new(handle: UInputHandle<F>) -> Self {
+        VirtualTablet {
+            writer: UInputWriter::new(handle),
+            tool: Key::ButtonToolPen,
+            in_range: false,
+            touching: false,
+        }
+    }
+
+    /// The tool in use, either `BTN_TOOL_PEN` or `BTN_TOOL_RUBBER`.
+    pub const fn tool(&self) -> Key {
+        self.tool
+    }
+
+    /// Whether the pen is in range of the tablet.
+    pub const fn in_range(&self) -> bool {
+        self.in_range
+    }
+
+    /// Whether the pen is touching the tablet.
+    pub const fn touching(&self) -> bool {
+        self.touching
+    }
+
+    /// A reference to the underlying uinput handle.
+    pub const fn handle(&self) -> &UInputHandle<F> {
+        self.writer.handle()
+    }
+
+    /// A mutable reference to the writer used for each frame.
+    pub fn writer_mut(&mut self) -> &mut UInputWriter<F> {
+        &mut self.writer
+    }
+
+    /// Extracts the underlying uinput handle.
+    pub fn into_inner(self) -> UInputHandle<F> {
+        self.writer.into_inner()
+    }
+
+    fn enter(&mut self, time: EventTime, events: &mut Vec<InputEvent>) {
+        if !self.in_range {
+            self.in_range = true;
+            events.push(KeyEvent::new(time, self.tool, KeyState::PRESSED).into());
+        }
+    }
+}
+
+impl<F: AsRawFd> VirtualTablet<F> {
+    /// Creates a uinput pen tablet.
+    pub fn create(handle: UInputHandle<F>, id: &InputId, name: &[u8], width: i32, height: i32, resolution: i32) -> io::Result<Self> {
+        handle.create_from_capabilities(&tablet_capabilities(*id, name, width, height, resolution))?;
+        Ok(Self::new(handle))
+    }
+
+    /// Moves the pen, bringing it into range if necessary.
+    pub fn move_to(&mut self, x: i32, y: i32) -> io::Result<()> {
+        let time = EventTime::default();
+        let mut events = Vec::new();
+        self.enter(time, &mut events);
+        events.push(AbsoluteEvent::new(time, AbsoluteAxis::X, x).into());
+        events.push(AbsoluteEvent::new(time, AbsoluteAxis::Y, y).into());
+        self.writer.write_frame(&events)
+    }
+
+    /// Tilts the pen, in degrees.
+    pub fn tilt(&mut self, x: i32, y: i32) -> io::Result<()> {
+        let time = EventTime::default();
+        self.writer.write_frame(&[
+            AbsoluteEvent::new(time, AbsoluteAxis::TiltX, x.clamp(-TILT_MAX, TILT_MAX)).into(),
+            AbsoluteEvent::new(time, AbsoluteAxis::TiltY, y.clamp(-TILT_MAX, TILT_MAX)).into(),
+        ])
+    }
+
+    /// Presses the pen against the tablet, or changes the pressure if it is
+    /// already touching.
+    pub fn press(&mut self, pressure: i32) -> io::Result<()> {
+        let time = EventTime::default();
+        let mut events = Vec::new();
+        self.enter(time, &mut events);
+        if !self.touching {
+            self.touching = true;
+            events.push(KeyEvent::new(time, Key::ButtonTouch, KeyState::PRESSED).into());
+        }
+        events.push(AbsoluteEvent::new(time, AbsoluteAxis::Pressure, pressure.clamp(0, PRESSURE_MAX)).into());
+        self.writer.write_frame(&events)
+    }
+
+    /// Lifts the pen off of the tablet, leaving it in range.
+    pub fn release(&mut self) -> io::Result<()> {
+        if !self.touching {
+            return Ok(())
+        }
+        self.touching = false;
+        let time = EventTime::default();
+        self.writer.write_frame(&[
+            AbsoluteEvent::new(time, AbsoluteAxis::Pressure, 0).into(),
+            KeyEvent::new(time, Key::ButtonTouch, KeyState::RELEASED).into(),
+        ])
+    }
+
+    /// Presses or releases one of the stylus buttons.
+    pub fn button(&mut self, key: Key, pressed: bool) -> io::Result<()> {
+        match key {
+            Key::ButtonStylus | Key::ButtonStylus2 => (),
+            _ => return Err(invalid("not a stylus button")),
+        }
+        let time = EventTime::default();
+        let mut events = Vec::new();
+        self.enter(time, &mut events);
+        events.push(KeyEvent::new(time, key, KeyState::pressed(pressed)).into());
+        self.writer.write_frame(&events)
+    }
+
+    /// Switches between the pen tip and the eraser.
+    ///
+    /// If the pen is in range, the old tool leaves and the new one enters,
+    /// lifting the pen first if necessary.
+    pub fn set_tool(&mut self, tool: Key) -> io::Result<()> {
+        match tool {
+            Key::ButtonToolPen | Key::ButtonToolRubber => (),
+            _ => return Err(invalid("not a tablet tool")),
+        }
+        if tool == self.tool {
+            return Ok(())
+        }
+        if !self.in_range {
+            self.tool = tool;
+            return Ok(())
+        }
+        self.release()?;
+        let time = EventTime::default();
+        let old = self.tool;
+        self.tool = tool;
+        self.writer.write_frame(&[
+            KeyEvent::new(time, old, KeyState::RELEASED).into(),
+            KeyEvent::new(time, tool, KeyState::PRESSED).into(),
+        ])
+    }
+
+    /// Moves the pen out of range, lifting it first if necessary.
+    pub fn leave(&mut self) -> io::Result<()> {
+        self.release()?;
+        if !self.in_range {
+            return Ok(())
+        }
+        self.in_range = false;
+        let time = EventTime::default();
+        self.writer.write_frame(&[
+            KeyEvent::new(time, self.tool, KeyState::RELEASED).into(),
+        ])
+    }
+}
+
+/// The position of a contact in a multitouch slot.
+#[derive(Copy, Clone, Debug)]
+struct Contact {
+    x: i32,
+    y: i32,
+}
+
+/// Tracks the contacts of a multitouch device, and produces the events of
+/// the slot-based multitouch protocol.
+#[derive(Clone, Debug)]
+struct Contacts {
+    slots: Vec<Option<Contact>>,
+    slot: usize,
+    next_tracking_id: i32,
+}
+
+impl Contacts {
+    fn new(slots: usize) -> Self {
+        Contacts {
+            slots: vec![None; slots],
+            slot: 0,
+            next_tracking_id: 0,
+        }
+    }
+
+    fn is_primary(&self, slot: usize) -> bool {
+        self.slots.iter().position(|s| s.is_some()) == Some(slot)
+    }
+
+    fn select(&mut self, slot: usize, time: EventTime, out: &mut Vec<InputEvent>) {
+        if self.slot != slot {
+            self.slot = slot;
+            out.push(AbsoluteEvent::new(time, AbsoluteAxis::MultitouchSlot, slot as i32).into());
+        }
+    }
+
+    fn position(&mut self, slot: usize, x: i32, y: i32, time: EventTime, out: &mut Vec<InputEvent>) {
+        if let Some(contact) = &mut self.slots[slot] {
+            contact.x = x;
+            contact.y = y;
+        }
+        out.push(AbsoluteEvent::new(time, AbsoluteAxis::MultitouchPositionX, x).into());
+        out.push(AbsoluteEvent::new(time, AbsoluteAxis::MultitouchPositionY, y).into());
+        if self.is_primary(slot) {
+            out.push(AbsoluteEvent::new(time, AbsoluteAxis::X, x).into());
+            out.push(AbsoluteEvent::new(time, AbsoluteAxis::Y, y).into());
+        }
+    }
+
+    fn down(&mut self, slot: usize, x: i32, y: i32, time: EventTime, out: &mut Vec<InputEvent>) -> io::Result<()> {
+        match self.slots.get(slot) {
+            None => return Err(invalid("multitouch slot out of range")),
+            Some(Some(..)) => return Err(invalid("multitouch slot already in contact")),
+            Some(None) => (),
+        }
+        let first = self.slots.iter().all(|s| s.is_none());
+        let tracking_id = self.next_tracking_id;
+        self.next_tracking_id = (tracking_id + 1) & 0xffff;
+        self.slots[slot] = Some(Contact { x, y });
+
+        self.select(slot, time, out);
+        out.push(AbsoluteEvent::new(time, AbsoluteAxis::MultitouchTrackingId, tracking_id).into());
+        self.position(slot, x, y, time, out);
+        if first {
+            out.push(KeyEvent::new(time, Key::ButtonTouch, KeyState::PRESSED).into());
+        }
+        Ok(())
+    }
+
+    fn moved(&mut self, slot: usize, x: i32, y: i32, time: EventTime, out: &mut Vec<InputEvent>) -> io::Result<()> {
+        match self.slots.get(slot) {
+            Some(Some(..)) => (),
+            _ => return Err(invalid("multitouch slot not in contact")),
+        }
+        self.select(slot, time, out);
+        self.position(slot, x, y, time, out);
+        Ok(())
+    }
+
+    fn up(&mut self, slot: usize, time: EventTime, out: &mut Vec<InputEvent>) -> io::Result<()> {
+        match self.slots.get(slot) {
+            Some(Some(..)) => (),
+            _ => return Err(invalid("multitouch slot not in contact")),
+        }
+        let primary = self.is_primary(slot);
+        self.slots[slot] = None;
+        self.select(slot, time, out);
+        out.push(AbsoluteEvent::new(time, AbsoluteAxis::MultitouchTrackingId, -1).into());
+        match self.slots.iter().flatten().next() {
+            // the next contact takes over the single-touch axes
+            Some(contact) => if primary {
+                out.push(AbsoluteEvent::new(time, AbsoluteAxis::X, contact.x).into());
+                out.push(AbsoluteEvent::new(time, AbsoluteAxis::Y, contact.y).into());
+            },
+            None => out.push(KeyEvent::new(time, Key::ButtonTouch, KeyState::RELEASED).into()),
+        }
+        Ok(())
+    }
+}
+
+/// A uinput multitouch touchscreen.
+///
+/// Contacts are reported using protocol B, with the first active slot also
+/// driving the single-touch `ABS_X` and `ABS_Y` axes.
+pub struct VirtualTouchscreen<F> {
+    writer: UInputWriter<F>,
+    contacts: Contacts,
+}
+
+impl<F> VirtualTouchscreen<F> {
+    /// The number of contacts currently touching the screen.
+    pub fn contacts(&self) -> usize {
+        self.contacts.slots.iter().filter(|s| s.is_some()).count()
+    }
+
+    /// A reference to the underlying uinput handle.
+    pub const fn handle(&self) -> &UInputHandle<F> {
+        self.writer.handle()
+    }
+
+    /// A mutable reference to the writer used for each frame.
+    pub fn writer_mut(&mut self) -> &mut UInputWriter<F> {
+        &mut self.writer
+    }
+
+    /// Extracts the underlying uinput handle.
+    pub fn into_inner(self) -> UInputHandle<F> {
+        self.writer.into_inner()
+    }
+}
+
+impl<F: AsRawFd> VirtualTouchscreen<F> {
+    /// Creates a uinput touchscreen.
+    pub fn create(handle: UInputHandle<F>, id: &InputId, name: &[u8], width: i32, height: i32, resolution: i32, slots: usize) -> io::Result<Self> {
+        if slots == 0 {
+            return Err(invalid("touchscreen needs at least one slot"))
+        }
+        handle.create_from_capabilities(&touchscreen_capabilities(*id, name, width, height, resolution, slots))?;
+        Ok(VirtualTouchscreen {
+            writer: UInputWriter::new(handle),
+            contacts: Contacts::new(slots),
+        })
+    }
+
+    fn frame<C: FnOnce(&mut Contacts, EventTime, &mut Vec<InputEvent>) -> io::Result<()>>(&mut self, f: C) -> io::Result<()> {
+        let mut events = Vec::new();
+        f(&mut self.contacts, EventTime::default(), &mut events)?;
+        self.writer.write_frame(&events)
+    }
+
+    /// Starts a new contact in the given slot.
+    pub fn touch_down(&mut self, slot: usize, x: i32, y: i32) -> io::Result<()> {
+        self.frame(|contacts, time, events| contacts.down(slot, x, y, time, events))
+    }
+
+    /// Moves the contact in the given slot.
+    pub fn touch_move(&mut self, slot: usize, x: i32, y: i32) -> io::Result<()> {
+        self.frame(|contacts, time, events| contacts.moved(slot, x, y, time, events))
+    }
+
+    /// Lifts the contact in the given slot.
+    pub fn touch_up(&mut self, slot: usize) -> io::Result<()> {
+        self.frame(|contacts, time, events| contacts.up(slot, time, events))
+    }
+
+    /// Lifts every contact in a single frame.
+    pub fn release_all(&mut self) -> io::Result<()> {
+        let slots: Vec<_> = (0..self.contacts.slots.len())
+            .filter(|&slot| self.contacts.slots[slot].is_some())
+            .collect();
+        if slots.is_empty() {
+            return Ok(())
+        }
+        self.frame(|contacts, time, events| slots.into_iter()
+            .try_for_each(|slot| contacts.up(slot, time, events))
+        )
+    }
+}
+
+#[test]
+fn touch_contacts() {
+    let time = EventTime::default();
+    let abs = |axis, value| InputEvent::from(AbsoluteEvent::new(time, axis, value));
+    let key = |key, value| InputEvent::from(KeyEvent::new(time, key, KeyState::pressed(value)));
+
+    let mut contacts = Contacts::new(2);
+    let mut events = Vec::new();
+    contacts.down(0, 10, 20, time, &mut events).unwrap();
+    contacts.down(1, 30, 40, time, &mut events).unwrap();
+    assert!(contacts.down(1, 0, 0, time, &mut Vec::new()).is_err());
+    assert!(contacts.down(2, 0, 0, time, &mut Vec::new()).is_err());
+    contacts.up(0, time, &mut events).unwrap();
+    contacts.moved(1, 31, 41, time, &mut events).unwrap();
+    contacts.up(1, time, &mut events).unwrap();
+    assert!(contacts.moved(1, 0, 0, time, &mut Vec::new()).is_err());
+
+    assert_eq!(events, [
+        abs(AbsoluteAxis::MultitouchTrackingId, 0),
+        abs(AbsoluteAxis::MultitouchPositionX, 10),
+        abs(AbsoluteAxis::MultitouchPositionY, 20),
+        abs(AbsoluteAxis::X, 10),
+        abs(AbsoluteAxis::Y, 20),
+        key(Key::ButtonTouch, true),
+        abs(AbsoluteAxis::MultitouchSlot, 1),
+        abs(AbsoluteAxis::MultitouchTrackingId, 1),
+        abs(AbsoluteAxis::MultitouchPositionX, 30),
+        abs(AbsoluteAxis::MultitouchPositionY, 40),
+        abs(AbsoluteAxis::MultitouchSlot, 0),
+        abs(AbsoluteAxis::MultitouchTrackingId, -1),
+        abs(AbsoluteAxis::X, 30),
+        abs(AbsoluteAxis::Y, 40),
+        abs(AbsoluteAxis::MultitouchSlot, 1),
+        abs(AbsoluteAxis::MultitouchPositionX, 31),
+        abs(AbsoluteAxis::MultitouchPositionY, 41),
+        abs(AbsoluteAxis::X, 31),
+        abs(AbsoluteAxis::Y, 41),
+        abs(AbsoluteAxis::MultitouchTrackingId, -1),
+        key(Key::ButtonTouch, false),
+    ][..]);
+}
+
+#[test]
+fn tablet_frames() {
+    use std::mem::MaybeUninit;
+    use std::os::unix::net::UnixStream;
+    use crate::{EvdevHandle, SynchronizeEvent};
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    rx.set_nonblocking(true).unwrap();
+    let rx = EvdevHandle::new(rx);
+    let read = || {
+        let mut events = [MaybeUninit::uninit(); 32];
+        match rx.read_input_events(&mut events) {
+            Ok(events) => events.iter().map(|e| InputEvent { time: EventTime::default(), ..*e }).collect(),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Vec::new(),
+            Err(err) => panic!("{}", err),
+        }
+    };
+    let time = EventTime::default();
+    let abs = |axis, value| InputEvent::from(AbsoluteEvent::new(time, axis, value));
+    let key = |key, value| InputEvent::from(KeyEvent::new(time, key, KeyState::pressed(value)));
+    let syn = InputEvent::from(SynchronizeEvent::report(time));
+
+    let mut tablet = VirtualTablet::new(UInputHandle::new(tx));
+    tablet.set_tool(Key::ButtonToolRubber).unwrap();
+    assert!(tablet.set_tool(Key::ButtonTouch).is_err());
+    assert!(read().is_empty());
+
+    tablet.move_to(10, 20).unwrap();
+    tablet.press(5000).unwrap();
+    tablet.press(100).unwrap();
+    tablet.release().unwrap();
+    tablet.set_tool(Key::ButtonToolPen).unwrap();
+    tablet.leave().unwrap();
+    tablet.leave().unwrap();
+    assert!(!tablet.in_range() && !tablet.touching());
+    assert_eq!(read(), [
+        key(Key::ButtonToolRubber, true), abs(AbsoluteAxis::X, 10), abs(AbsoluteAxis::Y, 20), syn,
+        key(Key::ButtonTouch, true), abs(AbsoluteAxis::Pressure, PRESSURE_MAX), syn,
+        abs(AbsoluteAxis::Pressure, 100), syn,
+        abs(AbsoluteAxis::Pressure, 0), key(Key::ButtonTouch, false), syn,
+        key(Key::ButtonToolRubber, false), key(Key::ButtonToolPen, true), syn,
+        key(Key::ButtonToolPen, false), syn,
+    ]);
+}
@@ -0,0 +1,286 @@
+//! Dual-role keys that act differently when tapped or held.
+//!
+//! A [`TapHoldProcessor`] delays the press of a dual-role key until it can
+//! decide whether the key was tapped or held, buffering any events that occur
+//! in the meantime. Decisions that depend on time are made either from the
+//! timestamps of incoming events or by calling
+//! [`timeout`](TapHoldProcessor::timeout) once the
+//! [`deadline`](TapHoldProcessor::deadline) passes.
+//!
+//! [`TapHoldProcessor::run`] drives this for a blocking [`EvdevDevice`]. When
+//! reading events asynchronously, such as from a stream using an
+//! [`EventCodec`](crate::EventCodec), wait on the next event with a timeout of
+//! [`time_until_deadline`](TapHoldProcessor::time_until_deadline) instead.
+
+use std::io;
+use std::mem::MaybeUninit;
+use std::time::Duration;
+use std::collections::HashMap;
+use crate::{
+    EvdevDevice, InputSink, EventTime, ClockId, InputEvent, Key, KeyEvent,
+    KeyState, SynchronizeEvent, GenericEvent,
+};
+use crate::stream::is_report;
+
+/// The behaviour of a single dual-role key.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TapHold {
+    /// The key emitted when tapped.
+    pub tap: Key,
+    /// The key emitted when held.
+    pub hold: Key,
+    /// How long the key must be held before it is considered held.
+    pub timeout: Duration,
+    /// Whether the key is considered held as soon as another key is pressed
+    /// and released while it is down.
+    pub permissive_hold: bool,
+    /// Whether the key is considered held as soon as another key is pressed
+    /// while it is down.
+    pub hold_on_other_key_press: bool,
+}
+
+impl TapHold {
+    /// The default tapping timeout.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(200);
+
+    /// A dual-role key that only resolves as held after the default timeout.
+    pub const fn new(tap: Key, hold: Key) -> Self {
+        TapHold {
+            tap,
+            hold,
+            timeout: Self::DEFAULT_TIMEOUT,
+            permissive_hold: false,
+            hold_on_other_key_press: false,
+        }
+    }
+
+    /// Sets the tapping timeout.
+    pub const fn with_timeout(self, timeout: Duration) -> Self {
+        TapHold { timeout, ..self }
+    }
+
+    /// Sets the permissive hold policy.
+    pub const fn with_permissive_hold(self, permissive_hold: bool) -> Self {
+        TapHold { permissive_hold, ..self }
+    }
+
+    /// Sets the hold on other key press policy.
+    pub const fn with_hold_on_other_key_press(self, hold_on_other_key_press: bool) -> Self {
+        TapHold { hold_on_other_key_press, ..self }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Pending {
+    key: Key,
+    config: TapHold,
+    time: EventTime,
+}
+
+impl Pending {
+    fn deadline(&self) -> EventTime {
+        self.time.checked_add(self.config.timeout).unwrap_or(self.time)
+    }
+}
+
+/// Whether `out` is empty or ends with a `SYN_REPORT`.
+fn ends_frame(out: &[InputEvent]) -> bool {
+    out.last().map(is_report).unwrap_or(true)
+}
+
+/// Applies tap-hold behaviour to a stream of events.
+#[derive(Clone, Debug, Default)]
+pub struct TapHoldProcessor {
+    keys: HashMap<Key, TapHold>,
+    pending: Option<Pending>,
+    buffer: Vec<InputEvent>,
+    resolved: HashMap<Key, Key>,
+}
+
+impl TapHoldProcessor {
+    /// A processor without any dual-role keys.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a dual-role key.
+    pub fn with(mut self, key: Key, config: TapHold) -> Self {
+        self.insert(key, config);
+        self
+    }
+
+    /// Adds or replaces a dual-role key, returning its previous behaviour.
+    pub fn insert(&mut self, key: Key, config: TapHold) -> Option<TapHold> {
+        self.keys.insert(key, config)
+    }
+
+    /// Removes a dual-role key.
+    ///
+    /// A key that is currently held keeps its behaviour until released.
+    pub fn remove(&mut self, key: Key) -> Option<TapHold> {
+        self.keys.remove(&key)
+    }
+
+    /// The time at which an undecided key will be considered held.
+    pub fn deadline(&self) -> Option<EventTime> {
+        self.pending.as_ref().map(Pending::deadline)
+    }
+
+    /// How long to wait for further events before calling
+    /// [`timeout`](Self::timeout).
+    ///
+    /// Returns `None` if there is no deadline to wait for.
+    pub fn time_until_deadline(&self, now: EventTime) -> Option<Duration> {
+        self.deadline().map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    fn resolve(&mut self, hold: bool, out: &mut Vec<InputEvent>) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let key = if hold { pending.config.hold } else { pending.config.tap };
+        self.resolved.insert(pending.key, key);
+        out.push(KeyEvent::new(pending.time, key, KeyState::PRESSED).into());
+        for event in std::mem::take(&mut self.buffer) {
+            self.process(&event, out);
+        }
+    }
+
+    /// Processes a single input event, appending the resulting events to `out`.
+    ///
+    /// While a dual-role key is undecided, all events are held back and
+    /// released in order once it is.
+    pub fn process(&mut self, event: &InputEvent, out: &mut Vec<InputEvent>) {
+        let key = KeyEvent::from_ref(event).ok();
+
+        if let Some(pending) = self.pending {
+            let expired = event.time >= pending.deadline();
+            let hold = match key {
+                Some(key) if key.key == pending.key => match key.value {
+                    KeyState::RELEASED => expired,
+                    _ => return,
+                },
+                _ if expired => true,
+                Some(key) if key.value == KeyState::PRESSED => pending.config.hold_on_other_key_press,
+                Some(key) if key.value == KeyState::RELEASED => pending.config.permissive_hold &&
+                    self.buffer.iter().any(|e| KeyEvent::from_ref(e).map(|e| e.key == key.key && e.value == KeyState::PRESSED).unwrap_or(false)),
+                _ => false,
+            };
+            let releasing = key.map(|key| key.key == pending.key).unwrap_or(false);
+            if hold || releasing {
+                self.resolve(hold, out);
+                if releasing && !ends_frame(out) {
+                    // report the press before the release so it isn't lost
+                    out.push(SynchronizeEvent::report(event.time).into());
+                }
+                self.process(event, out);
+            } else {
+                self.buffer.push(*event);
+            }
+            return
+        }
+
+        let key = match key {
+            Some(key) => key,
+            None => return out.push(*event),
+        };
+        match (key.value, self.resolved.get(&key.key).cloned()) {
+            (KeyState::RELEASED, Some(resolved)) => {
+                self.resolved.remove(&key.key);
+                out.push(KeyEvent::new(key.time, resolved, key.value).into());
+            },
+            (_, Some(resolved)) => out.push(KeyEvent::new(key.time, resolved, key.value).into()),
+            (KeyState::PRESSED, None) => match self.keys.get(&key.key) {
+                Some(&config) => self.pending = Some(Pending {
+                    key: key.key,
+                    config,
+                    time: key.time,
+                }),
+                None => out.push(*event),
+            },
+            _ => out.push(*event),
+        }
+    }
+
+    /// Resolves an undecided key as held if its deadline has passed.
+    ///
+    /// A `SYN_REPORT` is appended if the output would otherwise end
+    /// mid-frame.
+    pub fn timeout(&mut self, now: EventTime, out: &mut Vec<InputEvent>) {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => (),
+            _ => return,
+        }
+        self.resolve(true, out);
+        if !ends_frame(out) {
+            out.push(SynchronizeEvent::report(now).into());
+        }
+    }
+
+    /// Processes all events read from `evdev` and writes them to `sink`
+    /// until an error occurs.
+    ///
+    /// `clock` must match the clock used for the device's event timestamps,
+    /// see [`EvdevHandle::set_clock`](crate::EvdevHandle::set_clock).
+    pub fn run<D: EvdevDevice + ?Sized, O: InputSink>(&mut self, evdev: &D, mut sink: O, clock: ClockId) -> io::Result<()> {
+        let mut events = [MaybeUninit::<InputEvent>::uninit(); 64];
+        let mut out = Vec::new();
+        loop {
+            out.clear();
+            let wait = match self.deadline() {
+                Some(..) => self.time_until_deadline(EventTime::now(clock)?),
+                None => None,
+            };
+            if evdev.wait_readable(wait)? {
+                for event in evdev.read_input_events(&mut events)?.iter() {
+                    self.process(event, &mut out);
+                }
+            }
+            self.timeout(EventTime::now(clock)?, &mut out);
+            if !out.is_empty() {
+                sink.write_events(&out)?;
+                sink.flush()?;
+            }
+        }
+    }
+}
+
+#[test]
+fn tap_hold() {
+    let key = |ms: i64, key, value| InputEvent::from(KeyEvent::new(EventTime::new(0, ms * 1000), key, value));
+    let mut taphold = TapHoldProcessor::new()
+        .with(Key::Space, TapHold::new(Key::Space, Key::LeftCtrl).with_permissive_hold(true));
+
+    let mut out = Vec::new();
+    taphold.process(&key(0, Key::Space, KeyState::PRESSED), &mut out);
+    assert_eq!(taphold.deadline(), Some(EventTime::new(0, 200_000)));
+    taphold.process(&key(50, Key::Space, KeyState::RELEASED), &mut out);
+    assert_eq!(out, [
+        key(0, Key::Space, KeyState::PRESSED),
+        SynchronizeEvent::report(EventTime::new(0, 50_000)).into(),
+        key(50, Key::Space, KeyState::RELEASED),
+    ]);
+
+    out.clear();
+    taphold.process(&key(100, Key::Space, KeyState::PRESSED), &mut out);
+    taphold.process(&key(110, Key::C, KeyState::PRESSED), &mut out);
+    assert!(out.is_empty());
+    taphold.process(&key(120, Key::C, KeyState::RELEASED), &mut out);
+    assert_eq!(out, [
+        key(100, Key::LeftCtrl, KeyState::PRESSED),
+        key(110, Key::C, KeyState::PRESSED),
+        key(120, Key::C, KeyState::RELEASED),
+    ]);
+
+    out.clear();
+    taphold.process(&key(130, Key::Space, KeyState::RELEASED), &mut out);
+    taphold.process(&key(200, Key::Space, KeyState::PRESSED), &mut out);
+    taphold.timeout(EventTime::new(0, 399_000), &mut out);
+    taphold.timeout(EventTime::new(0, 400_000), &mut out);
+    assert_eq!(out, [
+        key(130, Key::LeftCtrl, KeyState::RELEASED),
+        key(200, Key::LeftCtrl, KeyState::PRESSED),
+        SynchronizeEvent::report(EventTime::new(0, 400_000)).into(),
+    ]);
+}
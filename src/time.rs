@@ -1,8 +1,12 @@
-use std::{fmt, cmp};
+use std::{io, fmt, cmp};
+use std::convert::TryFrom;
 use std::hash::{Hash, Hasher};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Add, AddAssign, Sub, SubAssign};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use crate::sys::timeval;
-use nix::libc::{time_t, suseconds_t};
+use crate::RangeError;
+use crate::macros::convert_error;
+use nix::libc::{self, time_t, suseconds_t};
 #[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
 
@@ -65,6 +69,123 @@ impl EventTime {
     pub const fn into_inner(self) -> timeval {
         self.0
     }
+
+    /// The current time of the specified clock.
+    ///
+    /// Use the same clock that was configured via
+    /// [`EvdevHandle::set_clock`](crate::EvdevHandle::set_clock) when
+    /// comparing against event timestamps.
+    pub fn now(clock: ClockId) -> io::Result<Self> {
+        nix::time::clock_gettime(clock.into())
+            .map(|ts| EventTime(timeval {
+                tv_sec: ts.tv_sec(),
+                tv_usec: ts.tv_nsec() / 1000,
+            }))
+            .map_err(convert_error)
+    }
+
+    /// The timestamp represented as a total number of microseconds.
+    pub const fn as_micros(&self) -> i128 {
+        self.seconds() as i128 * MICROS_PER_SEC + self.microseconds() as i128
+    }
+
+    /// Create a normalized timestamp from a total number of microseconds.
+    ///
+    /// Returns `None` if the value cannot be represented by [`timeval`].
+    pub fn from_micros(micros: i128) -> Option<Self> {
+        let secs = micros.div_euclid(MICROS_PER_SEC);
+        let usecs = micros.rem_euclid(MICROS_PER_SEC);
+        time_t::try_from(secs).ok()
+            .map(|secs| EventTime(timeval {
+                tv_sec: secs,
+                tv_usec: usecs as suseconds_t,
+            }))
+    }
+
+    /// The amount of time elapsed from an earlier timestamp.
+    ///
+    /// Returns `None` if `earlier` is later than `self`.
+    pub fn checked_duration_since(&self, earlier: EventTime) -> Option<Duration> {
+        micros_to_duration(self.as_micros() - earlier.as_micros())
+    }
+
+    /// The amount of time elapsed from an earlier timestamp, or zero if
+    /// `earlier` is later than `self`.
+    pub fn saturating_duration_since(&self, earlier: EventTime) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    /// Returns `Some(t)` where `t` is the time `self + duration` if it can be
+    /// represented.
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        Self::from_micros(self.as_micros() + duration.as_micros() as i128)
+    }
+
+    /// Returns `Some(t)` where `t` is the time `self - duration` if it can be
+    /// represented.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        Self::from_micros(self.as_micros() - duration.as_micros() as i128)
+    }
+}
+
+const MICROS_PER_SEC: i128 = 1_000_000;
+
+fn micros_to_duration(micros: i128) -> Option<Duration> {
+    match micros {
+        micros if micros < 0 => None,
+        micros => u64::try_from(micros / MICROS_PER_SEC).ok()
+            .map(|secs| Duration::new(secs, (micros % MICROS_PER_SEC) as u32 * 1000)),
+    }
+}
+
+/// Clocks that can be used to timestamp events.
+///
+/// See [`EvdevHandle::set_clock`](crate::EvdevHandle::set_clock).
+#[repr(i32)]
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum ClockId {
+    /// Wall-clock time, the default for evdev devices.
+    ///
+    /// This clock may jump when the system time is changed.
+    #[default]
+    Realtime = libc::CLOCK_REALTIME,
+    /// Monotonic time that does not advance while the system is suspended.
+    Monotonic = libc::CLOCK_MONOTONIC,
+    /// Monotonic time that includes time spent in suspend.
+    Boottime = libc::CLOCK_BOOTTIME,
+}
+
+impl ClockId {
+    /// Instantiates the clock from a raw `CLOCK_*` value.
+    pub fn from_raw(clock: i32) -> Result<Self, RangeError> {
+        match clock {
+            libc::CLOCK_REALTIME => Ok(ClockId::Realtime),
+            libc::CLOCK_MONOTONIC => Ok(ClockId::Monotonic),
+            libc::CLOCK_BOOTTIME => Ok(ClockId::Boottime),
+            _ => Err(Default::default()),
+        }
+    }
+}
+
+impl From<ClockId> for i32 {
+    fn from(clock: ClockId) -> Self {
+        clock as i32
+    }
+}
+
+impl TryFrom<i32> for ClockId {
+    type Error = RangeError;
+
+    fn try_from(clock: i32) -> Result<Self, Self::Error> {
+        Self::from_raw(clock)
+    }
+}
+
+impl From<ClockId> for nix::time::ClockId {
+    fn from(clock: ClockId) -> Self {
+        nix::time::ClockId::from_raw(clock.into())
+    }
 }
 
 impl Default for EventTime {
@@ -94,6 +215,96 @@ impl From<EventTime> for timeval {
     }
 }
 
+impl TryFrom<EventTime> for Duration {
+    type Error = RangeError;
+
+    /// Interprets the timestamp as time elapsed since its clock's epoch.
+    fn try_from(time: EventTime) -> Result<Self, Self::Error> {
+        micros_to_duration(time.as_micros()).ok_or(RangeError)
+    }
+}
+
+impl TryFrom<Duration> for EventTime {
+    type Error = RangeError;
+
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        EventTime::from_micros(duration.as_micros() as i128).ok_or(RangeError)
+    }
+}
+
+impl TryFrom<EventTime> for SystemTime {
+    type Error = RangeError;
+
+    /// Interprets the timestamp as a [`ClockId::Realtime`] value.
+    fn try_from(time: EventTime) -> Result<Self, Self::Error> {
+        let micros = time.as_micros();
+        match micros_to_duration(micros) {
+            Some(duration) => UNIX_EPOCH.checked_add(duration),
+            None => micros_to_duration(-micros).and_then(|d| UNIX_EPOCH.checked_sub(d)),
+        }.ok_or(RangeError)
+    }
+}
+
+impl TryFrom<SystemTime> for EventTime {
+    type Error = RangeError;
+
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => EventTime::try_from(duration),
+            Err(e) => EventTime::from_micros(-(e.duration().as_micros() as i128)).ok_or(RangeError),
+        }
+    }
+}
+
+/// Subtracting timestamps saturates: if `other` is later than `self` the
+/// result is zero rather than a panic or an error. Use
+/// [`EventTime::checked_duration_since`] to detect that case.
+impl Sub for EventTime {
+    type Output = Duration;
+
+    /// Returns the amount of time elapsed from another timestamp, saturating
+    /// to zero if `other` is later than `self`.
+    fn sub(self, other: EventTime) -> Duration {
+        self.saturating_duration_since(other)
+    }
+}
+
+impl Add<Duration> for EventTime {
+    type Output = EventTime;
+
+    /// # Panics
+    ///
+    /// This function panics if the resulting timestamp cannot be represented.
+    fn add(self, duration: Duration) -> EventTime {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to event time")
+    }
+}
+
+impl AddAssign<Duration> for EventTime {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for EventTime {
+    type Output = EventTime;
+
+    /// # Panics
+    ///
+    /// This function panics if the resulting timestamp cannot be represented.
+    fn sub(self, duration: Duration) -> EventTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from event time")
+    }
+}
+
+impl SubAssign<Duration> for EventTime {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
 impl Deref for EventTime {
     type Target = timeval;
 
@@ -160,3 +371,23 @@ impl Ord for EventTime {
 }
 
 impl Eq for EventTime {}
+
+#[test]
+fn event_time_arithmetic() {
+    let time = EventTime::new(5, 1_500_000);
+    assert_eq!(EventTime::from_micros(time.as_micros()), Some(EventTime::new(6, 500_000)));
+    assert_eq!(EventTime::new(-1, 0).as_micros(), -1_000_000);
+    assert_eq!(EventTime::from_micros(-1), Some(EventTime::new(-1, 999_999)));
+
+    let later = time + Duration::from_millis(750);
+    assert_eq!(later, EventTime::new(7, 250_000));
+    assert_eq!(later - time, Duration::from_millis(750));
+    assert_eq!(time - later, Duration::ZERO);
+    assert_eq!(time.checked_duration_since(later), None);
+    assert_eq!(later - Duration::from_millis(750), EventTime::new(6, 500_000));
+
+    assert_eq!(Duration::try_from(EventTime::new(-1, 0)), Err(RangeError));
+    assert_eq!(EventTime::try_from(Duration::from_micros(1_250_000)), Ok(EventTime::new(1, 250_000)));
+    assert_eq!(SystemTime::try_from(EventTime::new(-2, 0)), Ok(UNIX_EPOCH - Duration::from_secs(2)));
+    assert_eq!(EventTime::try_from(UNIX_EPOCH - Duration::from_micros(1)), Ok(EventTime::new(-1, 999_999)));
+}
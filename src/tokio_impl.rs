@@ -1,4 +1,4 @@
-use crate::{EventCodec, InputEvent};
+use crate::{EventCodec, FrameCodec, InputEvent};
 use bytes::BytesMut;
 use std::io::Error;
 
@@ -11,10 +11,35 @@ impl Decoder for EventCodec {
     }
 }
 
-impl Encoder<InputEvent> for EventCodec {
+impl<E: AsRef<InputEvent>> Encoder<E> for EventCodec {
     type Error = Error;
 
-    fn encode(&mut self, item: InputEvent, dst: &mut BytesMut) -> Result<(), Self::Error> {
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Self::Error> {
         self.encode_bytes(item, dst)
     }
 }
+
+impl Decoder for FrameCodec {
+    type Item = Vec<InputEvent>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_bytes(src)
+    }
+}
+
+impl<E: AsRef<InputEvent>> Encoder<Vec<E>> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, items: Vec<E>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_bytes(&items, dst)
+    }
+}
+
+impl<'a, E: AsRef<InputEvent>> Encoder<&'a [E]> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, items: &'a [E], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_bytes(items, dst)
+    }
+}
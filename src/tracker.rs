@@ -0,0 +1,366 @@
+//! Tracking the current state of a device's keys, modifiers and LEDs.
+
+use std::{io, fmt};
+use std::ops::{BitOr, BitOrAssign, BitAnd, Not};
+use crate::{
+    EventTime, InputEvent, Key, KeyEvent, KeyState,
+    LedEvent, LedKind, SynchronizeEvent, EventRef,
+};
+use crate::evdev::EvdevDevice;
+use crate::bitmask::Bitmask;
+
+/// A set of keyboard modifiers, distinguishing between the left and right keys.
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    bits: u8,
+}
+
+#[allow(missing_docs)]
+impl Modifiers {
+    pub const LEFT_CTRL: Self = Modifiers { bits: 1 << 0 };
+    pub const LEFT_SHIFT: Self = Modifiers { bits: 1 << 1 };
+    pub const LEFT_ALT: Self = Modifiers { bits: 1 << 2 };
+    pub const LEFT_META: Self = Modifiers { bits: 1 << 3 };
+    pub const RIGHT_CTRL: Self = Modifiers { bits: 1 << 4 };
+    pub const RIGHT_SHIFT: Self = Modifiers { bits: 1 << 5 };
+    pub const RIGHT_ALT: Self = Modifiers { bits: 1 << 6 };
+    pub const RIGHT_META: Self = Modifiers { bits: 1 << 7 };
+
+    pub const CTRL: Self = Self::LEFT_CTRL.union(Self::RIGHT_CTRL);
+    pub const SHIFT: Self = Self::LEFT_SHIFT.union(Self::RIGHT_SHIFT);
+    pub const ALT: Self = Self::LEFT_ALT.union(Self::RIGHT_ALT);
+    pub const META: Self = Self::LEFT_META.union(Self::RIGHT_META);
+
+    /// The modifier commonly used as AltGr in non-US layouts.
+    pub const ALTGR: Self = Self::RIGHT_ALT;
+}
+
+const MODIFIER_KEYS: [(Key, Modifiers); 8] = [
+    (Key::LeftCtrl, Modifiers::LEFT_CTRL),
+    (Key::LeftShift, Modifiers::LEFT_SHIFT),
+    (Key::LeftAlt, Modifiers::LEFT_ALT),
+    (Key::LeftMeta, Modifiers::LEFT_META),
+    (Key::RightCtrl, Modifiers::RIGHT_CTRL),
+    (Key::RightShift, Modifiers::RIGHT_SHIFT),
+    (Key::RightAlt, Modifiers::RIGHT_ALT),
+    (Key::RightMeta, Modifiers::RIGHT_META),
+];
+
+impl Modifiers {
+    /// No modifiers.
+    pub const fn empty() -> Self {
+        Modifiers { bits: 0 }
+    }
+
+    /// Creates a set of modifiers from its raw representation.
+    pub const fn from_bits(bits: u8) -> Self {
+        Modifiers { bits }
+    }
+
+    /// The raw representation of the modifiers.
+    pub const fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    /// The modifier represented by a key, if any.
+    pub fn from_key(key: Key) -> Option<Self> {
+        MODIFIER_KEYS.iter()
+            .find(|&&(k, _)| k == key)
+            .map(|&(_, m)| m)
+    }
+
+    /// The keys that make up this set of modifiers.
+    pub fn keys(self) -> impl Iterator<Item=Key> {
+        MODIFIER_KEYS.iter()
+            .filter(move |&&(_, m)| self.contains(m))
+            .map(|&(k, _)| k)
+    }
+
+    /// Combines two sets of modifiers.
+    pub const fn union(self, other: Self) -> Self {
+        Modifiers { bits: self.bits | other.bits }
+    }
+
+    /// Whether no modifiers are set.
+    pub const fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Whether all of `other` is contained in this set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Whether any of `other` is contained in this set.
+    pub const fn intersects(&self, other: Self) -> bool {
+        self.bits & other.bits != 0
+    }
+
+    /// Adds modifiers to the set.
+    pub fn insert(&mut self, other: Self) {
+        self.bits |= other.bits;
+    }
+
+    /// Removes modifiers from the set.
+    pub fn remove(&mut self, other: Self) {
+        self.bits &= !other.bits;
+    }
+
+    /// Whether either control key is held.
+    pub const fn ctrl(&self) -> bool {
+        self.intersects(Self::CTRL)
+    }
+
+    /// Whether either shift key is held.
+    pub const fn shift(&self) -> bool {
+        self.intersects(Self::SHIFT)
+    }
+
+    /// Whether either alt key is held.
+    pub const fn alt(&self) -> bool {
+        self.intersects(Self::ALT)
+    }
+
+    /// Whether either meta key is held.
+    pub const fn meta(&self) -> bool {
+        self.intersects(Self::META)
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other)
+    }
+}
+
+impl BitAnd for Modifiers {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Modifiers { bits: self.bits & other.bits }
+    }
+}
+
+impl Not for Modifiers {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Modifiers { bits: !self.bits }
+    }
+}
+
+impl fmt::Debug for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.keys()).finish()
+    }
+}
+
+/// Keeps track of which keys are currently held down, along with the state
+/// of the device's LEDs.
+#[derive(Clone, Debug, Default)]
+pub struct KeyStateTracker {
+    keys: Bitmask<Key>,
+    leds: Bitmask<LedKind>,
+}
+
+impl KeyStateTracker {
+    /// A new tracker with all keys released and all LEDs off.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a tracker seeded with the current state of an input device.
+    pub fn from_evdev<D: EvdevDevice + ?Sized>(evdev: &D) -> io::Result<Self> {
+        let mut tracker = Self::new();
+        tracker.sync(evdev).map(|()| tracker)
+    }
+
+    /// Resynchronizes the tracker with the current state of an input device.
+    ///
+    /// This should be done after a `SYN_DROPPED` event is received.
+    pub fn sync<D: EvdevDevice + ?Sized>(&mut self, evdev: &D) -> io::Result<()> {
+        self.keys = evdev.pressed_keys()?;
+        self.leds = evdev.active_leds()?;
+        Ok(())
+    }
+
+    /// Updates the tracked state with an event.
+    ///
+    /// Events other than keys and LEDs are ignored. Returns whether the state
+    /// of a key or LED has changed.
+    pub fn update(&mut self, event: &InputEvent) -> bool {
+        match EventRef::new(event) {
+            Ok(EventRef::Key(event)) => self.update_key(event),
+            Ok(EventRef::Led(event)) => self.update_led(event),
+            _ => false,
+        }
+    }
+
+    /// Updates the state of a key.
+    ///
+    /// Returns whether the state has changed, which is not the case for
+    /// autorepeat events.
+    pub fn update_key(&mut self, event: &KeyEvent) -> bool {
+        let pressed = event.value != KeyState::RELEASED;
+        let changed = self.keys.get(event.key) != pressed;
+        if pressed {
+            self.keys.insert(event.key);
+        } else {
+            self.keys.remove(event.key);
+        }
+        changed
+    }
+
+    /// Updates the state of an LED.
+    ///
+    /// Returns whether the state has changed.
+    pub fn update_led(&mut self, event: &LedEvent) -> bool {
+        let on = event.value != 0;
+        let changed = self.leds.get(event.led) != on;
+        if on {
+            self.leds.insert(event.led);
+        } else {
+            self.leds.remove(event.led);
+        }
+        changed
+    }
+
+    /// The set of keys that are currently held down.
+    pub fn pressed(&self) -> &Bitmask<Key> {
+        &self.keys
+    }
+
+    /// Whether a key is currently held down.
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.keys.get(key)
+    }
+
+    /// The set of LEDs that are currently lit.
+    pub fn leds(&self) -> &Bitmask<LedKind> {
+        &self.leds
+    }
+
+    /// Whether an LED is currently lit.
+    pub fn led(&self, led: LedKind) -> bool {
+        self.leds.get(led)
+    }
+
+    /// The modifier keys that are currently held down.
+    pub fn modifiers(&self) -> Modifiers {
+        MODIFIER_KEYS.iter()
+            .filter(|&&(key, _)| self.keys.get(key))
+            .fold(Modifiers::empty(), |mods, &(_, m)| mods | m)
+    }
+
+    /// Whether the caps lock LED is lit.
+    pub fn caps_lock(&self) -> bool {
+        self.led(LedKind::CapsLock)
+    }
+
+    /// Whether the num lock LED is lit.
+    pub fn num_lock(&self) -> bool {
+        self.led(LedKind::NumLock)
+    }
+
+    /// Whether the scroll lock LED is lit.
+    pub fn scroll_lock(&self) -> bool {
+        self.led(LedKind::ScrollLock)
+    }
+
+    /// Forgets all pressed keys and lit LEDs.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.leds.clear();
+    }
+
+    /// The events needed to release every pressed key, followed by a
+    /// `SYN_REPORT`.
+    ///
+    /// Writing these to a uinput device resets it to a clean state. The
+    /// keys are then considered released. Returns no events if no keys are
+    /// pressed.
+    pub fn release_events(&mut self, time: EventTime) -> Vec<InputEvent> {
+        let mut events: Vec<InputEvent> = self.keys.iter()
+            .map(|key| KeyEvent::new(time, key, KeyState::RELEASED).into())
+            .collect();
+        if !events.is_empty() {
+            events.push(SynchronizeEvent::report(time).into());
+        }
+        self.keys.clear();
+        events
+    }
+}
+
+impl<'a> Extend<&'a InputEvent> for KeyStateTracker {
+    fn extend<I: IntoIterator<Item=&'a InputEvent>>(&mut self, events: I) {
+        for event in events {
+            self.update(event);
+        }
+    }
+}
+
+#[test]
+fn key_state_tracker() {
+    use crate::{DeviceCapabilities, InputId, LedEvent};
+    use crate::mock::MockDevice;
+
+    let time = EventTime::default();
+    let key = |key, state| KeyEvent::new(time, key, state);
+    let led = |led, value| LedEvent::new(time, led, value);
+
+    let mut tracker = KeyStateTracker::new();
+    assert!(tracker.update_key(&key(Key::A, KeyState::PRESSED)));
+    assert!(!tracker.update_key(&key(Key::A, KeyState::AUTOREPEAT)));
+    assert!(tracker.is_pressed(Key::A));
+    assert!(!tracker.update_key(&key(Key::B, KeyState::RELEASED)));
+    assert!(tracker.update(&key(Key::A, KeyState::RELEASED).into()));
+    assert!(!tracker.is_pressed(Key::A));
+
+    tracker.extend(&[
+        key(Key::LeftShift, KeyState::PRESSED).into(),
+        key(Key::RightShift, KeyState::PRESSED).into(),
+        key(Key::RightAlt, KeyState::PRESSED).into(),
+    ]);
+    let mods = tracker.modifiers();
+    assert_eq!(mods, Modifiers::SHIFT | Modifiers::ALTGR);
+    assert!(mods.shift() && mods.alt() && !mods.ctrl() && !mods.meta());
+    assert!(!mods.contains(Modifiers::ALT) && mods.intersects(Modifiers::ALT));
+    assert_eq!(mods.keys().collect::<Vec<_>>(), [Key::LeftShift, Key::RightShift, Key::RightAlt]);
+    tracker.update_key(&key(Key::LeftShift, KeyState::RELEASED));
+    assert!(tracker.modifiers().shift());
+    tracker.update_key(&key(Key::RightShift, KeyState::RELEASED));
+    assert!(!tracker.modifiers().shift());
+    assert_eq!(Modifiers::from_key(Key::RightCtrl), Some(Modifiers::RIGHT_CTRL));
+    assert_eq!(Modifiers::from_key(Key::A), None);
+
+    assert!(tracker.update(&led(LedKind::CapsLock, 1).into()));
+    assert!(!tracker.update_led(&led(LedKind::CapsLock, 1)));
+    assert!(tracker.caps_lock() && !tracker.num_lock());
+    assert!(!tracker.update(&InputEvent::from(SynchronizeEvent::report(time))));
+
+    assert_eq!(tracker.release_events(time), [
+        key(Key::RightAlt, KeyState::RELEASED).into(),
+        SynchronizeEvent::report(time).into(),
+    ]);
+    assert!(tracker.release_events(time).is_empty());
+    assert!(tracker.caps_lock());
+
+    let mock = MockDevice::new(DeviceCapabilities::new(InputId::default(), b"mock"));
+    mock.push_events(vec![
+        key(Key::LeftCtrl, KeyState::PRESSED).into(),
+        led(LedKind::NumLock, 1).into(),
+    ]);
+    tracker.update_key(&key(Key::B, KeyState::PRESSED));
+    tracker.sync(&mock).unwrap();
+    assert!(!tracker.is_pressed(Key::B) && tracker.is_pressed(Key::LeftCtrl));
+    assert!(!tracker.caps_lock() && tracker.num_lock());
+    assert_eq!(KeyStateTracker::from_evdev(&mock).unwrap().modifiers(), Modifiers::LEFT_CTRL);
+}
@@ -0,0 +1,448 @@
+//! An interface to the Linux uhid kernel module that can be used to create
+//! virtual HID devices.
+//!
+//! Unlike uinput, the kernel treats uhid devices like any other HID device,
+//! so they are exposed through hidraw as well as evdev.
+
+use std::{io, fs};
+use std::convert::TryFrom;
+use std::os::unix::io::{RawFd, AsRawFd, IntoRawFd, FromRawFd};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use nix;
+use crate::InputId;
+use crate::macros::convert_error;
+
+/// The maximum size of a report.
+pub const UHID_DATA_MAX: usize = 4096;
+/// The maximum size of a report descriptor.
+pub const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+/// The size of `struct uhid_event`.
+pub const UHID_EVENT_SIZE: usize = 4376;
+
+/// `UHID_DEV_NUMBERED_FEATURE_REPORTS`
+pub const UHID_DEV_NUMBERED_FEATURE_REPORTS: u64 = 1 << 0;
+/// `UHID_DEV_NUMBERED_OUTPUT_REPORTS`
+pub const UHID_DEV_NUMBERED_OUTPUT_REPORTS: u64 = 1 << 1;
+/// `UHID_DEV_NUMBERED_INPUT_REPORTS`
+pub const UHID_DEV_NUMBERED_INPUT_REPORTS: u64 = 1 << 2;
+
+const UHID_DESTROY: u32 = 1;
+const UHID_START: u32 = 2;
+const UHID_STOP: u32 = 3;
+const UHID_OPEN: u32 = 4;
+const UHID_CLOSE: u32 = 5;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+/// The type of a HID report.
+#[repr(u8)]
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub enum ReportType {
+    /// `UHID_FEATURE_REPORT`
+    Feature = 0,
+    /// `UHID_OUTPUT_REPORT`
+    Output = 1,
+    /// `UHID_INPUT_REPORT`
+    Input = 2,
+}
+
+impl TryFrom<u8> for ReportType {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> io::Result<Self> {
+        match value {
+            0 => Ok(ReportType::Feature),
+            1 => Ok(ReportType::Output),
+            2 => Ok(ReportType::Input),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown uhid report type")),
+        }
+    }
+}
+
+/// An event sent by the kernel to a uhid device.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum UHidEvent {
+    /// `UHID_START`: the HID driver has bound to the device.
+    ///
+    /// The flags are a combination of the `UHID_DEV_*` constants.
+    Start {
+        /// Describes which report types are numbered.
+        flags: u64,
+    },
+    /// `UHID_STOP`: the HID driver has unbound from the device.
+    Stop,
+    /// `UHID_OPEN`: a user of the device has opened it.
+    Open,
+    /// `UHID_CLOSE`: the last user of the device has closed it.
+    Close,
+    /// `UHID_OUTPUT`: a report was written to the device.
+    Output {
+        /// The type of the report.
+        report_type: ReportType,
+        /// The report, prefixed by its number if reports are numbered.
+        data: Vec<u8>,
+    },
+    /// `UHID_GET_REPORT`: a report is requested.
+    ///
+    /// Must be answered with [`UHidHandle::get_report_reply`].
+    GetReport {
+        /// The request ID to reply with.
+        id: u32,
+        /// The report number.
+        report_number: u8,
+        /// The type of the report.
+        report_type: ReportType,
+    },
+    /// `UHID_SET_REPORT`: a report should be set.
+    ///
+    /// Must be answered with [`UHidHandle::set_report_reply`].
+    SetReport {
+        /// The request ID to reply with.
+        id: u32,
+        /// The report number.
+        report_number: u8,
+        /// The type of the report.
+        report_type: ReportType,
+        /// The contents of the report.
+        data: Vec<u8>,
+    },
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_ne_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn report_data(data: &[u8], size: u16) -> io::Result<Vec<u8>> {
+    data.get(..size as usize)
+        .map(|data| data.to_vec())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "uhid report too large"))
+}
+
+impl UHidEvent {
+    /// Parses an event read from a uhid device.
+    ///
+    /// Legacy and unknown events produce `None`.
+    pub fn from_bytes(bytes: &[u8; UHID_EVENT_SIZE]) -> io::Result<Option<Self>> {
+        let u = &bytes[4..];
+        Ok(Some(match read_u32(bytes) {
+            UHID_START => UHidEvent::Start {
+                flags: u64::from_ne_bytes([u[0], u[1], u[2], u[3], u[4], u[5], u[6], u[7]]),
+            },
+            UHID_STOP => UHidEvent::Stop,
+            UHID_OPEN => UHidEvent::Open,
+            UHID_CLOSE => UHidEvent::Close,
+            UHID_OUTPUT => UHidEvent::Output {
+                data: report_data(&u[..UHID_DATA_MAX], read_u16(&u[UHID_DATA_MAX..]))?,
+                report_type: ReportType::try_from(u[UHID_DATA_MAX + 2])?,
+            },
+            UHID_GET_REPORT => UHidEvent::GetReport {
+                id: read_u32(u),
+                report_number: u[4],
+                report_type: ReportType::try_from(u[5])?,
+            },
+            UHID_SET_REPORT => UHidEvent::SetReport {
+                id: read_u32(u),
+                report_number: u[4],
+                report_type: ReportType::try_from(u[5])?,
+                data: report_data(&u[8..][..UHID_DATA_MAX], read_u16(&u[6..]))?,
+            },
+            _ => return Ok(None),
+        }))
+    }
+}
+
+fn copy_field(dest: &mut [u8], value: &[u8], what: &str) -> io::Result<()> {
+    if value.len() > dest.len() {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} too long", what)))
+    } else {
+        dest[..value.len()].copy_from_slice(value);
+        Ok(())
+    }
+}
+
+/// Describes a HID device to be created with [`UHidHandle::create`].
+#[derive(Clone, Default, Debug)]
+pub struct UHidDeviceInfo {
+    /// The identity of the device.
+    pub id: InputId,
+    /// The name of the device.
+    pub name: Vec<u8>,
+    /// The physical location of the device.
+    pub phys: Vec<u8>,
+    /// A unique identifier such as a serial number.
+    pub uniq: Vec<u8>,
+    /// The HID country code.
+    pub country: u32,
+    /// The HID report descriptor.
+    pub report_descriptor: Vec<u8>,
+}
+
+impl UHidDeviceInfo {
+    /// Describes a device with the given identity and report descriptor.
+    pub fn new(id: InputId, name: &[u8], report_descriptor: &[u8]) -> Self {
+        UHidDeviceInfo {
+            id,
+            name: name.into(),
+            report_descriptor: report_descriptor.into(),
+            .. Default::default()
+        }
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0u8; UHID_EVENT_SIZE];
+        bytes[..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let u = &mut bytes[4..];
+        // leave room for a terminating NUL
+        copy_field(&mut u[0..127], &self.name, "name")?;
+        copy_field(&mut u[128..191], &self.phys, "phys")?;
+        copy_field(&mut u[192..255], &self.uniq, "uniq")?;
+        copy_field(&mut u[276..], &self.report_descriptor, "report descriptor")?;
+        u[256..258].copy_from_slice(&(self.report_descriptor.len() as u16).to_ne_bytes());
+        u[258..260].copy_from_slice(&self.id.bustype.to_ne_bytes());
+        u[260..264].copy_from_slice(&(self.id.vendor as u32).to_ne_bytes());
+        u[264..268].copy_from_slice(&(self.id.product as u32).to_ne_bytes());
+        u[268..272].copy_from_slice(&(self.id.version as u32).to_ne_bytes());
+        u[272..276].copy_from_slice(&self.country.to_ne_bytes());
+        Ok(bytes)
+    }
+}
+
+/// A handle to a uhid device.
+pub struct UHidHandle<F> {
+    fd: F,
+    device: Option<UHidDeviceInfo>,
+}
+
+impl<F> UHidHandle<F> {
+    /// Create a new handle using an existing open file object.
+    pub const fn new(fd: F) -> Self {
+        UHidHandle {
+            fd,
+            device: None,
+        }
+    }
+
+    /// Extracts the contained handle.
+    pub fn into_inner(self) -> F {
+        self.fd
+    }
+
+    /// A reference to the contained handle.
+    pub const fn as_inner(&self) -> &F {
+        &self.fd
+    }
+
+    /// A mutable reference to the contained handle.
+    pub fn as_inner_mut(&mut self) -> &mut F {
+        &mut self.fd
+    }
+}
+
+impl<F: AsRawFd> AsFd for UHidHandle<F> {
+    fn as_fd<'a>(&'a self) -> BorrowedFd<'a> {
+        unsafe {
+            BorrowedFd::borrow_raw(self.fd())
+        }
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for UHidHandle<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd()
+    }
+}
+
+impl<F: IntoRawFd> IntoRawFd for UHidHandle<F> {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+impl<F: FromRawFd> FromRawFd for UHidHandle<F> {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        UHidHandle::new(FromRawFd::from_raw_fd(fd))
+    }
+}
+
+impl<F: AsRawFd> UHidHandle<F> {
+    #[inline]
+    fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    fn write_event(&self, event: &[u8]) -> io::Result<()> {
+        nix::unistd::write(self, event)
+            .map(drop)
+            .map_err(convert_error)
+    }
+
+    /// `UHID_CREATE2`: creates the device.
+    pub fn create(&mut self, info: &UHidDeviceInfo) -> io::Result<()> {
+        self.write_event(&info.to_bytes()?)?;
+        self.device = Some(info.clone());
+        Ok(())
+    }
+
+    /// `UHID_DESTROY`: destroys the device.
+    ///
+    /// This also happens when the handle is closed.
+    pub fn destroy(&mut self) -> io::Result<()> {
+        self.write_event(&UHID_DESTROY.to_ne_bytes())?;
+        self.device = None;
+        Ok(())
+    }
+
+    /// `UHID_INPUT2`: sends an input report.
+    pub fn input(&self, data: &[u8]) -> io::Result<()> {
+        let mut event = Vec::with_capacity(6 + data.len());
+        event.extend_from_slice(&UHID_INPUT2.to_ne_bytes());
+        event.extend_from_slice(&report_size(data)?.to_ne_bytes());
+        event.extend_from_slice(data);
+        self.write_event(&event)
+    }
+
+    /// `UHID_GET_REPORT_REPLY`: answers a [`UHidEvent::GetReport`] request.
+    ///
+    /// A non-zero `err` indicates failure, in which case `data` is ignored.
+    pub fn get_report_reply(&self, id: u32, err: u16, data: &[u8]) -> io::Result<()> {
+        let mut event = Vec::with_capacity(12 + data.len());
+        event.extend_from_slice(&UHID_GET_REPORT_REPLY.to_ne_bytes());
+        event.extend_from_slice(&id.to_ne_bytes());
+        event.extend_from_slice(&err.to_ne_bytes());
+        event.extend_from_slice(&report_size(data)?.to_ne_bytes());
+        event.extend_from_slice(data);
+        self.write_event(&event)
+    }
+
+    /// `UHID_SET_REPORT_REPLY`: answers a [`UHidEvent::SetReport`] request.
+    pub fn set_report_reply(&self, id: u32, err: u16) -> io::Result<()> {
+        let mut event = Vec::with_capacity(10);
+        event.extend_from_slice(&UHID_SET_REPORT_REPLY.to_ne_bytes());
+        event.extend_from_slice(&id.to_ne_bytes());
+        event.extend_from_slice(&err.to_ne_bytes());
+        self.write_event(&event)
+    }
+
+    /// Reads the next event sent by the kernel.
+    ///
+    /// Legacy and unknown events are skipped.
+    pub fn read_event(&self) -> io::Result<UHidEvent> {
+        let mut bytes = vec![0u8; UHID_EVENT_SIZE];
+        loop {
+            let len = nix::unistd::read(self.fd(), &mut bytes).map_err(convert_error)?;
+            if len < 4 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short uhid event"))
+            }
+            let bytes = <&[u8; UHID_EVENT_SIZE]>::try_from(&bytes[..]).unwrap();
+            if let Some(event) = UHidEvent::from_bytes(bytes)? {
+                return Ok(event)
+            }
+        }
+    }
+
+    /// Returns the sysfs directory of the HID device.
+    ///
+    /// The kernel does not report which device belongs to a handle, so the
+    /// most recently created device matching the identity and name used to
+    /// create it is assumed.
+    pub fn sys_path(&self) -> io::Result<PathBuf> {
+        let device = self.device.as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "uhid device not created"))?;
+        let prefix = format!("{:04X}:{:04X}:{:04X}.", device.id.bustype, device.id.vendor, device.id.product);
+        let mut hid_name = b"HID_NAME=".to_vec();
+        hid_name.extend_from_slice(&device.name);
+
+        let mut found = None;
+        for entry in fs::read_dir("/sys/devices/virtual/misc/uhid/")? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let sequence = match name.to_str().and_then(|name| name.strip_prefix(&prefix[..])) {
+                Some(sequence) => u32::from_str_radix(sequence, 16).unwrap_or(0),
+                None => continue,
+            };
+            let uevent = fs::read(entry.path().join("uevent"))?;
+            let newer = found.as_ref().map(|&(s, _)| sequence > s).unwrap_or(true);
+            if newer && uevent.split(|&c| c == b'\n').any(|line| line == &hid_name[..]) {
+                found = Some((sequence, entry.path()));
+            }
+        }
+        found.map(|(_, path)| path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "uhid device not found"))
+    }
+
+    /// The device node paths of all input devices created for the HID device.
+    ///
+    /// These only appear once a HID driver has bound to the device, see
+    /// [`UHidEvent::Start`].
+    pub fn evdev_paths(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for input in fs::read_dir(self.sys_path()?.join("input"))? {
+            for entry in fs::read_dir(input?.path())? {
+                let name = entry?.file_name();
+                if name.to_str().map(|name| name.starts_with("event")).unwrap_or(false) {
+                    paths.push(Path::new("/dev/input/").join(name));
+                }
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// The device node path of the first input device created for the HID
+    /// device.
+    pub fn evdev_path(&self) -> io::Result<PathBuf> {
+        self.evdev_paths()?.into_iter().next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "event input device not found"))
+    }
+
+    /// The hidraw device node path of the HID device.
+    pub fn hidraw_path(&self) -> io::Result<PathBuf> {
+        fs::read_dir(self.sys_path()?.join("hidraw"))?
+            .next()
+            .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::NotFound, "hidraw device not found")))
+            .map(|entry| Path::new("/dev/").join(entry.file_name()))
+    }
+}
+
+fn report_size(data: &[u8]) -> io::Result<u16> {
+    if data.len() > UHID_DATA_MAX {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "report too large"))
+    } else {
+        Ok(data.len() as u16)
+    }
+}
+
+#[test]
+fn uhid_event_layout() {
+    let info = UHidDeviceInfo::new(InputId { bustype: 3, vendor: 0x1234, product: 0x5678, version: 1 }, b"test", &[0x05, 0x01]);
+    let bytes = info.to_bytes().unwrap();
+    assert_eq!(bytes.len(), UHID_EVENT_SIZE);
+    assert_eq!(&bytes[4..8], b"test");
+    assert_eq!(read_u16(&bytes[4 + 256..]), 2);
+    assert_eq!(read_u32(&bytes[4 + 264..]), 0x5678);
+    assert_eq!(&bytes[4 + 276..][..2], &[0x05, 0x01]);
+
+    let mut event = [0u8; UHID_EVENT_SIZE];
+    event[..4].copy_from_slice(&UHID_SET_REPORT.to_ne_bytes());
+    event[4..8].copy_from_slice(&7u32.to_ne_bytes());
+    event[8] = 1;
+    event[9] = ReportType::Feature as u8;
+    event[10..12].copy_from_slice(&2u16.to_ne_bytes());
+    event[12..14].copy_from_slice(&[0xaa, 0xbb]);
+    assert_eq!(UHidEvent::from_bytes(&event).unwrap(), Some(UHidEvent::SetReport {
+        id: 7,
+        report_number: 1,
+        report_type: ReportType::Feature,
+        data: vec![0xaa, 0xbb],
+    }));
+}
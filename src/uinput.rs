@@ -2,6 +2,7 @@
 //! virtual input devices.
 
 use std::{io, fs, ptr};
+use std::time::{Duration, Instant};
 use std::os::unix::io::{RawFd, AsRawFd, IntoRawFd, FromRawFd};
 use std::os::unix::ffi::OsStrExt;
 use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
@@ -12,7 +13,12 @@ use std::slice::{from_raw_parts, from_raw_parts_mut};
 use std::ffi::{OsStr, OsString, CStr};
 use crate::sys;
 use nix;
-use crate::{Key, InputId, AbsoluteInfoSetup, kinds};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use crate::{
+    Key, InputId, InputEvent, GenericEvent, SynchronizeEvent, AbsoluteInfoSetup,
+    EventTime, ClockId, EventKind, SynchronizeKind, kinds,
+};
 use crate::macros::convert_error;
 
 pub use crate::sys::{UINPUT_MAX_NAME_SIZE, UINPUT_VERSION};
@@ -63,6 +69,12 @@ impl<F: AsRawFd> AsFd for UInputHandle<F> {
     }
 }
 
+impl<F: AsRawFd> AsRawFd for UInputHandle<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd()
+    }
+}
+
 impl<F: IntoRawFd> IntoRawFd for UInputHandle<F> {
     fn into_raw_fd(self) -> RawFd {
         self.0.into_raw_fd()
@@ -186,6 +198,77 @@ impl<F: AsRawFd> UInputHandle<F> {
         self.evdev_name().map(|ev| Path::new("/dev/input/").join(ev))
     }
 
+    /// Waits until the device node of the input device is accessible and, if
+    /// udev is running, until udev has finished setting it up.
+    ///
+    /// Events written before userspace opens the new device are lost, so this
+    /// should be called after [`create`](Self::create) instead of sleeping.
+    /// Returns the path of the device node.
+    pub fn wait_ready(&self, timeout: Duration) -> io::Result<PathBuf> {
+        let deadline = Instant::now() + timeout;
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC).map_err(convert_error)?;
+        let udev = Path::new("/run/udev/control").exists();
+        let mut watches = [
+            (Path::new("/dev/input"), AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB, false),
+            (Path::new("/run/udev/data"), AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO, !udev),
+        ];
+
+        loop {
+            // watch before checking so that no change can be missed
+            let mut watching = true;
+            for (dir, flags, watched) in &mut watches {
+                if !*watched {
+                    *watched = inotify.add_watch(*dir, *flags | AddWatchFlags::IN_ONLYDIR).is_ok();
+                    watching &= *watched;
+                }
+            }
+
+            match self.evdev_ready(udev) {
+                Ok(Some(path)) => return Ok(path),
+                Ok(None) => (),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound || err.kind() == io::ErrorKind::PermissionDenied => (),
+                Err(err) => return Err(err),
+            }
+
+            let remaining = match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+                _ => return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for input device")),
+            };
+            // sysfs can't be watched, so poll periodically until everything can be
+            let remaining = match watching {
+                true => remaining,
+                false => remaining.min(Duration::from_millis(10)),
+            };
+            let timeout = crate::evdev::poll_timeout(remaining);
+            let mut fds = [PollFd::new(inotify.as_fd(), PollFlags::POLLIN)];
+            poll(&mut fds, timeout).map_err(convert_error)?;
+            let _ = inotify.read_events();
+        }
+    }
+
+    fn evdev_ready(&self, udev: bool) -> io::Result<Option<PathBuf>> {
+        let name = self.evdev_name()?;
+        let uevent = fs::read_to_string(self.sys_path()?.join(&name).join("uevent"))?;
+        let var = |key: &str| uevent.lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|&(k, _)| k == key)
+            .map(|(_, v)| v);
+        let path = match var("DEVNAME") {
+            Some(devname) => Path::new("/dev").join(devname),
+            None => Path::new("/dev/input").join(&name),
+        };
+        if udev {
+            let data = match (var("MAJOR"), var("MINOR")) {
+                (Some(major), Some(minor)) => format!("/run/udev/data/c{}:{}", major, minor),
+                _ => return Ok(None),
+            };
+            if !Path::new(&data).exists() {
+                return Ok(None)
+            }
+        }
+        fs::File::open(&path).map(|_| Some(path))
+    }
+
     ioctl_impl! {
         {
             /// `UI_DEV_CREATE`
@@ -273,3 +356,302 @@ impl<F: AsRawFd> UInputHandle<F> {
         }
     }
 }
+
+impl<F: AsRawFd> UInputHandle<F> {
+    /// Creates a new uinput device that is destroyed when dropped.
+    ///
+    /// See [`create`](Self::create).
+    pub fn create_device(self, id: &InputId, name: &[u8], ff_effects_max: u32, abs: &[AbsoluteInfoSetup]) -> io::Result<UInputDevice<F>> {
+        self.create(id, name, ff_effects_max, abs)?;
+        Ok(UInputDevice::new(self))
+    }
+}
+
+/// A created uinput device, destroyed when dropped.
+pub struct UInputDevice<F: AsRawFd> {
+    handle: Option<UInputHandle<F>>,
+    sys_path: Option<PathBuf>,
+    evdev_path: Option<PathBuf>,
+}
+
+impl<F: AsRawFd> UInputDevice<F> {
+    /// Takes ownership of a uinput handle whose device has already been
+    /// created.
+    pub fn new(handle: UInputHandle<F>) -> Self {
+        UInputDevice {
+            sys_path: handle.sys_path().ok(),
+            evdev_path: handle.evdev_path().ok(),
+            handle: Some(handle),
+        }
+    }
+
+    /// A reference to the uinput handle.
+    pub fn handle(&self) -> &UInputHandle<F> {
+        self.handle.as_ref().expect("UInputDevice handle missing")
+    }
+
+    /// The sysfs directory of the input device, if it could be determined.
+    pub fn sys_path(&self) -> Option<&Path> {
+        self.sys_path.as_deref()
+    }
+
+    /// The device node path of the input device, if it could be determined.
+    pub fn evdev_path(&self) -> Option<&Path> {
+        self.evdev_path.as_deref()
+    }
+
+    /// Waits until the evdev device node is ready to be opened.
+    ///
+    /// See [`UInputHandle::wait_ready`].
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<&Path> {
+        let path = self.handle().wait_ready(timeout)?;
+        Ok(self.evdev_path.insert(path))
+    }
+
+    /// Destroys the device, returning the handle.
+    pub fn destroy(mut self) -> io::Result<UInputHandle<F>> {
+        let handle = self.handle.take().expect("UInputDevice handle missing");
+        handle.dev_destroy()?;
+        Ok(handle)
+    }
+
+    /// Extracts the handle without destroying the device.
+    pub fn into_inner(mut self) -> UInputHandle<F> {
+        self.handle.take().expect("UInputDevice handle missing")
+    }
+}
+
+impl<F: AsRawFd> Drop for UInputDevice<F> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.dev_destroy();
+        }
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for UInputDevice<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.handle().fd()
+    }
+}
+
+impl<F: AsRawFd> AsFd for UInputDevice<F> {
+    fn as_fd<'a>(&'a self) -> BorrowedFd<'a> {
+        self.handle().as_fd()
+    }
+}
+
+/// Buffers events written to a uinput device into frames.
+///
+/// Events are collected by [`emit`](Self::emit) until [`sync`](Self::sync)
+/// stamps them with the current time and writes them out along with a
+/// `SYN_REPORT` in a single write. Any events still pending when the writer is
+/// dropped are discarded.
+pub struct UInputWriter<F> {
+    handle: UInputHandle<F>,
+    clock: ClockId,
+    coalesce: bool,
+    frame: Vec<InputEvent>,
+}
+
+impl<F> UInputWriter<F> {
+    /// Creates a writer that timestamps events using the realtime clock.
+    pub const fn new(handle: UInputHandle<F>) -> Self {
+        UInputWriter {
+            handle,
+            clock: ClockId::Realtime,
+            coalesce: true,
+            frame: Vec::new(),
+        }
+    }
+
+    /// Sets the clock used to timestamp events.
+    pub fn with_clock(self, clock: ClockId) -> Self {
+        UInputWriter { clock, ..self }
+    }
+
+    /// Sets whether redundant events within a frame are merged together.
+    ///
+    /// When enabled, relative motion along the same axis is summed up and
+    /// only the last value of each absolute axis is kept. Multitouch axes are
+    /// never merged, since their meaning depends on the current slot.
+    pub fn with_coalescing(self, coalesce: bool) -> Self {
+        UInputWriter { coalesce, ..self }
+    }
+
+    /// The clock used to timestamp events.
+    pub fn clock(&self) -> ClockId {
+        self.clock
+    }
+
+    /// Changes the clock used to timestamp events.
+    pub fn set_clock(&mut self, clock: ClockId) {
+        self.clock = clock;
+    }
+
+    /// The events of the frame that has not been written yet.
+    pub fn pending(&self) -> &[InputEvent] {
+        &self.frame
+    }
+
+    /// Discards the events of the frame that has not been written yet.
+    pub fn clear(&mut self) {
+        self.frame.clear();
+    }
+
+    /// A reference to the underlying uinput handle.
+    pub const fn handle(&self) -> &UInputHandle<F> {
+        &self.handle
+    }
+
+    /// Extracts the underlying uinput handle, discarding any pending events.
+    pub fn into_inner(self) -> UInputHandle<F> {
+        self.handle
+    }
+}
+
+/// Merges an event into an earlier one of the same frame if possible.
+pub(crate) fn coalesce(frame: &mut Vec<InputEvent>, event: &InputEvent) -> bool {
+    let multitouch = sys::ABS_MT_SLOT as u16 ..= sys::ABS_MT_TOOL_Y as u16;
+    match event.kind {
+        EventKind::Relative | EventKind::Absolute => (),
+        _ => return false,
+    }
+    if event.kind == EventKind::Absolute && multitouch.contains(&event.code) {
+        return false
+    }
+    let index = match frame.iter().rposition(|e| e.kind == event.kind && e.code == event.code) {
+        Some(index) => index,
+        None => return false,
+    };
+    if event.kind == EventKind::Relative {
+        let value = frame[index].value.saturating_add(event.value);
+        match value {
+            0 => drop(frame.remove(index)),
+            value => frame[index].value = value,
+        }
+    } else {
+        frame[index].value = event.value;
+    }
+    true
+}
+
+impl<F: AsRawFd> UInputWriter<F> {
+    /// Adds an event to the current frame.
+    ///
+    /// Emitting a `SYN_REPORT` is equivalent to calling [`sync`](Self::sync).
+    pub fn emit<E: GenericEvent>(&mut self, event: &E) -> io::Result<()> {
+        let event: &InputEvent = event.as_ref();
+        if event.kind == EventKind::Synchronize && event.code == SynchronizeKind::Report as u16 {
+            return self.sync()
+        }
+        if !self.coalesce || !coalesce(&mut self.frame, event) {
+            self.frame.push(*event);
+        }
+        Ok(())
+    }
+
+    /// Writes out the current frame followed by a `SYN_REPORT`.
+    ///
+    /// Nothing is written if the frame is empty. If the write fails, the
+    /// frame is kept so that it can be retried or [cleared](Self::clear).
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.frame.is_empty() {
+            return Ok(())
+        }
+        let time = EventTime::now(self.clock)?;
+        let events: Vec<_> = self.frame.iter()
+            .map(|e| InputEvent { time, ..*e }.into_raw())
+            .chain(Some(InputEvent::from(SynchronizeEvent::report(time)).into_raw()))
+            .collect();
+        let written = self.handle.write(&events)?;
+        match written == events.len() {
+            true => {
+                self.frame.clear();
+                Ok(())
+            },
+            false => Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole frame")),
+        }
+    }
+
+    /// Writes a complete frame of events.
+    pub fn write_frame(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        for event in events {
+            self.emit(event)?;
+        }
+        self.sync()
+    }
+}
+
+#[test]
+fn uinput_writer_coalesce() {
+    use crate::{AbsoluteAxis, AbsoluteEvent, RelativeAxis, RelativeEvent};
+
+    let time = EventTime::default();
+    let rel = |axis, value| InputEvent::from(RelativeEvent::new(time, axis, value));
+    let abs = |axis, value| InputEvent::from(AbsoluteEvent::new(time, axis, value));
+
+    let mut frame = Vec::new();
+    for event in &[
+        rel(RelativeAxis::X, 3),
+        abs(AbsoluteAxis::X, 10),
+        rel(RelativeAxis::X, 2),
+        rel(RelativeAxis::Y, 1),
+        abs(AbsoluteAxis::MultitouchPositionX, 10),
+        abs(AbsoluteAxis::X, 20),
+        abs(AbsoluteAxis::MultitouchPositionX, 20),
+        rel(RelativeAxis::Y, -1),
+    ] {
+        if !coalesce(&mut frame, event) {
+            frame.push(*event);
+        }
+    }
+    assert_eq!(frame, [
+        rel(RelativeAxis::X, 5),
+        abs(AbsoluteAxis::X, 20),
+        abs(AbsoluteAxis::MultitouchPositionX, 10),
+        abs(AbsoluteAxis::MultitouchPositionX, 20),
+    ]);
+}
+
+#[test]
+fn uinput_writer_frames() {
+    use std::os::unix::net::UnixStream;
+    use crate::{EvdevHandle, Key, KeyEvent, KeyState, RelativeAxis, RelativeEvent};
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let rx = EvdevHandle::new(rx);
+    let mut writer = UInputWriter::new(UInputHandle::new(tx)).with_clock(ClockId::Monotonic);
+    let time = EventTime::default();
+    let rel = |axis, value| InputEvent::from(RelativeEvent::new(time, axis, value));
+
+    writer.sync().unwrap();
+    writer.emit(&rel(RelativeAxis::X, 3)).unwrap();
+    writer.emit(&rel(RelativeAxis::X, 2)).unwrap();
+    writer.emit(&KeyEvent::new(time, Key::ButtonLeft, KeyState::PRESSED)).unwrap();
+    assert_eq!(writer.pending().len(), 2);
+    let before = EventTime::now(ClockId::Monotonic).unwrap();
+    writer.emit(&SynchronizeEvent::report(time)).unwrap();
+    assert!(writer.pending().is_empty());
+
+    let mut events = [unsafe { std::mem::zeroed() }; 8];
+    let len = rx.read(&mut events).unwrap();
+    let events: Vec<InputEvent> = events[..len].iter()
+        .map(|e| *InputEvent::from_raw(e).unwrap())
+        .collect();
+    let stamped = events[0].time;
+    assert!(stamped >= before);
+    assert_eq!(events, [
+        InputEvent { time: stamped, ..rel(RelativeAxis::X, 5) },
+        KeyEvent::new(stamped, Key::ButtonLeft, KeyState::PRESSED).into(),
+        SynchronizeEvent::report(stamped).into(),
+    ]);
+
+    // a failed write keeps the frame
+    drop(rx);
+    writer.emit(&rel(RelativeAxis::Y, 1)).unwrap();
+    assert!(writer.sync().is_err());
+    assert_eq!(writer.pending(), [rel(RelativeAxis::Y, 1)]);
+    writer.clear();
+    assert!(writer.pending().is_empty());
+}
@@ -0,0 +1,250 @@
+//! Interoperability with XKB keycodes and X11 keysyms.
+//!
+//! XKB keycodes are evdev key codes offset by 8, and are named as in the
+//! `evdev` file of xkeyboard-config. Keysyms are those produced by the first
+//! level of the `pc+us` keymap, which is enough to label keys without pulling
+//! in libxkbcommon.
+
+use std::borrow::Cow;
+use crate::Key;
+
+/// The offset between evdev key codes and XKB keycodes.
+pub const KEYCODE_OFFSET: u32 = 8;
+
+/// Keys along with their XKB keycode name, keysym name and keysym.
+const XKB: &[(Key, &str, &str, u32)] = &[
+    (Key::Esc, "ESC", "Escape", 0xff1b),
+    (Key::Num1, "AE01", "1", 0x31),
+    (Key::Num2, "AE02", "2", 0x32),
+    (Key::Num3, "AE03", "3", 0x33),
+    (Key::Num4, "AE04", "4", 0x34),
+    (Key::Num5, "AE05", "5", 0x35),
+    (Key::Num6, "AE06", "6", 0x36),
+    (Key::Num7, "AE07", "7", 0x37),
+    (Key::Num8, "AE08", "8", 0x38),
+    (Key::Num9, "AE09", "9", 0x39),
+    (Key::Num0, "AE10", "0", 0x30),
+    (Key::Minus, "AE11", "minus", 0x2d),
+    (Key::Equal, "AE12", "equal", 0x3d),
+    (Key::Backspace, "BKSP", "BackSpace", 0xff08),
+    (Key::Tab, "TAB", "Tab", 0xff09),
+    (Key::Q, "AD01", "q", 0x71),
+    (Key::W, "AD02", "w", 0x77),
+    (Key::E, "AD03", "e", 0x65),
+    (Key::R, "AD04", "r", 0x72),
+    (Key::T, "AD05", "t", 0x74),
+    (Key::Y, "AD06", "y", 0x79),
+    (Key::U, "AD07", "u", 0x75),
+    (Key::I, "AD08", "i", 0x69),
+    (Key::O, "AD09", "o", 0x6f),
+    (Key::P, "AD10", "p", 0x70),
+    (Key::LeftBrace, "AD11", "bracketleft", 0x5b),
+    (Key::RightBrace, "AD12", "bracketright", 0x5d),
+    (Key::Enter, "RTRN", "Return", 0xff0d),
+    (Key::LeftCtrl, "LCTL", "Control_L", 0xffe3),
+    (Key::A, "AC01", "a", 0x61),
+    (Key::S, "AC02", "s", 0x73),
+    (Key::D, "AC03", "d", 0x64),
+    (Key::F, "AC04", "f", 0x66),
+    (Key::G, "AC05", "g", 0x67),
+    (Key::H, "AC06", "h", 0x68),
+    (Key::J, "AC07", "j", 0x6a),
+    (Key::K, "AC08", "k", 0x6b),
+    (Key::L, "AC09", "l", 0x6c),
+    (Key::Semicolon, "AC10", "semicolon", 0x3b),
+    (Key::Apostrophe, "AC11", "apostrophe", 0x27),
+    (Key::Grave, "TLDE", "grave", 0x60),
+    (Key::LeftShift, "LFSH", "Shift_L", 0xffe1),
+    (Key::Backslash, "BKSL", "backslash", 0x5c),
+    (Key::Z, "AB01", "z", 0x7a),
+    (Key::X, "AB02", "x", 0x78),
+    (Key::C, "AB03", "c", 0x63),
+    (Key::V, "AB04", "v", 0x76),
+    (Key::B, "AB05", "b", 0x62),
+    (Key::N, "AB06", "n", 0x6e),
+    (Key::M, "AB07", "m", 0x6d),
+    (Key::Comma, "AB08", "comma", 0x2c),
+    (Key::Dot, "AB09", "period", 0x2e),
+    (Key::Slash, "AB10", "slash", 0x2f),
+    (Key::RightShift, "RTSH", "Shift_R", 0xffe2),
+    (Key::KpAsterisk, "KPMU", "KP_Multiply", 0xffaa),
+    (Key::LeftAlt, "LALT", "Alt_L", 0xffe9),
+    (Key::Space, "SPCE", "space", 0x20),
+    (Key::CapsLock, "CAPS", "Caps_Lock", 0xffe5),
+    (Key::F1, "FK01", "F1", 0xffbe),
+    (Key::F2, "FK02", "F2", 0xffbf),
+    (Key::F3, "FK03", "F3", 0xffc0),
+    (Key::F4, "FK04", "F4", 0xffc1),
+    (Key::F5, "FK05", "F5", 0xffc2),
+    (Key::F6, "FK06", "F6", 0xffc3),
+    (Key::F7, "FK07", "F7", 0xffc4),
+    (Key::F8, "FK08", "F8", 0xffc5),
+    (Key::F9, "FK09", "F9", 0xffc6),
+    (Key::F10, "FK10", "F10", 0xffc7),
+    (Key::NumLock, "NMLK", "Num_Lock", 0xff7f),
+    (Key::ScrollLock, "SCLK", "Scroll_Lock", 0xff14),
+    (Key::Kp7, "KP7", "KP_Home", 0xff95),
+    (Key::Kp8, "KP8", "KP_Up", 0xff97),
+    (Key::Kp9, "KP9", "KP_Prior", 0xff9a),
+    (Key::KpMinus, "KPSU", "KP_Subtract", 0xffad),
+    (Key::Kp4, "KP4", "KP_Left", 0xff96),
+    (Key::Kp5, "KP5", "KP_Begin", 0xff9d),
+    (Key::Kp6, "KP6", "KP_Right", 0xff98),
+    (Key::KpPlus, "KPAD", "KP_Add", 0xffab),
+    (Key::Kp1, "KP1", "KP_End", 0xff9c),
+    (Key::Kp2, "KP2", "KP_Down", 0xff99),
+    (Key::Kp3, "KP3", "KP_Next", 0xff9b),
+    (Key::Kp0, "KP0", "KP_Insert", 0xff9e),
+    (Key::KpDot, "KPDL", "KP_Delete", 0xff9f),
+    (Key::ZenkakuHankaku, "HZTG", "Zenkaku_Hankaku", 0xff2a),
+    (Key::NonUsBackslashAndPipe, "LSGT", "less", 0x3c),
+    (Key::F11, "FK11", "F11", 0xffc8),
+    (Key::F12, "FK12", "F12", 0xffc9),
+    (Key::Ro, "AB11", "backslash", 0x5c),
+    (Key::Katakana, "KATA", "Katakana", 0xff26),
+    (Key::Hiragana, "HIRA", "Hiragana", 0xff25),
+    (Key::Henkan, "HENK", "Henkan_Mode", 0xff23),
+    (Key::KatakanaHiragana, "HKTG", "Hiragana_Katakana", 0xff27),
+    (Key::Muhenkan, "MUHE", "Muhenkan", 0xff22),
+    (Key::KpJpComma, "JPCM", "KP_Separator", 0xffac),
+    (Key::KpEnter, "KPEN", "KP_Enter", 0xff8d),
+    (Key::RightCtrl, "RCTL", "Control_R", 0xffe4),
+    (Key::KpSlash, "KPDV", "KP_Divide", 0xffaf),
+    (Key::Sysrq, "PRSC", "Print", 0xff61),
+    (Key::RightAlt, "RALT", "Alt_R", 0xffea),
+    (Key::LineFeed, "LNFD", "Linefeed", 0xff0a),
+    (Key::Home, "HOME", "Home", 0xff50),
+    (Key::Up, "UP", "Up", 0xff52),
+    (Key::PageUp, "PGUP", "Prior", 0xff55),
+    (Key::Left, "LEFT", "Left", 0xff51),
+    (Key::Right, "RGHT", "Right", 0xff53),
+    (Key::End, "END", "End", 0xff57),
+    (Key::Down, "DOWN", "Down", 0xff54),
+    (Key::PageDown, "PGDN", "Next", 0xff56),
+    (Key::Insert, "INS", "Insert", 0xff63),
+    (Key::Delete, "DELE", "Delete", 0xffff),
+    (Key::Mute, "MUTE", "XF86AudioMute", 0x1008ff12),
+    (Key::VolumeDown, "VOL-", "XF86AudioLowerVolume", 0x1008ff11),
+    (Key::VolumeUp, "VOL+", "XF86AudioRaiseVolume", 0x1008ff13),
+    (Key::Power, "POWR", "XF86PowerOff", 0x1008ff2a),
+    (Key::KpEqual, "KPEQ", "KP_Equal", 0xffbd),
+    (Key::Pause, "PAUS", "Pause", 0xff13),
+    (Key::KpComma, "I129", "KP_Decimal", 0xffae),
+    (Key::Hangul, "HNGL", "Hangul", 0xff31),
+    (Key::Hanja, "HJCV", "Hangul_Hanja", 0xff34),
+    (Key::Yen, "AE13", "yen", 0xa5),
+    (Key::LeftMeta, "LWIN", "Super_L", 0xffeb),
+    (Key::RightMeta, "RWIN", "Super_R", 0xffec),
+    (Key::Compose, "COMP", "Menu", 0xff67),
+    (Key::Stop, "STOP", "Cancel", 0xff69),
+    (Key::Again, "AGAI", "Redo", 0xff66),
+    (Key::Props, "PROP", "SunProps", 0x1005ff70),
+    (Key::Undo, "UNDO", "Undo", 0xff65),
+    (Key::Front, "FRNT", "SunFront", 0x1005ff71),
+    (Key::Copy, "COPY", "XF86Copy", 0x1008ff57),
+    (Key::Open, "OPEN", "XF86Open", 0x1008ff6b),
+    (Key::Paste, "PAST", "XF86Paste", 0x1008ff6d),
+    (Key::Find, "FIND", "Find", 0xff68),
+    (Key::Cut, "CUT", "XF86Cut", 0x1008ff58),
+    (Key::Help, "HELP", "Help", 0xff6a),
+    (Key::Calc, "I148", "XF86Calculator", 0x1008ff1d),
+    (Key::Sleep, "I150", "XF86Sleep", 0x1008ff2f),
+    (Key::WWW, "I158", "XF86WWW", 0x1008ff2e),
+    (Key::Mail, "I163", "XF86Mail", 0x1008ff19),
+    (Key::Back, "I166", "XF86Back", 0x1008ff26),
+    (Key::Forward, "I167", "XF86Forward", 0x1008ff27),
+    (Key::NextSong, "I171", "XF86AudioNext", 0x1008ff17),
+    (Key::PlayPause, "I172", "XF86AudioPlay", 0x1008ff14),
+    (Key::PreviousSong, "I173", "XF86AudioPrev", 0x1008ff16),
+    (Key::StopCD, "I174", "XF86AudioStop", 0x1008ff15),
+    (Key::Homepage, "I180", "XF86HomePage", 0x1008ff18),
+    (Key::Refresh, "I181", "XF86Reload", 0x1008ff73),
+    (Key::F13, "FK13", "F13", 0xffca),
+    (Key::F14, "FK14", "F14", 0xffcb),
+    (Key::F15, "FK15", "F15", 0xffcc),
+    (Key::F16, "FK16", "F16", 0xffcd),
+    (Key::F17, "FK17", "F17", 0xffce),
+    (Key::F18, "FK18", "F18", 0xffcf),
+    (Key::F19, "FK19", "F19", 0xffd0),
+    (Key::F20, "FK20", "F20", 0xffd1),
+    (Key::F21, "FK21", "F21", 0xffd2),
+    (Key::F22, "FK22", "F22", 0xffd3),
+    (Key::F23, "FK23", "F23", 0xffd4),
+    (Key::F24, "FK24", "F24", 0xffd5),
+    (Key::BrightnessDown, "I232", "XF86MonBrightnessDown", 0x1008ff03),
+    (Key::BrightnessUp, "I233", "XF86MonBrightnessUp", 0x1008ff02),
+];
+
+impl Key {
+    /// The XKB keycode of the key.
+    pub const fn to_xkb_keycode(&self) -> u32 {
+        *self as u32 + KEYCODE_OFFSET
+    }
+
+    /// Looks up a key from its XKB keycode.
+    pub fn from_xkb_keycode(keycode: u32) -> Option<Self> {
+        keycode.checked_sub(KEYCODE_OFFSET)
+            .filter(|&code| code <= u16::MAX as u32)
+            .and_then(|code| Key::from_code(code as u16).ok())
+    }
+
+    fn xkb_entry(&self) -> Option<&'static (Key, &'static str, &'static str, u32)> {
+        XKB.iter().find(|&&(key, ..)| key == *self)
+    }
+
+    /// The name of the key's XKB keycode, such as `AE01`.
+    ///
+    /// Keycodes without a dedicated name are named after their value, such
+    /// as `I148`. Keycodes above 255 cannot be used with X11 and have no name.
+    pub fn xkb_keycode_name(&self) -> Option<Cow<'static, str>> {
+        match self.xkb_entry() {
+            Some(&(_, name, ..)) => Some(name.into()),
+            None => match self.to_xkb_keycode() {
+                keycode @ KEYCODE_OFFSET..=255 => Some(format!("I{}", keycode).into()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Looks up a key from the name of its XKB keycode.
+    pub fn from_xkb_keycode_name(name: &str) -> Option<Self> {
+        match XKB.iter().find(|&&(_, n, ..)| n == name) {
+            Some(&(key, ..)) => Some(key),
+            None => name.strip_prefix('I')
+                .and_then(|keycode| keycode.parse().ok())
+                .filter(|&keycode| keycode <= 255)
+                .and_then(Key::from_xkb_keycode),
+        }
+    }
+
+    /// The name of the keysym the key produces with a US layout, such as
+    /// `Escape` or `a`.
+    pub fn keysym_name(&self) -> Option<&'static str> {
+        self.xkb_entry().map(|&(_, _, name, _)| name)
+    }
+
+    /// The keysym the key produces with a US layout.
+    pub fn keysym(&self) -> Option<u32> {
+        self.xkb_entry().map(|&(.., keysym)| keysym)
+    }
+
+    /// Looks up the key that produces a keysym with a US layout.
+    pub fn from_keysym(keysym: u32) -> Option<Self> {
+        XKB.iter()
+            .find(|&&(.., k)| k == keysym)
+            .map(|&(key, ..)| key)
+    }
+}
+
+#[test]
+fn xkb_keycodes() {
+    assert_eq!(Key::Esc.to_xkb_keycode(), 9);
+    assert_eq!(Key::from_xkb_keycode(38), Some(Key::A));
+    assert_eq!(Key::from_xkb_keycode(7), None);
+    assert_eq!(Key::Num1.xkb_keycode_name().as_deref(), Some("AE01"));
+    assert_eq!(Key::Calc.xkb_keycode_name().as_deref(), Some("I148"));
+    assert_eq!(Key::from_xkb_keycode_name("I150"), Some(Key::Sleep));
+    assert_eq!(Key::from_xkb_keycode_name("LFSH"), Some(Key::LeftShift));
+    assert_eq!(Key::Enter.keysym_name(), Some("Return"));
+    assert_eq!(Key::from_keysym(0xff1b), Some(Key::Esc));
+}
@@ -0,0 +1,45 @@
+//! These tests need access to `/dev/uinput`, so they are ignored by default.
+//! Run them with `cargo test -- --ignored`.
+
+use std::io;
+use std::time::Duration;
+use input_linux::{
+    DeviceCapabilities, EventTime, InputEvent, InputId, Key, KeyEvent, KeyState,
+    RelativeAxis, RelativeEvent, sys,
+};
+use input_linux::loopback::Loopback;
+
+fn without_time(events: Vec<InputEvent>) -> Vec<InputEvent> {
+    events.into_iter().map(|e| InputEvent { time: EventTime::default(), ..e }).collect()
+}
+
+#[test]
+#[ignore = "requires access to /dev/uinput"]
+fn loopback_events() {
+    let id = InputId { bustype: sys::BUS_VIRTUAL, vendor: 0x1234, product: 0x5678, version: 1 };
+    let mut caps = DeviceCapabilities::new(id, b"input-linux loopback test");
+    caps.insert_key(Key::ButtonLeft);
+    caps.insert_relative(RelativeAxis::X);
+    caps.insert_relative(RelativeAxis::Y);
+    let loopback = Loopback::new(&caps).expect("failed to create loopback device");
+
+    let evdev_caps = loopback.evdev().capabilities().unwrap();
+    assert_eq!(evdev_caps.id, id);
+    assert_eq!(evdev_caps.name, caps.name);
+    assert!(evdev_caps.keys.get(Key::ButtonLeft));
+
+    let time = EventTime::default();
+    let frame: Vec<InputEvent> = vec![
+        KeyEvent::new(time, Key::ButtonLeft, KeyState::PRESSED).into(),
+        RelativeEvent::new(time, RelativeAxis::X, 5).into(),
+        RelativeEvent::new(time, RelativeAxis::Y, -3).into(),
+    ];
+    loopback.write_frame(&frame).unwrap();
+    let read = loopback.read_frame(Duration::from_secs(1)).unwrap();
+    assert_eq!(without_time(read), frame);
+
+    assert_eq!(
+        loopback.read_frame(Duration::from_millis(10)).unwrap_err().kind(),
+        io::ErrorKind::TimedOut,
+    );
+}